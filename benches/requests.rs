@@ -0,0 +1,90 @@
+//! Benchmarks of sequentially awaited IndexedDB requests, run with
+//! `cargo bench --target wasm32-unknown-unknown`.
+//!
+//! The results include the time the browser needs to serve the requests, run
+//! the benchmarks on two revisions in the same browser to compare the request
+//! futures.
+
+use std::hint::black_box;
+
+use indexeddb::{IndexedDb, TransactionMode};
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+async fn open() -> IndexedDb {
+    let db = IndexedDb::open("bench", 1, |_, db| {
+        db.create_object_store("bench").unwrap();
+    })
+    .await
+    .expect("Failed to open indexed DB");
+
+    let transaction = db.transaction(TransactionMode::ReadWrite);
+    let store = transaction.object_store("bench").unwrap();
+    store.put(&1, &1).await.unwrap();
+    transaction.done().await.unwrap();
+
+    db
+}
+
+/// Sequentially awaited reads inside of a single transaction.
+#[wasm_bindgen_bench]
+async fn get(c: &mut Criterion) {
+    let db = open().await;
+
+    c.bench_async_function("get", |b| {
+        let db = db.clone();
+
+        Box::pin(b.iter_custom_future(move |iters| {
+            let db = db.clone();
+
+            async move {
+                let transaction = db.transaction(TransactionMode::Readonly);
+                let store = transaction.object_store("bench").unwrap();
+
+                let start = Instant::now();
+
+                for _ in 0..iters {
+                    black_box(store.get::<u32>(&1).await.unwrap());
+                }
+
+                start.elapsed()
+            }
+        }))
+    })
+    .await;
+}
+
+/// Sequentially awaited writes inside of a single transaction.
+#[wasm_bindgen_bench]
+async fn put(c: &mut Criterion) {
+    let db = open().await;
+
+    c.bench_async_function("put", |b| {
+        let db = db.clone();
+
+        Box::pin(b.iter_custom_future(move |iters| {
+            let db = db.clone();
+
+            async move {
+                let transaction = db.transaction(TransactionMode::ReadWrite);
+                let store = transaction.object_store("bench").unwrap();
+
+                let start = Instant::now();
+
+                for i in 0..iters {
+                    store.put(&(i % 100), &i).await.unwrap();
+                }
+
+                let elapsed = start.elapsed();
+                transaction.done().await.unwrap();
+                elapsed
+            }
+        }))
+    })
+    .await;
+}
+
+// The bench doesn't use the libtest harness, wasm-bindgen-test-runner calls
+// the exported benchmarks instead.
+fn main() {}
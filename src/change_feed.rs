@@ -0,0 +1,334 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{
+    codec::deserialize_key,
+    request::{EventListener, SharedWaker},
+};
+
+thread_local! {
+    // Identifies the current tab or worker in the broadcast messages.
+    static SOURCE: String = format!("{:x}", (js_sys::Math::random() * u64::MAX as f64) as u64);
+}
+
+fn source() -> String {
+    SOURCE.with(Clone::clone)
+}
+
+fn channel_name(database: &str) -> String {
+    format!("indexeddb-changes:{}", database)
+}
+
+/// The kinds of writes that are reported by the change feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Values were added or stored using `add` or `put`.
+    Put,
+    /// Values were deleted.
+    Delete,
+    /// The object store was cleared.
+    Clear,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Put => "put",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Clear => "clear",
+        }
+    }
+
+    fn from_str(kind: &str) -> Option<Self> {
+        match kind {
+            "put" => Some(ChangeKind::Put),
+            "delete" => Some(ChangeKind::Delete),
+            "clear" => Some(ChangeKind::Clear),
+            _ => None,
+        }
+    }
+}
+
+/// A write to an object store that was committed.
+#[derive(Debug, Clone)]
+pub struct Change {
+    store: String,
+    kind: ChangeKind,
+    keys: Vec<JsValue>,
+}
+
+impl Change {
+    /// The name of the object store that was written to.
+    pub fn store(&self) -> &str {
+        &self.store
+    }
+
+    /// The kind of the write.
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// The keys of the values that were written, empty for a cleared store.
+    pub fn keys<K: DeserializeOwned>(&self) -> Result<Vec<K>, JsValue> {
+        self.keys.iter().cloned().map(deserialize_key).collect()
+    }
+
+    /// The keys of the values that were written as JavaScript values.
+    pub fn keys_raw(&self) -> &[JsValue] {
+        &self.keys
+    }
+
+    fn to_js(&self) -> JsValue {
+        let change = js_sys::Object::new();
+        let keys: js_sys::Array = self.keys.iter().collect();
+
+        set(&change, "store", &self.store.as_str().into());
+        set(&change, "kind", &self.kind.as_str().into());
+        set(&change, "keys", &keys);
+
+        change.into()
+    }
+
+    fn from_js(change: &JsValue) -> Option<Self> {
+        Some(Self {
+            store: get(change, "store").as_string()?,
+            kind: ChangeKind::from_str(&get(change, "kind").as_string()?)?,
+            keys: get(change, "keys")
+                .dyn_into::<js_sys::Array>()
+                .ok()?
+                .to_vec(),
+        })
+    }
+}
+
+fn set(object: &js_sys::Object, key: &str, value: &JsValue) {
+    js_sys::Reflect::set(object, &key.into(), value).expect("setting a property failed");
+}
+
+fn get(object: &JsValue, key: &str) -> JsValue {
+    js_sys::Reflect::get(object, &key.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// The writes of a committed transaction.
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    /// Were the writes made in the current tab or worker.
+    pub local: bool,
+    /// The writes in the order they were made.
+    pub changes: Vec<Change>,
+}
+
+impl ChangeEvent {
+    fn from_js(message: &JsValue) -> Option<Self> {
+        let source = get(message, "source").as_string()?;
+        let changes = get(message, "changes")
+            .dyn_into::<js_sys::Array>()
+            .ok()?
+            .iter()
+            .map(|change| Change::from_js(&change))
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            local: source == self::source(),
+            changes,
+        })
+    }
+}
+
+/// Broadcasts the writes of the committed transactions of a database.
+#[derive(Debug)]
+pub(crate) struct ChangeFeed {
+    channel: web_sys::BroadcastChannel,
+}
+
+impl ChangeFeed {
+    pub(crate) fn new(database: &str) -> Result<Self, JsValue> {
+        Ok(Self {
+            channel: web_sys::BroadcastChannel::new(&channel_name(database))?,
+        })
+    }
+
+    fn publish(&self, changes: &[Change]) -> Result<(), JsValue> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let message = js_sys::Object::new();
+        let changes: js_sys::Array = changes.iter().map(Change::to_js).collect();
+
+        set(&message, "source", &source().into());
+        set(&message, "changes", &changes);
+
+        self.channel.post_message(&message)
+    }
+}
+
+impl Drop for ChangeFeed {
+    fn drop(&mut self) {
+        self.channel.close();
+    }
+}
+
+/// The writes made by a transaction of a database with a change feed.
+#[derive(Debug)]
+pub(crate) struct ChangeLog {
+    feed: Rc<ChangeFeed>,
+    changes: RefCell<Vec<Change>>,
+}
+
+impl ChangeLog {
+    pub(crate) fn new(feed: Rc<ChangeFeed>) -> Self {
+        Self {
+            feed,
+            changes: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Record a write, writes are recorded when they are requested. A failed
+    /// request aborts the transaction, nothing is published in that case.
+    pub(crate) fn record(&self, store: String, kind: ChangeKind, keys: Vec<JsValue>) {
+        self.changes.borrow_mut().push(Change { store, kind, keys });
+    }
+
+    /// Publish the writes once the transaction committed.
+    pub(crate) fn publish(&self) -> Result<(), JsValue> {
+        let changes = self.changes.take();
+        self.feed.publish(&changes)
+    }
+}
+
+/// A stream of the writes that were committed to a database, in the current
+/// tab or worker as well as in others.
+///
+/// Only writes of handles with an enabled change feed are reported, see
+/// [`IndexedDb::with_change_feed`]. The stream never ends.
+///
+/// [`IndexedDb::with_change_feed`]: crate::IndexedDb::with_change_feed
+pub struct ChangeStream {
+    channel: web_sys::BroadcastChannel,
+    events: Rc<RefCell<VecDeque<ChangeEvent>>>,
+    waker: SharedWaker,
+    _on_message: EventListener,
+}
+
+impl ChangeStream {
+    pub(crate) fn new(database: &str) -> Result<Self, JsValue> {
+        let channel = web_sys::BroadcastChannel::new(&channel_name(database))?;
+        let events = Rc::new(RefCell::new(VecDeque::new()));
+        let waker = SharedWaker::default();
+
+        let on_message = {
+            let events = events.clone();
+            let waker = waker.clone();
+
+            EventListener::new(&channel, &["message"], move |event| {
+                let event: web_sys::MessageEvent = event.unchecked_into();
+
+                // Messages of other libraries on the same channel are ignored.
+                if let Some(event) = ChangeEvent::from_js(&event.data()) {
+                    events.borrow_mut().push_back(event);
+                    waker.wake();
+                }
+            })
+        };
+
+        Ok(Self {
+            channel,
+            events,
+            waker,
+            _on_message: on_message,
+        })
+    }
+}
+
+impl Stream for ChangeStream {
+    type Item = ChangeEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChangeEvent>> {
+        match self.events.borrow_mut().pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                self.waker.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for ChangeStream {
+    fn drop(&mut self) {
+        self.channel.close();
+    }
+}
+
+impl fmt::Debug for ChangeStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChangeStream")
+            .field("channel", &self.channel.name())
+            .field("pending", &self.events.borrow().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ChangeKind, IndexedDb, TransactionMode};
+    use futures::StreamExt;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn committed_writes() {
+        let db = IndexedDb::open("change_feed", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB")
+        .with_change_feed()
+        .unwrap();
+
+        let mut changes = db.changes().unwrap();
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.put(&"a", &1).await.unwrap();
+        store.delete(&"b").await.unwrap();
+        transaction.done().await.unwrap();
+
+        // Aborted writes aren't reported.
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.put(&"c", &1).await.unwrap();
+        transaction.abort().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        transaction
+            .object_store("test")
+            .unwrap()
+            .clear()
+            .await
+            .unwrap();
+        transaction.done().await.unwrap();
+
+        let event = changes.next().await.unwrap();
+        assert!(event.local);
+        assert_eq!(event.changes.len(), 2);
+        assert_eq!(event.changes[0].store(), "test");
+        assert_eq!(event.changes[0].kind(), ChangeKind::Put);
+        assert_eq!(event.changes[0].keys::<String>().unwrap(), vec!["a"]);
+        assert_eq!(event.changes[1].kind(), ChangeKind::Delete);
+
+        let event = changes.next().await.unwrap();
+        assert_eq!(event.changes[0].kind(), ChangeKind::Clear);
+        assert!(event.changes[0].keys_raw().is_empty());
+    }
+}
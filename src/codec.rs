@@ -0,0 +1,287 @@
+//! Codecs convert Rust values into the `JsValue`s that are stored in object
+//! stores and back.
+//!
+//! The codec of an object store is chosen with a type parameter, either for
+//! all the stores of a transaction using [`Transaction::with_codec`] or for a
+//! single store using [`Transaction::object_store_with_codec`].
+//!
+//! [`Transaction::with_codec`]: crate::Transaction::with_codec
+//! [`Transaction::object_store_with_codec`]: crate::Transaction::object_store_with_codec
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+
+/// A codec that decides how values are represented inside of an object store.
+///
+/// Codecs are marker types, the conversions themselves are implemented using
+/// the [`Encode`] and [`Decode`] traits for every type the codec supports.
+pub trait Codec: 'static {}
+
+/// Convert a value of type `T` into a `JsValue` that can be stored.
+pub trait Encode<T: ?Sized>: Codec {
+    /// Encode the given value.
+    fn encode(value: &T) -> Result<JsValue, JsValue>;
+}
+
+/// Convert a stored `JsValue` back into a value of type `T`.
+pub trait Decode<T>: Codec {
+    /// Decode the given value.
+    fn decode(value: JsValue) -> Result<T, JsValue>;
+}
+
+/// A codec that supports every type implementing the serde traits.
+///
+/// [`Encode`] and [`Decode`] can't express that a codec works for all serde
+/// types, this trait does, which allows object stores to implement the
+/// generic [`storage`](crate::storage) traits.
+pub trait SerdeCodec: Codec {
+    /// Encode the given value.
+    fn encode_serde<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue>;
+
+    /// Decode the given value.
+    fn decode_serde<T: DeserializeOwned>(value: JsValue) -> Result<T, JsValue>;
+}
+
+macro_rules! serde_codec {
+    ($codec:ty) => {
+        impl SerdeCodec for $codec {
+            fn encode_serde<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+                <Self as Encode<T>>::encode(value)
+            }
+
+            fn decode_serde<T: DeserializeOwned>(value: JsValue) -> Result<T, JsValue> {
+                <Self as Decode<T>>::decode(value)
+            }
+        }
+    };
+}
+
+/// Codec that round trips values through JSON.
+///
+/// This is the default codec. Values are serialized to JSON using
+/// `serde_json` and parsed into plain JavaScript objects, types that JSON
+/// can't represent, like binary data or large integers, are lost.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {}
+
+serde_codec!(Json);
+
+impl<T: Serialize + ?Sized> Encode<T> for Json {
+    fn encode(value: &T) -> Result<JsValue, JsValue> {
+        let json = serde_json::to_string(value).map_err(|e| JsValue::from(e.to_string()))?;
+        js_sys::JSON::parse(&json)
+    }
+}
+
+impl<T: DeserializeOwned> Decode<T> for Json {
+    fn decode(value: JsValue) -> Result<T, JsValue> {
+        let json: String = js_sys::JSON::stringify(&value)?.into();
+        serde_json::from_str(&json).map_err(|e| e.to_string().into())
+    }
+}
+
+/// Codec that converts values directly to native JavaScript values using
+/// `serde-wasm-bindgen`.
+///
+/// Maps are stored as `Map` objects and 64 bit integers as `BigInt`s, both of
+/// which survive the structured clone that IndexedDB performs.
+///
+/// Byte vectors and slices are stored as plain arrays of numbers, they are
+/// only stored as `Uint8Array`s if they are serialized as bytes, e.g. with
+/// `#[serde(with = "serde_bytes")]` or as a `serde_bytes::ByteBuf`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerdeWasmBindgen;
+
+impl Codec for SerdeWasmBindgen {}
+
+serde_codec!(SerdeWasmBindgen);
+
+impl<T: Serialize + ?Sized> Encode<T> for SerdeWasmBindgen {
+    fn encode(value: &T) -> Result<JsValue, JsValue> {
+        let serializer =
+            serde_wasm_bindgen::Serializer::new().serialize_large_number_types_as_bigints(true);
+        Ok(value.serialize(&serializer)?)
+    }
+}
+
+impl<T: DeserializeOwned> Decode<T> for SerdeWasmBindgen {
+    fn decode(value: JsValue) -> Result<T, JsValue> {
+        Ok(serde_wasm_bindgen::from_value(value)?)
+    }
+}
+
+/// Codec that stores JavaScript values as they are.
+///
+/// This codec supports every type that can be cast from a `JsValue`, e.g.
+/// `JsValue` itself, `js_sys::Object` or `web_sys::Blob`. Decoding fails if
+/// the stored value isn't an instance of the requested type.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Raw;
+
+impl Codec for Raw {}
+
+impl<T: JsCast> Encode<T> for Raw {
+    fn encode(value: &T) -> Result<JsValue, JsValue> {
+        Ok(value.as_ref().clone())
+    }
+}
+
+impl<T: JsCast> Decode<T> for Raw {
+    fn decode(value: JsValue) -> Result<T, JsValue> {
+        value
+            .dyn_into()
+            .map_err(|v| format!("unexpected value type: {:?}", v).into())
+    }
+}
+
+/// Codec that stores values in the `bincode` format inside of a
+/// `Uint8Array`.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {}
+
+#[cfg(feature = "bincode")]
+serde_codec!(Bincode);
+
+#[cfg(feature = "bincode")]
+impl<T: Serialize + ?Sized> Encode<T> for Bincode {
+    fn encode(value: &T) -> Result<JsValue, JsValue> {
+        let bytes = bincode::serialize(value).map_err(|e| JsValue::from(e.to_string()))?;
+        Ok(bytes_to_js(&bytes))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<T: DeserializeOwned> Decode<T> for Bincode {
+    fn decode(value: JsValue) -> Result<T, JsValue> {
+        bincode::deserialize(&js_to_bytes(value)?).map_err(|e| e.to_string().into())
+    }
+}
+
+/// Codec that stores values in the `postcard` format inside of a
+/// `Uint8Array`.
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl Codec for Postcard {}
+
+#[cfg(feature = "postcard")]
+serde_codec!(Postcard);
+
+#[cfg(feature = "postcard")]
+impl<T: Serialize + ?Sized> Encode<T> for Postcard {
+    fn encode(value: &T) -> Result<JsValue, JsValue> {
+        let bytes = postcard::to_allocvec(value).map_err(|e| JsValue::from(e.to_string()))?;
+        Ok(bytes_to_js(&bytes))
+    }
+}
+
+#[cfg(feature = "postcard")]
+impl<T: DeserializeOwned> Decode<T> for Postcard {
+    fn decode(value: JsValue) -> Result<T, JsValue> {
+        postcard::from_bytes(&js_to_bytes(value)?).map_err(|e| e.to_string().into())
+    }
+}
+
+/// Codec that stores values in the CBOR format inside of a `Uint8Array`.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {}
+
+#[cfg(feature = "cbor")]
+serde_codec!(Cbor);
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize + ?Sized> Encode<T> for Cbor {
+    fn encode(value: &T) -> Result<JsValue, JsValue> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes).map_err(|e| JsValue::from(e.to_string()))?;
+        Ok(bytes_to_js(&bytes))
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<T: DeserializeOwned> Decode<T> for Cbor {
+    fn decode(value: JsValue) -> Result<T, JsValue> {
+        ciborium::de::from_reader(js_to_bytes(value)?.as_slice()).map_err(|e| e.to_string().into())
+    }
+}
+
+#[cfg(any(feature = "bincode", feature = "postcard", feature = "cbor"))]
+fn bytes_to_js(bytes: &[u8]) -> JsValue {
+    js_sys::Uint8Array::from(bytes).into()
+}
+
+#[cfg(any(feature = "bincode", feature = "postcard", feature = "cbor"))]
+fn js_to_bytes(value: JsValue) -> Result<Vec<u8>, JsValue> {
+    let array: js_sys::Uint8Array = Raw::decode(value)?;
+    Ok(array.to_vec())
+}
+
+/// Convert a key into a `JsValue`.
+///
+/// Keys don't go through the codec of the store since the representation of
+/// keys decides how they are ordered, strings, numbers and sequences of those
+/// are converted to the matching JavaScript types.
+pub(crate) fn serialize_key<K: Serialize + ?Sized>(key: &K) -> Result<JsValue, JsValue> {
+    Ok(serde_wasm_bindgen::to_value(key)?)
+}
+
+/// Convert a `JsValue` key back into a Rust value.
+pub(crate) fn deserialize_key<K: DeserializeOwned>(key: JsValue) -> Result<K, JsValue> {
+    Ok(serde_wasm_bindgen::from_value(key)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        codec::{Raw, SerdeWasmBindgen},
+        IndexedDb, TransactionMode,
+    };
+    use std::collections::BTreeMap;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn codecs() {
+        let db = IndexedDb::open("codecs", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let mut map = BTreeMap::new();
+        map.insert(1u64 << 60, "large".to_owned());
+
+        let transaction = db
+            .transaction(TransactionMode::ReadWrite)
+            .with_codec::<SerdeWasmBindgen>();
+        let store = transaction.object_store("test").unwrap();
+        store.add(&"map", &map).await.unwrap();
+
+        let raw_store = transaction.object_store_with_codec::<Raw>("test").unwrap();
+        let bytes = js_sys::Uint8Array::from(&[1u8, 2, 3][..]);
+        raw_store.add(&"bytes", &bytes).await.unwrap();
+        transaction.done().await.unwrap();
+
+        let transaction = db
+            .transaction(TransactionMode::Readonly)
+            .with_codec::<SerdeWasmBindgen>();
+        let store = transaction.object_store("test").unwrap();
+        let loaded: BTreeMap<u64, String> = store.get(&"map").await.unwrap().unwrap();
+        assert_eq!(loaded, map);
+
+        let raw_store = transaction.object_store_with_codec::<Raw>("test").unwrap();
+        let loaded: js_sys::Uint8Array = raw_store.get(&"bytes").await.unwrap().unwrap();
+        assert_eq!(loaded.to_vec(), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{
+    codec::{deserialize_key, Codec, Decode, Json},
+    object_store::ObjectStore,
+    request::IndexedDbRequest,
+};
+
+/// A cursor iterating over the entries of an object store or index.
+///
+/// The cursor starts out before the first entry, [`Cursor::next`] needs to be
+/// called to move it onto an entry. Values are decoded using the codec `C` of
+/// the object store the cursor was opened on.
+#[derive(Debug)]
+pub struct Cursor<'a, C: Codec = Json> {
+    request: web_sys::IdbRequest,
+    current: Option<web_sys::IdbCursorWithValue>,
+    done: bool,
+    store: PhantomData<&'a ObjectStore<C>>,
+}
+
+impl<'a, C: Codec> Cursor<'a, C> {
+    pub(crate) fn new(request: web_sys::IdbRequest) -> Self {
+        Self {
+            request,
+            current: None,
+            done: false,
+            store: PhantomData,
+        }
+    }
+
+    /// Move the cursor to the next entry.
+    ///
+    /// Returns `false` if there are no more entries left, the cursor doesn't
+    /// point to any entry anymore in that case.
+    pub async fn next(&mut self) -> Result<bool, JsValue> {
+        if self.done {
+            return Ok(false);
+        }
+
+        if let Some(cursor) = self.current.take() {
+            cursor.continue_()?;
+        }
+
+        let result = IndexedDbRequest::new(self.request.clone()).await?;
+
+        if result.is_null() || result.is_undefined() {
+            self.done = true;
+            Ok(false)
+        } else {
+            self.current = Some(result.unchecked_into());
+            Ok(true)
+        }
+    }
+
+    /// The key of the current entry, the index key for cursors of an index.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn key<K: DeserializeOwned>(&self) -> Result<K, JsValue> {
+        deserialize_key(self.key_raw())
+    }
+
+    /// The primary key of the current entry, the same as [`Cursor::key`] for
+    /// cursors of an object store.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn primary_key<K: DeserializeOwned>(&self) -> Result<K, JsValue> {
+        deserialize_key(
+            self.current()
+                .primary_key()
+                .expect("cursor primary key can't be read"),
+        )
+    }
+
+    /// The key of the current entry as a JavaScript value.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn key_raw(&self) -> JsValue {
+        self.current().key().expect("cursor key can't be read")
+    }
+
+    /// The value of the current entry.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn value<V>(&self) -> Result<V, JsValue>
+    where
+        C: Decode<V>,
+    {
+        C::decode(self.value_raw())
+    }
+
+    /// The value of the current entry as a JavaScript value, the codec of the
+    /// store is bypassed.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn value_raw(&self) -> JsValue {
+        self.current().value().expect("cursor value can't be read")
+    }
+
+    /// Get the underlying `web_sys` cursor, if the cursor points to an entry.
+    pub fn as_raw(&self) -> Option<&web_sys::IdbCursorWithValue> {
+        self.current.as_ref()
+    }
+
+    fn current(&self) -> &web_sys::IdbCursorWithValue {
+        self.current
+            .as_ref()
+            .expect("the cursor doesn't point to an entry")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{IndexedDb, TransactionMode};
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn iterate_raw_and_typed() {
+        let db = IndexedDb::open("cursor", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+
+        store.put(&"b", &"second".to_owned()).await.unwrap();
+        store
+            .put_raw(&"a", &JsValue::from_str("first"))
+            .await
+            .unwrap();
+        transaction.done().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("test").unwrap();
+
+        let raw = store.get_raw(&"a").await.unwrap().unwrap();
+        assert_eq!(raw.as_string().unwrap(), "first");
+
+        let mut cursor = store.open_cursor().unwrap();
+        let mut entries = Vec::new();
+
+        while cursor.next().await.unwrap() {
+            let key: String = cursor.key().unwrap();
+            let value: String = cursor.value().unwrap();
+            entries.push((key, value));
+        }
+
+        assert_eq!(
+            entries,
+            vec![
+                ("a".to_owned(), "first".to_owned()),
+                ("b".to_owned(), "second".to_owned())
+            ]
+        );
+        assert!(!cursor.next().await.unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    async fn stored_null() {
+        let db = IndexedDb::open("cursor_null", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.put_raw(&"a", &JsValue::NULL).await.unwrap();
+
+        // Only the typed getter treats a stored `null` like a missing value.
+        assert!(store.get_raw(&"a").await.unwrap().unwrap().is_null());
+        assert!(store.get_raw(&"b").await.unwrap().is_none());
+        assert!(store.get::<String>(&"a").await.unwrap().is_none());
+        assert_eq!(store.count(None).await.unwrap(), 1);
+    }
+}
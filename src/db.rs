@@ -1,33 +1,33 @@
-use std::{marker::PhantomData, sync::Arc};
+use futures::future::LocalBoxFuture;
+use std::{rc::Rc, sync::Arc};
 use wasm_bindgen::{prelude::*, JsCast};
 
 use crate::{
-    object_store::{KeyPath, ObjectStore, ObjectStoreDuringUpgrade},
-    request::IdbOpenDbRequest,
-    transaction::{Transaction, TransactionMode},
+    change_feed::{ChangeFeed, ChangeStream},
+    factory::Factory,
+    object_store::{KeyPath, ObjectStoreDuringUpgrade},
+    transaction::{ReadOnly, ReadWrite, StaticMode, Transaction, TransactionMode, VersionChange},
 };
 
-#[inline]
-fn factory() -> web_sys::IdbFactory {
-    web_sys::window().unwrap().indexed_db().unwrap().unwrap()
-}
-
 /// A handle on the database during an upgrade.
 #[derive(Debug)]
 pub struct DbDuringUpgrade {
     db: IndexedDb,
-    request: Arc<web_sys::IdbOpenDbRequest>,
+    // Created once, all the stores of the upgrade share its listeners.
+    transaction: Transaction<'static, VersionChange>,
 }
 
 impl DbDuringUpgrade {
-    pub(crate) fn from_raw_unchecked(
-        raw: JsValue,
-        request: Arc<web_sys::IdbOpenDbRequest>,
-    ) -> Self {
-        let db = IndexedDb {
-            inner: Arc::new(web_sys::IdbDatabase::unchecked_from_js(raw)),
-        };
-        DbDuringUpgrade { db, request }
+    pub(crate) fn from_raw_unchecked(raw: JsValue, request: Rc<web_sys::IdbOpenDbRequest>) -> Self {
+        let db = IndexedDb::new(raw.unchecked_into());
+        let transaction = request
+            .transaction()
+            .expect("no versionchange transaction during an upgrade");
+
+        DbDuringUpgrade {
+            db,
+            transaction: Transaction::new(transaction),
+        }
     }
 
     /// Get the name of this database.
@@ -40,6 +40,11 @@ impl DbDuringUpgrade {
         self.db.version()
     }
 
+    /// Get the underlying `web_sys` database.
+    pub fn as_raw(&self) -> &web_sys::IdbDatabase {
+        self.db.as_raw()
+    }
+
     /// Create a new object store.
     ///
     /// * `name` - The name that the object store should be created with.
@@ -65,20 +70,52 @@ impl DbDuringUpgrade {
 
         let key_path: KeyPath = KeyPath::None;
         let key_path: JsValue = key_path.into();
-        let mut parameters = web_sys::IdbObjectStoreParameters::new();
+        let parameters = web_sys::IdbObjectStoreParameters::new();
 
-        parameters.key_path(Some(&key_path));
-        parameters.auto_increment(false);
+        parameters.set_key_path(&key_path);
+        parameters.set_auto_increment(false);
 
         let store = self
             .db
             .inner
             .create_object_store_with_optional_parameters(name, &parameters)?;
 
-        Ok(ObjectStoreDuringUpgrade {
-            inner: ObjectStore { inner: store },
-            db: self,
-        })
+        Ok(ObjectStoreDuringUpgrade::new(store, self))
+    }
+
+    /// Get an existing object store.
+    ///
+    /// The store is fetched through the `versionchange` transaction of the
+    /// upgrade, indexes can be created and deleted and its entries can be
+    /// read and written to migrate them. Reading needs an upgrade callback
+    /// that can await, see [`IndexedDb::open_with_async_upgrade`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the object store that should be fetched.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let db = IndexedDb::open("users", 2, |old_version, db| {
+    ///     if old_version < 1 {
+    ///         db.create_object_store("users").unwrap();
+    ///     }
+    ///
+    ///     if old_version < 2 {
+    ///         let store = db.object_store("users").unwrap();
+    ///         store.create_index("by_email", "email", true).unwrap();
+    ///     }
+    /// }).await .expect("Failed to open indexed DB");
+    /// # });
+    /// ```
+    pub fn object_store(&self, name: &str) -> Result<ObjectStoreDuringUpgrade<'_>, JsValue> {
+        let store = self.transaction.as_raw().object_store(name)?;
+
+        Ok(ObjectStoreDuringUpgrade::new(store, self))
     }
 
     /// Is there already a store with the given name?
@@ -107,8 +144,45 @@ impl DbDuringUpgrade {
             .any(|store| store == name)
     }
 
-    /// Deletes an object store
-    pub(crate) fn delete_object_store(&self, name: &str) -> Result<(), JsValue> {
+    /// Abort the upgrade.
+    ///
+    /// The database keeps its old version and schema and opening it fails
+    /// with an `AbortError`.
+    pub fn abort(&self) {
+        // Fails if the transaction already finished, there is nothing to
+        // roll back then.
+        let _ = self.transaction.as_raw().abort();
+    }
+
+    /// Get the `versionchange` transaction of the upgrade.
+    ///
+    /// Object stores fetched from this transaction allow writes.
+    pub fn transaction(&self) -> &Transaction<'_, VersionChange> {
+        &self.transaction
+    }
+
+    /// Delete the object store with the given name together with its
+    /// entries and indexes.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the store that should be deleted.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let db = IndexedDb::open("test", 2, |old_version, db| {
+    ///     if old_version == 1 {
+    ///         db.delete_object_store("legacy")
+    ///             .expect("Couldn't delete object store");
+    ///     }
+    /// }).await .expect("Failed to open indexed DB");
+    /// # });
+    /// ```
+    pub fn delete_object_store(&self, name: &str) -> Result<(), JsValue> {
         self.db.inner.delete_object_store(name)?;
         Ok(())
     }
@@ -118,23 +192,35 @@ impl DbDuringUpgrade {
 #[derive(Debug, Clone)]
 pub struct IndexedDb {
     pub(crate) inner: Arc<web_sys::IdbDatabase>,
+    pub(crate) feed: Option<Rc<ChangeFeed>>,
 }
 
 impl IndexedDb {
+    pub(crate) fn new(inner: web_sys::IdbDatabase) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            feed: None,
+        }
+    }
+
     /// Open a database with the given name.
     ///
+    /// The database is opened using the factory of the current global scope,
+    /// which can be a window or a worker, use [`Factory::open`] to open a
+    /// database with a different factory.
+    ///
     /// # Arguments
     ///
     /// * `name` - The name of the database.
     ///
     /// * `version` - The current version of the database, if the database
-    /// already existed but the given version is newer the `on_upgrade_needed`
-    /// callback will be triggered. This needs to be a positive number bigger
-    /// than zero.
+    ///   already existed but the given version is newer the `on_upgrade_needed`
+    ///   callback will be triggered. This needs to be a positive number bigger
+    ///   than zero.
     ///
     /// * `on_upgrade_needed` - Callback that will be called if the database
-    /// needs to be upgraded, this includes the initial creation of the
-    /// database.
+    ///   needs to be upgraded, this includes the initial creation of the
+    ///   database.
     ///
     /// # Panics
     ///
@@ -159,14 +245,30 @@ impl IndexedDb {
         version: u32,
         on_upgrade_needed: impl Fn(u32, &DbDuringUpgrade) + 'static,
     ) -> Result<IndexedDb, JsValue> {
-        if version == 0 {
-            panic!("indexeddb version must be >= 1");
-        }
-
-        let request = factory().open_with_u32(name, version)?;
-        let request = IdbOpenDbRequest::new(request, on_upgrade_needed);
+        Factory::new()?.open(name, version, on_upgrade_needed).await
+    }
 
-        request.await
+    /// Open a database with the given name with an asynchronous upgrade
+    /// callback, the requests of the upgrade can be awaited.
+    ///
+    /// The database is opened using the factory of the current global scope,
+    /// see [`Factory::open_with_async_upgrade`] for the details.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the given `version` is 0.
+    pub async fn open_with_async_upgrade<F>(
+        name: &str,
+        version: u32,
+        on_upgrade_needed: F,
+    ) -> Result<IndexedDb, JsValue>
+    where
+        F: for<'a> Fn(u32, &'a DbDuringUpgrade) -> LocalBoxFuture<'a, Result<(), JsValue>>
+            + 'static,
+    {
+        Factory::new()?
+            .open_with_async_upgrade(name, version, on_upgrade_needed)
+            .await
     }
 
     /// Get the name of this database.
@@ -184,6 +286,67 @@ impl IndexedDb {
         to_collection!(self.inner.object_store_names() => Vec<String> : push)
     }
 
+    /// Enable the change feed for the transactions of this handle.
+    ///
+    /// Once a transaction started through the returned handle committed, the
+    /// writes it made using `add`, `put`, `delete` and `clear` are broadcast
+    /// to the [`ChangeStream`]s of the database in all tabs and workers of
+    /// the origin. Writes made through the underlying `web_sys` objects
+    /// aren't reported. Reporting is best effort, a transaction that
+    /// committed succeeds even if its writes can't be broadcast.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::{executor::block_on, StreamExt};
+    /// # block_on(async {
+    /// let db = IndexedDb::open("test", 1, |_, db| {
+    ///     db.create_object_store("test").unwrap();
+    /// }).await.expect("Failed to open indexed DB")
+    ///     .with_change_feed()
+    ///     .unwrap();
+    ///
+    /// let mut changes = db.changes().unwrap();
+    ///
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let store = transaction.object_store("test").unwrap();
+    /// store.put(&"Hello", &"world").await.unwrap();
+    /// transaction.done().await.unwrap();
+    ///
+    /// let event = changes.next().await.unwrap();
+    /// assert_eq!(event.changes[0].keys::<String>().unwrap(), vec!["Hello"]);
+    /// # });
+    /// ```
+    pub fn with_change_feed(mut self) -> Result<Self, JsValue> {
+        if self.feed.is_none() {
+            self.feed = Some(Rc::new(ChangeFeed::new(&self.name())?));
+        }
+
+        Ok(self)
+    }
+
+    /// Get a stream of the writes that are committed to this database by
+    /// handles with an enabled change feed, in this as well as in other tabs
+    /// and workers.
+    pub fn changes(&self) -> Result<ChangeStream, JsValue> {
+        ChangeStream::new(&self.name())
+    }
+
+    /// Close the connection to the database.
+    ///
+    /// The connection is closed once all of its transactions finished, new
+    /// transactions can't be started anymore. Clones of this handle share the
+    /// connection and are closed as well.
+    pub fn close(&self) {
+        self.inner.close();
+    }
+
+    /// Get the underlying `web_sys` database.
+    pub fn as_raw(&self) -> &web_sys::IdbDatabase {
+        &self.inner
+    }
+
     /// Start a dababase transaction.
     ///
     /// All read/write operations in indexeddb need to happen using a
@@ -215,29 +378,97 @@ impl IndexedDb {
     /// transaction.done().await;
     /// # });
     /// ```
-    pub fn transaction(&self, mode: TransactionMode) -> Transaction {
-        let inner = self
-            .inner
+    pub fn transaction(&self, mode: TransactionMode) -> Transaction<'_> {
+        Transaction::new(self.raw_transaction(mode)).with_feed(self.feed.clone())
+    }
+
+    /// Start a read only database transaction.
+    ///
+    /// Unlike [`IndexedDb::transaction`] the mode of the transaction is
+    /// encoded in its type, the object stores of the transaction don't offer
+    /// any methods that write to the store.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.readonly_transaction();
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// let value: Option<String> = store.get(&"Hello").await.unwrap();
+    /// # });
+    /// ```
+    ///
+    /// Writing to a store of a read only transaction doesn't compile:
+    ///
+    /// ```compile_fail
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.readonly_transaction();
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// store.add(&"Hello", &"world").await.unwrap();
+    /// # });
+    /// ```
+    pub fn readonly_transaction(&self) -> Transaction<'_, ReadOnly> {
+        self.typed_transaction()
+    }
+
+    /// Start a read/write database transaction.
+    ///
+    /// The mode of the transaction is encoded in its type, see
+    /// [`IndexedDb::readonly_transaction`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.readwrite_transaction();
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// store.add(&"Hello", &"world").await.unwrap();
+    /// transaction.done().await.unwrap();
+    /// # });
+    /// ```
+    pub fn readwrite_transaction(&self) -> Transaction<'_, ReadWrite> {
+        self.typed_transaction()
+    }
+
+    fn typed_transaction<M: StaticMode>(&self) -> Transaction<'_, M> {
+        Transaction::new(self.raw_transaction(M::MODE)).with_feed(self.feed.clone())
+    }
+
+    fn raw_transaction(&self, mode: TransactionMode) -> web_sys::IdbTransaction {
+        self.inner
             .transaction_with_str_sequence_and_mode(
                 &self.inner.object_store_names().into(),
                 mode.into(),
             )
-            .unwrap();
-
-        Transaction {
-            inner,
-            db: PhantomData,
-        }
+            .unwrap()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::IndexedDb;
+    use crate::{IndexedDb, TransactionMode};
+    use futures::FutureExt;
+    use serde_json::json;
     use wasm_bindgen_test::*;
 
-    wasm_bindgen_test_configure!(run_in_browser);
-
     #[wasm_bindgen_test]
     async fn open() {
         let db = IndexedDb::open("test", 1, |_old_version, _upgrader| ())
@@ -260,4 +491,122 @@ mod test {
 
         assert!(!db.object_store_names().is_empty());
     }
+
+    #[wasm_bindgen_test]
+    async fn rename_object_stores() {
+        let db = IndexedDb::open("rename_stores", 1, |_, db| {
+            let store = db.create_object_store("draft").unwrap();
+            store.create_index("by_draft", "draft", false).unwrap();
+            db.create_object_store("other").unwrap();
+
+            store.rename("users").unwrap();
+            store.rename_index("by_draft", "by_email").unwrap();
+            assert_eq!(store.index_names(), vec!["by_email"]);
+            assert!(store.rename("other").is_err());
+
+            db.delete_object_store("other").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        assert_eq!(db.object_store_names(), vec!["users"]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn existing_object_stores() {
+        let db = IndexedDb::open("existing_stores", 1, |_, db| {
+            db.create_object_store("users").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("users").unwrap();
+        store.put(&1, &"a@example.com").await.unwrap();
+        transaction.done().await.unwrap();
+        db.close();
+
+        let db = IndexedDb::open("existing_stores", 2, |old_version, db| {
+            if old_version < 2 {
+                let store = db.object_store("users").unwrap();
+                store.create_index("by_email", "email", true).unwrap();
+
+                // Requests can be queued, a synchronous upgrade callback
+                // can't await them.
+                let _ = store
+                    .queue_put(&2, &serde_json::json!({ "email": "b@example.com" }))
+                    .unwrap();
+            }
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("users").unwrap();
+        let index = store.index("by_email").unwrap();
+        assert_eq!(index.count(None).await.unwrap(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    async fn read_during_upgrade() {
+        let db = IndexedDb::open("read_during_upgrade", 1, |_, db| {
+            db.create_object_store("users").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("users").unwrap();
+        store.put(&1, &"a@example.com").await.unwrap();
+        store.put(&2, &"b@example.com").await.unwrap();
+        transaction.done().await.unwrap();
+        db.close();
+
+        // Turn the stored emails into objects.
+        let db = IndexedDb::open_with_async_upgrade("read_during_upgrade", 2, |_, db| {
+            async move {
+                let store = db.object_store("users")?;
+                let emails: Vec<String> = store.get_all(None).await?;
+
+                for (id, email) in (1u32..).zip(emails) {
+                    store.put(&id, &json!({ "email": email })).await?;
+                }
+
+                store.create_index("by_email", "email", true)
+            }
+            .boxed_local()
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.readonly_transaction();
+        let store = transaction.object_store("users").unwrap();
+        let user: Option<serde_json::Value> = store
+            .index("by_email")
+            .unwrap()
+            .get(&"b@example.com")
+            .await
+            .unwrap();
+        assert_eq!(user, Some(json!({ "email": "b@example.com" })));
+        db.close();
+
+        // An error of the upgrade aborts it.
+        let error = IndexedDb::open_with_async_upgrade("read_during_upgrade", 3, |_, db| {
+            async move {
+                db.create_object_store("other")?;
+                db.object_store("users")?.count(None).await?;
+
+                Err("the migration failed".into())
+            }
+            .boxed_local()
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.as_string().unwrap(), "the migration failed");
+
+        let db = IndexedDb::open("read_during_upgrade", 2, |_, _| ())
+            .await
+            .expect("Failed to open indexed DB");
+        assert_eq!(db.object_store_names(), vec!["users"]);
+    }
 }
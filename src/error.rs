@@ -0,0 +1,46 @@
+/// The kind of a storage error.
+///
+/// The kinds mirror the `DOMException` names that IndexedDB uses, they are
+/// the kinds of the errors of the in-memory backend and of the errors that
+/// [`fault`](crate::fault) injects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The transaction was aborted.
+    Abort,
+    /// A write violated a constraint, e.g. a key already exists.
+    Constraint,
+    /// A value or key couldn't be converted.
+    Data,
+    /// An operation was called on an object in the wrong state.
+    InvalidState,
+    /// The requested object store or database doesn't exist.
+    NotFound,
+    /// A write was attempted in a read only transaction.
+    ReadOnly,
+    /// The storage quota of the origin was exceeded.
+    QuotaExceeded,
+    /// A request was made on a transaction that isn't active anymore.
+    TransactionInactive,
+    /// A database was opened with a version lower than its current version.
+    Version,
+    /// The storage of a persisted database failed.
+    Unknown,
+}
+
+impl ErrorKind {
+    /// The `DOMException` name of this kind of error.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Abort => "AbortError",
+            ErrorKind::Constraint => "ConstraintError",
+            ErrorKind::Data => "DataError",
+            ErrorKind::InvalidState => "InvalidStateError",
+            ErrorKind::NotFound => "NotFoundError",
+            ErrorKind::ReadOnly => "ReadOnlyError",
+            ErrorKind::QuotaExceeded => "QuotaExceededError",
+            ErrorKind::TransactionInactive => "TransactionInactiveError",
+            ErrorKind::Version => "VersionError",
+            ErrorKind::Unknown => "UnknownError",
+        }
+    }
+}
@@ -0,0 +1,1361 @@
+use std::{cell::RefCell, rc::Rc};
+
+use futures::{
+    future::LocalBoxFuture,
+    io::{AsyncRead, AsyncReadExt},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map as JsonMap, Value as Json};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    db::{DbDuringUpgrade, IndexedDb},
+    factory::Factory,
+    object_store::KeyPath,
+    request::IndexedDbRequest,
+    transaction::TransactionMode,
+};
+
+const FORMAT: &str = "indexeddb-export";
+const FORMAT_VERSION: u32 = 1;
+/// The first bytes of an export in the binary format.
+const MAGIC: &[u8; 4] = b"IDBX";
+/// The number of entries that are read or written per transaction.
+pub(crate) const CHUNK_SIZE: u32 = 500;
+/// The number of bytes that are read from the input of an import at once.
+const READ_SIZE: usize = 64 * 1024;
+
+/// The typed arrays that can be stored in a database.
+const VIEWS: &[&str] = &[
+    "DataView",
+    "Int8Array",
+    "Uint8Array",
+    "Uint8ClampedArray",
+    "Int16Array",
+    "Uint16Array",
+    "Int32Array",
+    "Uint32Array",
+    "Float32Array",
+    "Float64Array",
+    "BigInt64Array",
+    "BigUint64Array",
+];
+
+/// The formats a database can be exported in, see [`IndexedDb::export`].
+///
+/// Both formats start with a header that describes the schema of the
+/// database and hold the same values, they only differ in their encoding.
+///
+/// # JSON
+///
+/// The export consists of newline delimited JSON objects. The first line is
+/// the header:
+///
+/// ```json
+/// {"format":"indexeddb-export","version":1,"database":"app","databaseVersion":3,
+///  "stores":[{"name":"users","keyPath":{"Single":"id"},"autoIncrement":false,
+///  "indexes":[{"name":"by_email","keyPath":{"Single":"email"},"unique":true,"multiEntry":false}]}]}
+/// ```
+///
+/// Every following line is an entry of a store:
+///
+/// ```json
+/// {"store":"users","key":1,"value":{"id":1,"email":"a@example.com"}}
+/// ```
+///
+/// Strings, booleans, `null`, finite numbers and arrays are stored as plain
+/// JSON, the properties of objects are sorted by name. Other values are
+/// stored as objects with a `"$type"` property:
+///
+/// * `{"$type":"object","value":{..}}` - an object that has a `"$type"`
+///   property itself, other objects are stored as plain JSON objects.
+///
+/// * `{"$type":"undefined"}`
+///
+/// * `{"$type":"number","value":"NaN"}` - also `"Infinity"` and `"-Infinity"`.
+///
+/// * `{"$type":"date","value":1600000000000}` - milliseconds since the epoch.
+///
+/// * `{"$type":"binary","kind":"Uint8Array","value":"AAEC"}` - an
+///   `ArrayBuffer`, a typed array or a `DataView` with its bytes in base64.
+///
+/// * `{"$type":"blob","mime":"image/png","value":"AAEC"}` - a `Blob` or
+///   `File`, file names aren't kept.
+///
+/// * `{"$type":"map","value":[[key,value],..]}` and
+///   `{"$type":"set","value":[..]}`
+///
+/// # Binary
+///
+/// The export starts with the bytes `IDBX`, followed by the length of the
+/// header as a little endian `u32` and the header as JSON. Every entry is
+/// stored as the index of its store in the header as `u32`, followed by its
+/// key and value.
+///
+/// Values start with a tag byte: `0` undefined, `1` null, `2` false, `3` true,
+/// `4` number, `5` string, `6` date, `7` binary, `8` blob, `9` array,
+/// `10` object, `11` map and `12` set. Numbers and dates are stored as little
+/// endian `f64`, strings as `u32` length and UTF-8, binary values as their kind
+/// followed by the `u32` length and the bytes, blobs as their MIME type followed
+/// by the length and bytes. Arrays, maps and sets store their number of
+/// entries as `u32` followed by the entries, objects the number of properties
+/// followed by the names and values of the properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Newline delimited JSON, binary data is encoded as base64.
+    Json,
+    /// A compact binary encoding.
+    Binary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Header {
+    format: String,
+    version: u32,
+    database: String,
+    pub(crate) database_version: u64,
+    pub(crate) stores: Vec<StoreSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StoreSchema {
+    pub(crate) name: String,
+    pub(crate) key_path: KeyPath,
+    auto_increment: bool,
+    indexes: Vec<IndexSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexSchema {
+    name: String,
+    key_path: KeyPath,
+    unique: bool,
+    multi_entry: bool,
+}
+
+/// A value that can be stored in a database, decoupled from the JavaScript
+/// heap so it can be encoded in both formats.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Date(f64),
+    Binary(String, Vec<u8>),
+    Blob(String, Vec<u8>),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+    Map(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+}
+
+fn bytes_of(buffer: &JsValue, offset: u32, length: u32) -> Vec<u8> {
+    js_sys::Uint8Array::new_with_byte_offset_and_length(buffer, offset, length).to_vec()
+}
+
+fn number_property(value: &JsValue, name: &str) -> Result<u32, JsValue> {
+    js_sys::Reflect::get(value, &name.into())?
+        .as_f64()
+        .map(|number| number as u32)
+        .ok_or_else(|| format!("missing property {}", name).into())
+}
+
+impl Value {
+    /// Read a value of the JavaScript heap, the content of blobs is read
+    /// asynchronously.
+    fn from_js(value: &JsValue) -> LocalBoxFuture<'_, Result<Self, JsValue>> {
+        Box::pin(async move {
+            if let Some(string) = value.as_string() {
+                return Ok(Value::String(string));
+            }
+
+            if let Some(number) = value.as_f64() {
+                return Ok(Value::Number(number));
+            }
+
+            if let Some(bool) = value.as_bool() {
+                return Ok(Value::Bool(bool));
+            }
+
+            if value.is_null() {
+                return Ok(Value::Null);
+            }
+
+            if value.is_undefined() {
+                return Ok(Value::Undefined);
+            }
+
+            if let Some(date) = value.dyn_ref::<js_sys::Date>() {
+                return Ok(Value::Date(date.get_time()));
+            }
+
+            if let Some(buffer) = value.dyn_ref::<js_sys::ArrayBuffer>() {
+                let bytes = bytes_of(buffer, 0, buffer.byte_length());
+                return Ok(Value::Binary("ArrayBuffer".to_owned(), bytes));
+            }
+
+            if js_sys::ArrayBuffer::is_view(value) {
+                let kind = js_sys::Reflect::get(value, &"constructor".into())
+                    .and_then(|constructor| js_sys::Reflect::get(&constructor, &"name".into()))?
+                    .as_string()
+                    .unwrap_or_default();
+                let buffer = js_sys::Reflect::get(value, &"buffer".into())?;
+                let bytes = bytes_of(
+                    &buffer,
+                    number_property(value, "byteOffset")?,
+                    number_property(value, "byteLength")?,
+                );
+
+                return Ok(Value::Binary(kind, bytes));
+            }
+
+            if let Some(blob) = value.dyn_ref::<web_sys::Blob>() {
+                let buffer = JsFuture::from(blob.array_buffer()).await?;
+                let bytes = js_sys::Uint8Array::new(&buffer).to_vec();
+                return Ok(Value::Blob(blob.type_(), bytes));
+            }
+
+            if js_sys::Array::is_array(value) {
+                let mut values = Vec::new();
+
+                for value in js_sys::Array::from(value).iter() {
+                    values.push(Value::from_js(&value).await?);
+                }
+
+                return Ok(Value::Array(values));
+            }
+
+            if value.is_instance_of::<js_sys::Map>() {
+                let mut entries = Vec::new();
+
+                for entry in js_sys::Array::from(value).iter() {
+                    let entry: js_sys::Array = entry.unchecked_into();
+                    let key = Value::from_js(&entry.get(0)).await?;
+                    entries.push((key, Value::from_js(&entry.get(1)).await?));
+                }
+
+                return Ok(Value::Map(entries));
+            }
+
+            if value.is_instance_of::<js_sys::Set>() {
+                let mut values = Vec::new();
+
+                for value in js_sys::Array::from(value).iter() {
+                    values.push(Value::from_js(&value).await?);
+                }
+
+                return Ok(Value::Set(values));
+            }
+
+            if let Some(object) = value.dyn_ref::<js_sys::Object>() {
+                let mut properties = Vec::new();
+
+                for entry in js_sys::Object::entries(object).iter() {
+                    let entry: js_sys::Array = entry.unchecked_into();
+                    let name = entry.get(0).as_string().unwrap_or_default();
+                    properties.push((name, Value::from_js(&entry.get(1)).await?));
+                }
+
+                return Ok(Value::Object(properties));
+            }
+
+            Err(format!("can't export the value {:?}", value).into())
+        })
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        Ok(match self {
+            Value::Undefined => JsValue::UNDEFINED,
+            Value::Null => JsValue::NULL,
+            Value::Bool(bool) => (*bool).into(),
+            Value::Number(number) => (*number).into(),
+            Value::String(string) => string.into(),
+            Value::Date(time) => js_sys::Date::new(&(*time).into()).into(),
+            Value::Binary(kind, bytes) => {
+                let buffer = js_sys::Uint8Array::from(bytes.as_slice()).buffer();
+
+                if kind == "ArrayBuffer" {
+                    buffer.into()
+                } else if VIEWS.contains(&kind.as_str()) {
+                    let constructor: js_sys::Function =
+                        js_sys::Reflect::get(&js_sys::global(), &kind.into())?.dyn_into()?;
+                    js_sys::Reflect::construct(&constructor, &js_sys::Array::of1(&buffer))?
+                } else {
+                    return Err(format!("unknown binary kind {}", kind).into());
+                }
+            }
+            Value::Blob(mime, bytes) => {
+                let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes.as_slice()));
+                let options = web_sys::BlobPropertyBag::new();
+                options.set_type(mime);
+
+                web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?.into()
+            }
+            Value::Array(values) => values
+                .iter()
+                .map(Value::to_js)
+                .collect::<Result<js_sys::Array, _>>()?
+                .into(),
+            Value::Object(properties) => {
+                let object = js_sys::Object::new();
+
+                for (name, value) in properties {
+                    js_sys::Reflect::set(&object, &name.into(), &value.to_js()?)?;
+                }
+
+                object.into()
+            }
+            Value::Map(entries) => {
+                let map = js_sys::Map::new();
+
+                for (key, value) in entries {
+                    map.set(&key.to_js()?, &value.to_js()?);
+                }
+
+                map.into()
+            }
+            Value::Set(values) => {
+                let set = js_sys::Set::new(&JsValue::UNDEFINED);
+
+                for value in values {
+                    set.add(&value.to_js()?);
+                }
+
+                set.into()
+            }
+        })
+    }
+
+    fn to_json(&self) -> Json {
+        match self {
+            Value::Undefined => json!({ "$type": "undefined" }),
+            Value::Null => Json::Null,
+            Value::Bool(bool) => Json::Bool(*bool),
+            Value::Number(number) if number.is_finite() => {
+                // Integers are written without a fraction.
+                if number.fract() == 0.0
+                    && number.abs() < 9_007_199_254_740_992.0
+                    && !(*number == 0.0 && number.is_sign_negative())
+                {
+                    json!(*number as i64)
+                } else {
+                    json!(number)
+                }
+            }
+            Value::Number(number) => {
+                let value = if number.is_nan() {
+                    "NaN"
+                } else if *number > 0.0 {
+                    "Infinity"
+                } else {
+                    "-Infinity"
+                };
+
+                json!({ "$type": "number", "value": value })
+            }
+            Value::String(string) => Json::String(string.clone()),
+            Value::Date(time) => json!({ "$type": "date", "value": time }),
+            Value::Binary(kind, bytes) => {
+                json!({ "$type": "binary", "kind": kind, "value": base64_encode(bytes) })
+            }
+            Value::Blob(mime, bytes) => {
+                json!({ "$type": "blob", "mime": mime, "value": base64_encode(bytes) })
+            }
+            Value::Array(values) => Json::Array(values.iter().map(Value::to_json).collect()),
+            Value::Object(properties) => {
+                let object: JsonMap<_, _> = properties
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_json()))
+                    .collect();
+
+                if object.contains_key("$type") {
+                    json!({ "$type": "object", "value": object })
+                } else {
+                    Json::Object(object)
+                }
+            }
+            Value::Map(entries) => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, value)| json!([key.to_json(), value.to_json()]))
+                    .collect();
+
+                json!({ "$type": "map", "value": entries })
+            }
+            Value::Set(values) => {
+                let values: Vec<_> = values.iter().map(Value::to_json).collect();
+                json!({ "$type": "set", "value": values })
+            }
+        }
+    }
+
+    fn from_json(json: &Json) -> Result<Self, JsValue> {
+        let object = match json {
+            Json::Null => return Ok(Value::Null),
+            Json::Bool(bool) => return Ok(Value::Bool(*bool)),
+            Json::Number(number) => {
+                return number
+                    .as_f64()
+                    .map(Value::Number)
+                    .ok_or_else(|| "invalid number".into())
+            }
+            Json::String(string) => return Ok(Value::String(string.clone())),
+            Json::Array(values) => {
+                return values
+                    .iter()
+                    .map(Value::from_json)
+                    .collect::<Result<_, _>>()
+                    .map(Value::Array)
+            }
+            Json::Object(object) => object,
+        };
+
+        let tag = match object.get("$type") {
+            Some(Json::String(tag)) => tag.as_str(),
+            Some(_) => return Err("invalid $type".into()),
+            None => return Value::object_from_json(object),
+        };
+
+        let value = object.get("value").unwrap_or(&Json::Null);
+        let string = |name: &str| {
+            object
+                .get(name)
+                .and_then(Json::as_str)
+                .ok_or_else(|| JsValue::from(format!("missing {} of {}", name, tag)))
+        };
+        let array = || {
+            value
+                .as_array()
+                .ok_or_else(|| JsValue::from(format!("missing values of {}", tag)))
+        };
+
+        Ok(match tag {
+            "object" => match value {
+                Json::Object(object) => Value::object_from_json(object)?,
+                _ => return Err("missing properties of object".into()),
+            },
+            "undefined" => Value::Undefined,
+            "number" => Value::Number(match string("value")? {
+                "NaN" => f64::NAN,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                _ => return Err("invalid number".into()),
+            }),
+            "date" => Value::Date(value.as_f64().ok_or("invalid date")?),
+            "binary" => Value::Binary(string("kind")?.to_owned(), base64_decode(string("value")?)?),
+            "blob" => Value::Blob(string("mime")?.to_owned(), base64_decode(string("value")?)?),
+            "map" => Value::Map(
+                array()?
+                    .iter()
+                    .map(|entry| match entry.as_array().map(Vec::as_slice) {
+                        Some([key, value]) => {
+                            Ok((Value::from_json(key)?, Value::from_json(value)?))
+                        }
+                        _ => Err(JsValue::from("invalid map entry")),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "set" => Value::Set(
+                array()?
+                    .iter()
+                    .map(Value::from_json)
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(format!("unknown $type {}", tag).into()),
+        })
+    }
+
+    fn object_from_json(object: &JsonMap<String, Json>) -> Result<Self, JsValue> {
+        object
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), Value::from_json(value)?)))
+            .collect::<Result<_, JsValue>>()
+            .map(Value::Object)
+    }
+
+    fn write_binary(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Undefined => out.push(0),
+            Value::Null => out.push(1),
+            Value::Bool(false) => out.push(2),
+            Value::Bool(true) => out.push(3),
+            Value::Number(number) => {
+                out.push(4);
+                out.extend_from_slice(&number.to_le_bytes());
+            }
+            Value::String(string) => {
+                out.push(5);
+                write_bytes(out, string.as_bytes());
+            }
+            Value::Date(time) => {
+                out.push(6);
+                out.extend_from_slice(&time.to_le_bytes());
+            }
+            Value::Binary(kind, bytes) => {
+                out.push(7);
+                write_bytes(out, kind.as_bytes());
+                write_bytes(out, bytes);
+            }
+            Value::Blob(mime, bytes) => {
+                out.push(8);
+                write_bytes(out, mime.as_bytes());
+                write_bytes(out, bytes);
+            }
+            Value::Array(values) => {
+                out.push(9);
+                write_len(out, values.len());
+                values.iter().for_each(|value| value.write_binary(out));
+            }
+            Value::Object(properties) => {
+                out.push(10);
+                write_len(out, properties.len());
+
+                for (name, value) in properties {
+                    write_bytes(out, name.as_bytes());
+                    value.write_binary(out);
+                }
+            }
+            Value::Map(entries) => {
+                out.push(11);
+                write_len(out, entries.len());
+
+                for (key, value) in entries {
+                    key.write_binary(out);
+                    value.write_binary(out);
+                }
+            }
+            Value::Set(values) => {
+                out.push(12);
+                write_len(out, values.len());
+                values.iter().for_each(|value| value.write_binary(out));
+            }
+        }
+    }
+
+    fn read_binary(input: &mut BinaryReader<'_>) -> Result<Self, JsValue> {
+        Ok(match input.byte()? {
+            0 => Value::Undefined,
+            1 => Value::Null,
+            2 => Value::Bool(false),
+            3 => Value::Bool(true),
+            4 => Value::Number(input.f64()?),
+            5 => Value::String(input.string()?),
+            6 => Value::Date(input.f64()?),
+            7 => Value::Binary(input.string()?, input.bytes()?.to_vec()),
+            8 => Value::Blob(input.string()?, input.bytes()?.to_vec()),
+            9 => Value::Array(
+                (0..input.u32()?)
+                    .map(|_| Value::read_binary(input))
+                    .collect::<Result<_, _>>()?,
+            ),
+            10 => Value::Object(
+                (0..input.u32()?)
+                    .map(|_| Ok((input.string()?, Value::read_binary(input)?)))
+                    .collect::<Result<_, JsValue>>()?,
+            ),
+            11 => Value::Map(
+                (0..input.u32()?)
+                    .map(|_| Ok((Value::read_binary(input)?, Value::read_binary(input)?)))
+                    .collect::<Result<_, JsValue>>()?,
+            ),
+            12 => Value::Set(
+                (0..input.u32()?)
+                    .map(|_| Value::read_binary(input))
+                    .collect::<Result<_, _>>()?,
+            ),
+            tag => return Err(format!("unknown value tag {}", tag).into()),
+        })
+    }
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    // Set if reading failed because the data ended.
+    truncated: bool,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            truncated: false,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], JsValue> {
+        if self.data.len() < len {
+            self.truncated = true;
+            return Err("the export is truncated".into());
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, JsValue> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, JsValue> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f64(&mut self) -> Result<f64, JsValue> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], JsValue> {
+        let len = self.u32()?;
+        self.take(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, JsValue> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|e| e.to_string().into())
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | ((*byte as u32) << (16 - 8 * i))
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[((group >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, JsValue> {
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+
+    for chunk in text.chunks(4) {
+        if chunk.len() == 1 {
+            return Err("invalid base64".into());
+        }
+
+        let mut group = 0u32;
+
+        for (i, char) in chunk.iter().enumerate() {
+            let value = BASE64
+                .iter()
+                .position(|c| c == char)
+                .ok_or("invalid base64")?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+
+        for i in 0..chunk.len() - 1 {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Ok(out)
+}
+
+/// An entry of a store in an export.
+struct Entry {
+    store: usize,
+    key: Value,
+    value: Value,
+}
+
+/// Writes an export in either format.
+struct Writer<F> {
+    format: ExportFormat,
+    write: F,
+}
+
+impl<F: FnMut(&[u8])> Writer<F> {
+    fn header(&mut self, header: &Header) -> Result<(), JsValue> {
+        let json = serde_json::to_vec(header).map_err(|e| JsValue::from(e.to_string()))?;
+
+        match self.format {
+            ExportFormat::Json => (self.write)(&[json.as_slice(), b"\n"].concat()),
+            ExportFormat::Binary => {
+                let mut out = MAGIC.to_vec();
+                write_bytes(&mut out, &json);
+                (self.write)(&out);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn entries(&mut self, header: &Header, entries: &[Entry]) -> Result<(), JsValue> {
+        let mut out = Vec::new();
+
+        for entry in entries {
+            match self.format {
+                ExportFormat::Json => {
+                    let line = json!({
+                        "store": header.stores[entry.store].name,
+                        "key": entry.key.to_json(),
+                        "value": entry.value.to_json(),
+                    });
+
+                    serde_json::to_writer(&mut out, &line)
+                        .map_err(|e| JsValue::from(e.to_string()))?;
+                    out.push(b'\n');
+                }
+                ExportFormat::Binary => {
+                    write_len(&mut out, entry.store);
+                    entry.key.write_binary(&mut out);
+                    entry.value.write_binary(&mut out);
+                }
+            }
+        }
+
+        (self.write)(&out);
+        Ok(())
+    }
+}
+
+/// Reads an export in either format from the input of an import, the format
+/// is detected from the first bytes.
+///
+/// The input is read as the entries are needed, only the entry that is
+/// parsed is buffered.
+struct Reader<R> {
+    input: R,
+    buffer: Vec<u8>,
+    // The number of bytes at the start of the buffer that were parsed.
+    parsed: usize,
+    eof: bool,
+    binary: bool,
+}
+
+impl<R: AsyncRead + Unpin> Reader<R> {
+    async fn new(input: R) -> Result<(Self, Header), JsValue> {
+        let mut reader = Reader {
+            input,
+            buffer: Vec::new(),
+            parsed: 0,
+            eof: false,
+            binary: false,
+        };
+
+        while reader.buffer.len() < MAGIC.len() && reader.fill().await? {}
+        reader.binary = reader.buffer.starts_with(MAGIC);
+
+        let header = if reader.binary {
+            reader.parsed = MAGIC.len();
+            let header = reader
+                .parse(|input| input.bytes().map(<[u8]>::to_vec))
+                .await?;
+
+            serde_json::from_slice(&header)
+        } else {
+            serde_json::from_slice(&reader.line().await?.unwrap_or_default())
+        };
+
+        let header: Header = header.map_err(|e| JsValue::from(e.to_string()))?;
+
+        if header.format != FORMAT || header.version > FORMAT_VERSION {
+            return Err("unsupported export format".into());
+        }
+
+        Ok((reader, header))
+    }
+
+    /// Read more of the input into the buffer, returns `false` at its end.
+    async fn fill(&mut self) -> Result<bool, JsValue> {
+        if self.eof {
+            return Ok(false);
+        }
+
+        self.buffer.drain(..self.parsed);
+        self.parsed = 0;
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + READ_SIZE, 0);
+
+        let read = self.input.read(&mut self.buffer[len..]).await;
+        self.buffer
+            .truncate(len + read.as_ref().map_or(0, |read| *read));
+
+        self.eof = read.map_err(|e| JsValue::from(e.to_string()))? == 0;
+        Ok(!self.eof)
+    }
+
+    /// Parse the next part of the binary format, the input is read until the
+    /// part is complete.
+    async fn parse<T>(
+        &mut self,
+        f: impl Fn(&mut BinaryReader<'_>) -> Result<T, JsValue>,
+    ) -> Result<T, JsValue> {
+        loop {
+            let mut input = BinaryReader::new(&self.buffer[self.parsed..]);
+
+            match f(&mut input) {
+                Ok(value) => {
+                    self.parsed = self.buffer.len() - input.data.len();
+                    return Ok(value);
+                }
+                Err(_) if input.truncated && !self.eof => {
+                    // At least double the buffered data, a big entry would be
+                    // parsed over and over otherwise.
+                    let wanted = 2 * (self.buffer.len() - self.parsed);
+                    while self.fill().await? && self.buffer.len() < wanted {}
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Read the next line of the JSON format, the last line doesn't need to
+    /// end with a newline.
+    async fn line(&mut self) -> Result<Option<Vec<u8>>, JsValue> {
+        let mut scanned = 0;
+
+        loop {
+            let rest = &self.buffer[self.parsed..];
+
+            if let Some(end) = rest[scanned..].iter().position(|byte| *byte == b'\n') {
+                let line = rest[..scanned + end].to_vec();
+                self.parsed += scanned + end + 1;
+                return Ok(Some(line));
+            }
+
+            scanned = rest.len();
+
+            if !self.fill().await? {
+                let line = self.buffer[self.parsed..].to_vec();
+                self.parsed = self.buffer.len();
+                return Ok(Some(line).filter(|line| !line.is_empty()));
+            }
+        }
+    }
+
+    async fn next(&mut self, header: &Header) -> Result<Option<Entry>, JsValue> {
+        let entry = if self.binary {
+            if self.parsed == self.buffer.len() && !self.fill().await? {
+                return Ok(None);
+            }
+
+            self.parse(|input| {
+                Ok(Entry {
+                    store: input.u32()? as usize,
+                    key: Value::read_binary(input)?,
+                    value: Value::read_binary(input)?,
+                })
+            })
+            .await?
+        } else {
+            let line = loop {
+                match self.line().await? {
+                    Some(line) if line.iter().all(u8::is_ascii_whitespace) => (),
+                    Some(line) => break line,
+                    None => return Ok(None),
+                }
+            };
+            let line: Json =
+                serde_json::from_slice(&line).map_err(|e| JsValue::from(e.to_string()))?;
+            let store = line["store"].as_str().ok_or("missing store of entry")?;
+
+            Entry {
+                store: header
+                    .stores
+                    .iter()
+                    .position(|schema| schema.name == store)
+                    .ok_or_else(|| format!("unknown store {}", store))?,
+                key: Value::from_json(&line["key"])?,
+                value: Value::from_json(&line["value"])?,
+            }
+        };
+
+        if entry.store >= header.stores.len() {
+            return Err("unknown store of entry".into());
+        }
+
+        Ok(Some(entry))
+    }
+}
+
+impl IndexedDb {
+    /// Read the schema of the database.
+    pub(crate) fn schema(&self) -> Result<Header, JsValue> {
+        let names = self.object_store_names();
+        let mut stores = Vec::new();
+
+        if !names.is_empty() {
+            let transaction = self.readonly_transaction();
+
+            for name in names {
+                let store = transaction.object_store(&name)?;
+                let store = store.as_raw();
+                let mut indexes = Vec::new();
+
+                for index in to_collection!(store.index_names() => Vec<String> : push) {
+                    let index = store.index(&index)?;
+
+                    indexes.push(IndexSchema {
+                        name: index.name(),
+                        key_path: index.key_path()?.into(),
+                        unique: index.unique(),
+                        multi_entry: index.multi_entry(),
+                    });
+                }
+
+                stores.push(StoreSchema {
+                    name,
+                    key_path: store.key_path()?.into(),
+                    auto_increment: store.auto_increment(),
+                    indexes,
+                });
+            }
+        }
+
+        Ok(Header {
+            format: FORMAT.to_owned(),
+            version: FORMAT_VERSION,
+            database: self.name(),
+            database_version: self.version(),
+            stores,
+        })
+    }
+
+    /// Export the schema and all the entries of the database.
+    ///
+    /// The export is passed to `write` in pieces as it is produced, see
+    /// [`ExportFormat`] for a description of the formats. The entries are read
+    /// in chunks using one transaction per chunk, writes made by other
+    /// transactions while the export runs might be partially included.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format the database should be exported in.
+    ///
+    /// * `write` - Called with the consecutive pieces of the export.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{ExportFormat, IndexedDb};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let mut backup = Vec::new();
+    ///
+    /// db.export(ExportFormat::Json, |bytes| backup.extend_from_slice(bytes))
+    ///     .await
+    ///     .expect("Failed to export the database");
+    /// # });
+    /// ```
+    pub async fn export(
+        &self,
+        format: ExportFormat,
+        write: impl FnMut(&[u8]),
+    ) -> Result<(), JsValue> {
+        let header = self.schema()?;
+        let mut writer = Writer { format, write };
+
+        writer.header(&header)?;
+
+        for (index, schema) in header.stores.iter().enumerate() {
+            let mut last_key: Option<JsValue> = None;
+
+            loop {
+                let transaction = self.readonly_transaction();
+                let store = transaction.object_store(&schema.name)?;
+                let store = store.as_raw();
+
+                let range = match &last_key {
+                    Some(key) => web_sys::IdbKeyRange::lower_bound_with_open(key, true)?.into(),
+                    None => JsValue::UNDEFINED,
+                };
+                let keys = store.get_all_keys_with_key_and_limit(&range, CHUNK_SIZE)?;
+                let values = store.get_all_with_key_and_limit(&range, CHUNK_SIZE)?;
+
+                let keys: js_sys::Array = IndexedDbRequest::new(keys).await?.unchecked_into();
+                let values: js_sys::Array = IndexedDbRequest::new(values).await?.unchecked_into();
+                transaction.done().await?;
+
+                // Blobs are read after the transaction, it would commit while
+                // waiting for them.
+                let mut entries = Vec::new();
+
+                for (key, value) in keys.iter().zip(values.iter()) {
+                    entries.push(Entry {
+                        store: index,
+                        key: Value::from_js(&key).await?,
+                        value: Value::from_js(&value).await?,
+                    });
+                }
+
+                writer.entries(&header, &entries)?;
+
+                if keys.length() < CHUNK_SIZE {
+                    break;
+                }
+
+                last_key = Some(keys.get(keys.length() - 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Import an export of [`IndexedDb::export`] into the database with the
+    /// given name.
+    ///
+    /// The database is opened with the version of the export. Object stores
+    /// and indexes of the export that don't exist yet are created in an
+    /// upgrade, if the database already has the version of the export but
+    /// lacks some of them its version is incremented to create them. The
+    /// entries are then written in chunks using one transaction per chunk.
+    /// Existing entries with the same keys are replaced. The format of the
+    /// export is detected automatically.
+    ///
+    /// Fails if the database already has a newer version.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database the export is imported into, it
+    ///   doesn't need to match the name of the exported database.
+    ///
+    /// * `data` - The complete export, use
+    ///   [`import_from_reader`](Self::import_from_reader) to read it while
+    ///   it is imported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let backup: Vec<u8> = Vec::new();
+    /// let db = IndexedDb::import("restored", &backup)
+    ///     .await
+    ///     .expect("Failed to import the database");
+    /// # });
+    /// ```
+    pub async fn import(name: &str, data: &[u8]) -> Result<IndexedDb, JsValue> {
+        Self::import_from_reader(name, data).await
+    }
+
+    /// Import an export of [`IndexedDb::export`] that is read from the given
+    /// input into the database with the given name.
+    ///
+    /// The export doesn't need to fit into memory, only the entries of the
+    /// chunk that is written are buffered. A stream of byte chunks, e.g. of
+    /// a download, can be turned into an input with
+    /// [`TryStreamExt::into_async_read`]. See [`IndexedDb::import`] for the
+    /// details.
+    ///
+    /// [`TryStreamExt::into_async_read`]: futures::TryStreamExt::into_async_read
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::{executor::block_on, stream, TryStreamExt};
+    /// # block_on(async {
+    /// # let chunks: Vec<Result<Vec<u8>, std::io::Error>> = Vec::new();
+    /// let db = IndexedDb::import_from_reader("restored", stream::iter(chunks).into_async_read())
+    ///     .await
+    ///     .expect("Failed to import the database");
+    /// # });
+    /// ```
+    pub async fn import_from_reader(
+        name: &str,
+        input: impl AsyncRead + Unpin,
+    ) -> Result<IndexedDb, JsValue> {
+        let (mut reader, header) = Reader::new(input).await?;
+        let factory = Factory::new()?;
+        let mut version = header.database_version as u32;
+
+        // Stores and indexes are only created if the version changes.
+        if let Some(existing) = factory.open_existing(name).await? {
+            let complete = includes(&existing.schema()?, &header.stores);
+            let current = existing.version();
+            existing.close();
+
+            if current == u64::from(version) && !complete {
+                version += 1;
+            }
+        }
+
+        let db = open_with_schema(&factory, name, version, &header.stores).await?;
+
+        loop {
+            // The input is read before the transaction is started, it would
+            // commit while waiting for the input.
+            let mut entries = Vec::new();
+
+            while entries.len() < CHUNK_SIZE as usize {
+                match reader.next(&header).await? {
+                    Some(entry) => entries.push(entry),
+                    None => break,
+                }
+            }
+
+            if entries.is_empty() {
+                break;
+            }
+
+            let transaction = db.transaction(TransactionMode::ReadWrite);
+
+            for entry in &entries {
+                let schema = &header.stores[entry.store];
+                let store = transaction.as_raw().object_store(&schema.name)?;
+                let value = entry.value.to_js()?;
+
+                // Failed requests abort the transaction, `done` reports them.
+                if schema.key_path == KeyPath::None {
+                    store.put_with_key(&value, &entry.key.to_js()?)?;
+                } else {
+                    store.put(&value)?;
+                }
+            }
+
+            transaction.done().await?;
+        }
+
+        Ok(db)
+    }
+}
+
+/// Does the schema include the given object stores and their indexes?
+fn includes(schema: &Header, stores: &[StoreSchema]) -> bool {
+    stores.iter().all(|store| {
+        schema.stores.iter().any(|existing| {
+            existing.name == store.name
+                && store.indexes.iter().all(|index| {
+                    existing
+                        .indexes
+                        .iter()
+                        .any(|existing| existing.name == index.name)
+                })
+        })
+    })
+}
+
+/// Open the database with the given name, object stores and indexes that
+/// don't exist yet are created in an upgrade.
+pub(crate) async fn open_with_schema(
+    factory: &Factory,
+    name: &str,
+    version: u32,
+    stores: &[StoreSchema],
+) -> Result<IndexedDb, JsValue> {
+    let stores = stores.to_vec();
+    let error = Rc::new(RefCell::new(None));
+    let upgrade_error = error.clone();
+
+    let db = factory
+        .open(name, version, move |_, db| {
+            if let Err(e) = create_schema(db, &stores) {
+                *upgrade_error.borrow_mut() = Some(e);
+                let _ = db.transaction().as_raw().abort();
+            }
+        })
+        .await;
+
+    match error.take() {
+        Some(e) => Err(e),
+        None => db,
+    }
+}
+
+fn create_schema(db: &DbDuringUpgrade, stores: &[StoreSchema]) -> Result<(), JsValue> {
+    let transaction = db.transaction();
+    let transaction = transaction.as_raw();
+    let db = db.as_raw();
+    let existing = to_collection!(db.object_store_names() => Vec<String> : push);
+
+    for schema in stores {
+        let store = if existing.contains(&schema.name) {
+            transaction.object_store(&schema.name)?
+        } else {
+            let parameters = web_sys::IdbObjectStoreParameters::new();
+            parameters.set_key_path(&schema.key_path.clone().into());
+            parameters.set_auto_increment(schema.auto_increment);
+
+            db.create_object_store_with_optional_parameters(&schema.name, &parameters)?
+        };
+
+        let indexes = to_collection!(store.index_names() => Vec<String> : push);
+
+        for index in &schema.indexes {
+            if indexes.contains(&index.name) {
+                continue;
+            }
+
+            let parameters = web_sys::IdbIndexParameters::new();
+            parameters.set_unique(index.unique);
+            parameters.set_multi_entry(index.multi_entry);
+
+            store.create_index_with_str_sequence_and_optional_parameters(
+                &index.name,
+                &index.key_path.clone().into(),
+                &parameters,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64_decode, base64_encode, BinaryReader, Value};
+    use crate::{ExportFormat, IndexedDb, TransactionMode};
+    use futures::{stream, TryStreamExt};
+    use wasm_bindgen_test::*;
+
+    #[test]
+    fn base64() {
+        for bytes in [&b""[..], b"a", b"ab", b"abc", b"abcd", &[0, 255, 128, 7]] {
+            assert_eq!(base64_decode(&base64_encode(bytes)).unwrap(), bytes);
+        }
+
+        assert_eq!(base64_encode(b"abcd"), "YWJjZA==");
+    }
+
+    #[test]
+    fn value_encodings() {
+        // Properties are sorted, JSON objects don't keep their order.
+        let value = Value::Object(vec![
+            ("$type".to_owned(), Value::String("tricky".to_owned())),
+            (
+                "bytes".to_owned(),
+                Value::Binary("Uint8Array".to_owned(), vec![1, 2, 3]),
+            ),
+            ("date".to_owned(), Value::Date(1.5)),
+            ("int".to_owned(), Value::Number(42.0)),
+            ("list".to_owned(), Value::Array(vec![Value::Undefined])),
+            (
+                "map".to_owned(),
+                Value::Map(vec![(Value::Null, Value::Set(vec![Value::Bool(true)]))]),
+            ),
+            ("nan".to_owned(), Value::Number(f64::NAN)),
+        ]);
+
+        let json = Value::from_json(&value.to_json()).unwrap();
+        let mut binary = Vec::new();
+        value.write_binary(&mut binary);
+        let binary = Value::read_binary(&mut BinaryReader::new(&binary)).unwrap();
+
+        // NaN never equals itself, compare the debug output instead.
+        assert_eq!(format!("{:?}", json), format!("{:?}", value));
+        assert_eq!(format!("{:?}", binary), format!("{:?}", value));
+    }
+
+    #[wasm_bindgen_test]
+    async fn round_trip() {
+        let db = IndexedDb::open("export", 1, |_, db| {
+            let store = db.create_object_store("test").unwrap();
+            store.create_index("by_name", "name", false).unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.clear().await.unwrap();
+
+        for i in 0..1200u32 {
+            store
+                .put(&i, &serde_json::json!({ "name": i }))
+                .await
+                .unwrap();
+        }
+
+        store.put_bytes(&"bytes", &[1, 2, 3]).await.unwrap();
+        transaction.done().await.unwrap();
+
+        for format in [ExportFormat::Json, ExportFormat::Binary] {
+            let mut data = Vec::new();
+            db.export(format, |bytes| data.extend_from_slice(bytes))
+                .await
+                .unwrap();
+
+            let name = format!("import_{:?}", format);
+            let imported = IndexedDb::import(&name, &data).await.unwrap();
+
+            let transaction = imported.transaction(TransactionMode::Readonly);
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.count(None).await.unwrap(), 1201);
+            assert!(store.index("by_name").is_ok());
+            assert_eq!(
+                store.get_bytes(&"bytes").await.unwrap().unwrap().to_vec(),
+                vec![1, 2, 3]
+            );
+
+            imported.close();
+
+            // The export is read in small pieces.
+            let chunks: Vec<_> = data
+                .chunks(7)
+                .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+                .collect();
+            let name = format!("import_chunks_{:?}", format);
+            let imported =
+                IndexedDb::import_from_reader(&name, stream::iter(chunks).into_async_read())
+                    .await
+                    .unwrap();
+
+            let transaction = imported.transaction(TransactionMode::Readonly);
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.count(None).await.unwrap(), 1201);
+
+            imported.close();
+        }
+
+        // A database that already has the version of the export but lacks
+        // its index is upgraded to create it.
+        let existing = IndexedDb::open("import_existing", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+        existing.close();
+
+        let mut data = Vec::new();
+        db.export(ExportFormat::Binary, |bytes| data.extend_from_slice(bytes))
+            .await
+            .unwrap();
+
+        let imported = IndexedDb::import("import_existing", &data).await.unwrap();
+        assert_eq!(imported.version(), 2);
+
+        let transaction = imported.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("test").unwrap();
+        assert_eq!(
+            store.index("by_name").unwrap().count(None).await.unwrap(),
+            1200
+        );
+    }
+}
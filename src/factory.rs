@@ -0,0 +1,254 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use futures::future::LocalBoxFuture;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{
+    db::{DbDuringUpgrade, IndexedDb},
+    quota::{self, StorageEstimate},
+    request::{IdbOpenDbRequest, IndexedDbRequest},
+};
+
+/// A handle to the IndexedDB factory that is used to open databases.
+///
+/// The factory is available in the window context as well as in dedicated,
+/// shared and service workers.
+#[derive(Debug, Clone)]
+pub struct Factory {
+    inner: web_sys::IdbFactory,
+}
+
+impl Factory {
+    /// Get the factory of the current global scope.
+    ///
+    /// The factory is looked up on the global object, this works for windows
+    /// and all kinds of workers. Fails if IndexedDB isn't available in the
+    /// current context.
+    pub fn new() -> Result<Self, JsValue> {
+        let factory = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?;
+
+        if factory.is_undefined() || factory.is_null() {
+            return Err("IndexedDB isn't available in this context".into());
+        }
+
+        Ok(Self::from_raw(factory.dyn_into()?))
+    }
+
+    /// Create a factory from the given `web_sys` factory.
+    ///
+    /// This can be used if the factory needs to be taken from a different
+    /// place than the current global scope.
+    pub fn from_raw(inner: web_sys::IdbFactory) -> Self {
+        Self { inner }
+    }
+
+    /// Get the underlying `web_sys` factory.
+    pub fn as_raw(&self) -> &web_sys::IdbFactory {
+        &self.inner
+    }
+
+    /// Open a database with the given name using this factory.
+    ///
+    /// See [`IndexedDb::open`] for a description of the arguments.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the given `version` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::Factory;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let factory = Factory::new().expect("IndexedDB isn't available");
+    ///
+    /// let db = factory.open("test", 1, |_, db| {
+    ///     db.create_object_store("test")
+    ///         .expect("Couldn't create object store");
+    /// }).await .expect("Failed to open indexed DB");
+    /// # });
+    /// ```
+    pub async fn open(
+        &self,
+        name: &str,
+        version: u32,
+        on_upgrade_needed: impl Fn(u32, &DbDuringUpgrade) + 'static,
+    ) -> Result<IndexedDb, JsValue> {
+        if version == 0 {
+            panic!("indexeddb version must be >= 1");
+        }
+
+        let request = self.inner.open_with_u32(name, version)?;
+        let request = IdbOpenDbRequest::new(request, move |old_version, db| {
+            on_upgrade_needed(old_version, &db)
+        });
+
+        request.await
+    }
+
+    /// Open a database with the given name using this factory, with an
+    /// asynchronous upgrade callback.
+    ///
+    /// Unlike with [`Factory::open`] the requests of the upgrade can be
+    /// awaited, e.g. to read the existing entries of a store and migrate
+    /// them. The `versionchange` transaction commits once the future returned
+    /// by the callback completed and all of its requests are done. Like in
+    /// any other transaction, awaiting something else than a request of the
+    /// transaction lets it commit early and later requests fail.
+    ///
+    /// An error returned by the callback aborts the upgrade, the database
+    /// keeps its old version and schema and opening it fails with the error.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the given `version` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::Factory;
+    /// # use futures::{executor::block_on, FutureExt};
+    /// # use serde_json::json;
+    /// # block_on(async {
+    /// let factory = Factory::new().expect("IndexedDB isn't available");
+    ///
+    /// // Version 1 stored the email of a user, version 2 stores an object.
+    /// let db = factory.open_with_async_upgrade("users", 2, |old_version, db| {
+    ///     async move {
+    ///         if old_version < 1 {
+    ///             db.create_object_store("users")?;
+    ///         } else {
+    ///             let store = db.object_store("users")?;
+    ///             let emails: Vec<String> = store.get_all(None).await?;
+    ///
+    ///             for (id, email) in (1u32..).zip(emails) {
+    ///                 store.put(&id, &json!({ "email": email })).await?;
+    ///             }
+    ///         }
+    ///
+    ///         Ok(())
+    ///     }
+    ///     .boxed_local()
+    /// }).await.expect("Failed to open indexed DB");
+    /// # });
+    /// ```
+    pub async fn open_with_async_upgrade<F>(
+        &self,
+        name: &str,
+        version: u32,
+        on_upgrade_needed: F,
+    ) -> Result<IndexedDb, JsValue>
+    where
+        F: for<'a> Fn(u32, &'a DbDuringUpgrade) -> LocalBoxFuture<'a, Result<(), JsValue>>
+            + 'static,
+    {
+        if version == 0 {
+            panic!("indexeddb version must be >= 1");
+        }
+
+        let on_upgrade_needed = Rc::new(on_upgrade_needed);
+        let failure = Rc::new(RefCell::new(None));
+        let on_failure = failure.clone();
+
+        let request = self.inner.open_with_u32(name, version)?;
+        let request = IdbOpenDbRequest::new(request, move |old_version, db| {
+            let on_upgrade_needed = on_upgrade_needed.clone();
+            let on_failure = on_failure.clone();
+
+            // The future is first polled in a microtask of the
+            // `upgradeneeded` event, while the transaction is still active.
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(error) = on_upgrade_needed(old_version, &db).await {
+                    db.abort();
+                    *on_failure.borrow_mut() = Some(error);
+                }
+            });
+        });
+
+        // Report why the upgrade was aborted instead of the `AbortError`.
+        request
+            .await
+            .map_err(|error| failure.take().unwrap_or(error))
+    }
+
+    /// Delete the database with the given name.
+    ///
+    /// Other connections to the database receive a `versionchange` event, the
+    /// deletion waits until they are closed. Deleting a database that doesn't
+    /// exist succeeds.
+    pub async fn delete_database(&self, name: &str) -> Result<(), JsValue> {
+        let request = self.inner.delete_database(name)?;
+        IndexedDbRequest::new(request.into()).await?;
+
+        Ok(())
+    }
+
+    /// Open the database with the given name at its current version, without
+    /// creating it if it doesn't exist.
+    pub(crate) async fn open_existing(&self, name: &str) -> Result<Option<IndexedDb>, JsValue> {
+        let created = Rc::new(Cell::new(false));
+        let on_created = created.clone();
+
+        let request = self.inner.open(name)?;
+        let request = IdbOpenDbRequest::new(request, move |old_version, db| {
+            // Aborting the upgrade of a new database deletes it again.
+            if old_version == 0 {
+                on_created.set(true);
+                db.abort();
+            }
+        });
+
+        let db = request.await;
+
+        if created.get() {
+            Ok(None)
+        } else {
+            db.map(Some)
+        }
+    }
+
+    /// Estimate how much storage the origin uses and how much it may use.
+    ///
+    /// The quota is shared by all the databases and other storage of the
+    /// origin. Writes fail with a `QuotaExceededError` once it is used up,
+    /// unless the storage is persisted the browser might also evict all of
+    /// it when the device runs low on space.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::Factory;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let factory = Factory::new().expect("IndexedDB isn't available");
+    ///
+    /// let estimate = factory.estimate().await.unwrap();
+    ///
+    /// if estimate.used_fraction() > 0.9 {
+    ///     // Warn the user before writes start failing.
+    /// }
+    /// # });
+    /// ```
+    pub async fn estimate(&self) -> Result<StorageEstimate, JsValue> {
+        quota::estimate().await
+    }
+
+    /// Ask the browser to not evict the storage of the origin.
+    ///
+    /// Returns if the storage is persisted. The browser might ask the user
+    /// for the permission or decide on its own, e.g. based on how often the
+    /// site is used.
+    pub async fn persist(&self) -> Result<bool, JsValue> {
+        quota::persist().await
+    }
+
+    /// Check if the storage of the origin is persisted, see
+    /// [`Factory::persist`].
+    pub async fn persisted(&self) -> Result<bool, JsValue> {
+        quota::persisted().await
+    }
+}
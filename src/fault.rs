@@ -0,0 +1,834 @@
+//! Fault injection for testing how storage code copes with failures.
+//!
+//! Errors like a `QuotaExceededError`, a transaction that is aborted halfway
+//! through, a blocked upgrade or a version change from another tab are hard
+//! to trigger in a real browser. The wrappers of this module implement the
+//! [`storage`](crate::storage) traits on top of any other implementation and
+//! fail requests as scripted through a shared [`Faults`] handle.
+//!
+//! Like in IndexedDB a failed request aborts its transaction, later requests
+//! fail with a `TransactionInactiveError` and committing the transaction
+//! fails with the injected error.
+//!
+//! # Examples
+//!
+//! ```
+//! # use futures::executor::block_on;
+//! use indexeddb::{
+//!     fault::{Faults, Operation},
+//!     memory::{ErrorKind, MemoryDb},
+//!     storage::{Database, Store, Transaction},
+//!     TransactionMode,
+//! };
+//!
+//! # block_on(async {
+//! let faults = Faults::new();
+//! let db = faults
+//!     .open(MemoryDb::open("test", 1, |_, db| {
+//!         db.create_object_store("test").unwrap();
+//!     }))
+//!     .await
+//!     .expect("Failed to open the database");
+//!
+//! faults.fail(Operation::Put, Some("test"), ErrorKind::QuotaExceeded);
+//!
+//! let transaction = db.transaction(TransactionMode::ReadWrite);
+//! let store = transaction.object_store("test").unwrap();
+//!
+//! let error = store.put(&"Hello", &"world").await.unwrap_err();
+//! assert_eq!(error.kind(), ErrorKind::QuotaExceeded);
+//! # });
+//! ```
+
+// See the storage module, the futures of the traits are never `Send`.
+#![allow(async_fn_in_trait)]
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    future::Future,
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+use futures::future;
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::JsValue;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::memory::Error as MemoryError;
+use crate::{
+    storage::{AbortHandle, Cursor, Database, Index, Store, Transaction},
+    ErrorKind, KeyRange, TransactionMode,
+};
+
+/// The kinds of requests a fault can be injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Reading a single value from a store or index.
+    Get,
+    /// Reading multiple values from a store or index.
+    GetAll,
+    /// Counting the values of a store or index.
+    Count,
+    /// Opening or advancing a cursor.
+    Cursor,
+    /// Adding a value.
+    Add,
+    /// Storing a value.
+    Put,
+    /// Deleting a value.
+    Delete,
+    /// Clearing a store.
+    Clear,
+    /// Committing a transaction.
+    Commit,
+}
+
+/// Errors that can be created by fault injection.
+///
+/// The kinds of [`ErrorKind`] correspond to the `DOMException` names of
+/// IndexedDB.
+pub trait InjectedError {
+    /// Create an error of the given kind.
+    fn injected(kind: ErrorKind, message: &str) -> Self;
+}
+
+impl InjectedError for JsValue {
+    fn injected(kind: ErrorKind, message: &str) -> Self {
+        web_sys::DomException::new_with_message_and_name(message, kind.name())
+            .map(Into::into)
+            .unwrap_or_else(|_| format!("{}: {}", kind.name(), message).into())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl InjectedError for MemoryError {
+    fn injected(kind: ErrorKind, message: &str) -> Self {
+        MemoryError::new(kind, message)
+    }
+}
+
+/// A `versionchange` event, emitted when another connection wants to upgrade
+/// or delete the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionChangeEvent {
+    /// The version of the open database.
+    pub old_version: u64,
+    /// The requested version, `None` if the database is being deleted.
+    pub new_version: Option<u64>,
+}
+
+type VersionChangeHandler = Rc<dyn Fn(VersionChangeEvent)>;
+
+#[derive(Debug)]
+struct Rule {
+    operation: Operation,
+    store: Option<String>,
+    skip: usize,
+    kind: ErrorKind,
+}
+
+#[derive(Default)]
+struct FaultState {
+    rules: Vec<Rule>,
+    abort_after: Option<usize>,
+    block_upgrades: bool,
+    // The tasks of the opens that wait for upgrades to be unblocked.
+    blocked: Vec<Waker>,
+    handlers: Vec<(String, u64, VersionChangeHandler)>,
+}
+
+impl fmt::Debug for FaultState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FaultState")
+            .field("rules", &self.rules)
+            .field("abort_after", &self.abort_after)
+            .field("block_upgrades", &self.block_upgrades)
+            .field("blocked", &self.blocked.len())
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+/// A script of faults that are injected into wrapped databases.
+///
+/// Cloning the handle yields a handle to the same script, faults can be
+/// scripted while the databases are in use.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    state: Rc<RefCell<FaultState>>,
+}
+
+impl Faults {
+    /// Create a script without any faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail the next request of the given kind with an error of the given
+    /// kind.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The kind of request that should fail.
+    ///
+    /// * `store` - The object store the request needs to be made on, requests
+    ///   on any store match if no store is given.
+    ///
+    /// * `kind` - The kind of error the request fails with.
+    pub fn fail(&self, operation: Operation, store: Option<&str>, kind: ErrorKind) {
+        self.fail_nth(operation, store, 0, kind);
+    }
+
+    /// Fail a request of the given kind after `skip` matching requests
+    /// succeeded, see [`Faults::fail`].
+    pub fn fail_nth(
+        &self,
+        operation: Operation,
+        store: Option<&str>,
+        skip: usize,
+        kind: ErrorKind,
+    ) {
+        self.state.borrow_mut().rules.push(Rule {
+            operation,
+            store: store.map(ToOwned::to_owned),
+            skip,
+            kind,
+        });
+    }
+
+    /// Abort every transaction once it made the given number of requests,
+    /// the next request fails with an `AbortError`.
+    ///
+    /// `None` disables the limit.
+    pub fn abort_after(&self, operations: Option<usize>) {
+        self.state.borrow_mut().abort_after = operations;
+    }
+
+    /// Block upgrades, like another connection that doesn't close when the
+    /// database is upgraded.
+    ///
+    /// Databases opened using [`Faults::open`] wait until upgrades are
+    /// unblocked again, the open is only started then. Code that gives up
+    /// on a blocked upgrade, e.g. after a timeout, does so by dropping the
+    /// future, which cancels the open.
+    pub fn block_upgrades(&self, blocked: bool) {
+        let waiting = {
+            let mut state = self.state.borrow_mut();
+            state.block_upgrades = blocked;
+
+            if blocked {
+                Vec::new()
+            } else {
+                std::mem::take(&mut state.blocked)
+            }
+        };
+
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+
+    /// Emit a `versionchange` event to the handlers of all the wrapped
+    /// databases with the given name.
+    ///
+    /// Only the handlers registered using
+    /// [`FaultyDatabase::on_version_change`] are called, the wrapped
+    /// connection doesn't receive an event. Listeners on the connection
+    /// itself, e.g. the ones of a [`ConnectionPool`], need a real version
+    /// change, open the database with a newer version from a second
+    /// connection to test them.
+    ///
+    /// [`ConnectionPool`]: crate::ConnectionPool
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - The name of the database.
+    ///
+    /// * `new_version` - The version another connection requested, `None` if
+    ///   the database is being deleted.
+    pub fn emit_version_change(&self, database: &str, new_version: Option<u64>) {
+        let handlers: Vec<_> = self
+            .state
+            .borrow()
+            .handlers
+            .iter()
+            .filter(|(name, _, _)| name == database)
+            .map(|(_, version, handler)| (*version, handler.clone()))
+            .collect();
+
+        for (old_version, handler) in handlers {
+            handler(VersionChangeEvent {
+                old_version,
+                new_version,
+            });
+        }
+    }
+
+    /// Remove all the scripted faults, registered `versionchange` handlers
+    /// are kept.
+    pub fn reset(&self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.rules.clear();
+            state.abort_after = None;
+        }
+
+        self.block_upgrades(false);
+    }
+
+    /// Open a database and wrap it.
+    ///
+    /// # Arguments
+    ///
+    /// * `open` - The future opening the database, e.g. the result of
+    ///   [`IndexedDb::open`](crate::IndexedDb::open). The future isn't polled
+    ///   while upgrades are blocked, see [`Faults::block_upgrades`].
+    pub async fn open<D, F>(&self, open: F) -> Result<FaultyDatabase<D>, D::Error>
+    where
+        D: Database,
+        F: Future<Output = Result<D, D::Error>>,
+    {
+        future::poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+
+            if state.block_upgrades {
+                state.blocked.push(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+
+        Ok(self.wrap(open.await?))
+    }
+
+    /// Wrap an opened database.
+    pub fn wrap<D: Database>(&self, db: D) -> FaultyDatabase<D> {
+        FaultyDatabase {
+            inner: db,
+            faults: self.clone(),
+        }
+    }
+
+    /// Find and remove the rule that fails the given request.
+    fn take_rule(&self, operation: Operation, store: &str) -> Option<ErrorKind> {
+        let mut state = self.state.borrow_mut();
+        let position = state.rules.iter().position(|rule| {
+            rule.operation == operation && rule.store.as_deref().is_none_or(|s| s == store)
+        })?;
+
+        let rule = &mut state.rules[position];
+
+        if rule.skip > 0 {
+            rule.skip -= 1;
+            None
+        } else {
+            Some(state.rules.remove(position).kind)
+        }
+    }
+}
+
+/// The fault state of a single transaction.
+#[derive(Debug)]
+struct TransactionFaults {
+    faults: Faults,
+    operations: Cell<usize>,
+    failure: RefCell<Option<(ErrorKind, String)>>,
+    abort: AbortHandle,
+}
+
+impl TransactionFaults {
+    /// Decide if a request fails, a failing request aborts the wrapped
+    /// transaction right away so none of its writes can be committed.
+    fn check<E: InjectedError>(&self, operation: Operation, store: &str) -> Result<(), E> {
+        if self.failure.borrow().is_some() {
+            return Err(E::injected(
+                ErrorKind::TransactionInactive,
+                "the transaction was aborted by an injected fault",
+            ));
+        }
+
+        let operations = self.operations.get() + 1;
+        self.operations.set(operations);
+
+        let abort_after = self.faults.state.borrow().abort_after;
+
+        let failure = if abort_after.is_some_and(|limit| operations > limit) {
+            Some((ErrorKind::Abort, "the transaction was aborted".to_owned()))
+        } else {
+            self.faults.take_rule(operation, store).map(|kind| {
+                let message = format!("injected failure of a {:?} request", operation);
+                (kind, message)
+            })
+        };
+
+        match failure {
+            Some((kind, message)) => {
+                let error = E::injected(kind, &message);
+                *self.failure.borrow_mut() = Some((kind, message));
+                self.abort.abort();
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn failure<E: InjectedError>(&self) -> Option<E> {
+        self.failure
+            .borrow()
+            .as_ref()
+            .map(|(kind, message)| E::injected(*kind, message))
+    }
+}
+
+/// A database that injects the faults of a [`Faults`] script.
+#[derive(Debug)]
+pub struct FaultyDatabase<D> {
+    inner: D,
+    faults: Faults,
+}
+
+impl<D: Database> FaultyDatabase<D> {
+    /// Get the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Register a handler that is called for the `versionchange` events
+    /// emitted using [`Faults::emit_version_change`].
+    pub fn on_version_change(&self, handler: impl Fn(VersionChangeEvent) + 'static) {
+        self.faults.state.borrow_mut().handlers.push((
+            self.inner.name(),
+            self.inner.version(),
+            Rc::new(handler),
+        ));
+    }
+}
+
+impl<D> Database for FaultyDatabase<D>
+where
+    D: Database,
+    D::Error: InjectedError,
+{
+    type Error = D::Error;
+    type Transaction<'a>
+        = FaultyTransaction<D::Transaction<'a>>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn version(&self) -> u64 {
+        self.inner.version()
+    }
+
+    fn object_store_names(&self) -> Vec<String> {
+        self.inner.object_store_names()
+    }
+
+    fn transaction(&self, mode: TransactionMode) -> Self::Transaction<'_> {
+        let inner = self.inner.transaction(mode);
+        let faults = Rc::new(TransactionFaults {
+            faults: self.faults.clone(),
+            operations: Cell::new(0),
+            failure: RefCell::new(None),
+            abort: inner.abort_handle(),
+        });
+
+        FaultyTransaction { inner, faults }
+    }
+}
+
+/// A transaction of a [`FaultyDatabase`].
+///
+/// The wrapped transaction is aborted as soon as a fault is injected into one
+/// of its requests.
+#[derive(Debug)]
+pub struct FaultyTransaction<T: Transaction> {
+    inner: T,
+    faults: Rc<TransactionFaults>,
+}
+
+impl<T> Transaction for FaultyTransaction<T>
+where
+    T: Transaction,
+    T::Error: InjectedError,
+{
+    type Error = T::Error;
+    type Store<'a>
+        = FaultyStore<T::Store<'a>>
+    where
+        Self: 'a;
+
+    fn object_store(&self, name: &str) -> Result<Self::Store<'_>, T::Error> {
+        let inner = self.inner.object_store(name)?;
+
+        Ok(FaultyStore {
+            inner,
+            name: name.to_owned(),
+            faults: self.faults.clone(),
+        })
+    }
+
+    async fn done(self) -> Result<(), T::Error> {
+        if self.faults.failure::<T::Error>().is_none() {
+            // The commit isn't a request on a store, rules for it match any
+            // store.
+            let _ = self.faults.check::<T::Error>(Operation::Commit, "");
+        }
+
+        match self.faults.failure() {
+            // The wrapped transaction was aborted when the fault was injected.
+            Some(error) => Err(error),
+            None => self.inner.done().await,
+        }
+    }
+
+    async fn abort(self) -> Result<(), T::Error> {
+        self.inner.abort().await
+    }
+
+    fn abort_handle(&self) -> AbortHandle {
+        self.inner.abort_handle()
+    }
+}
+
+/// An object store of a [`FaultyTransaction`].
+#[derive(Debug)]
+pub struct FaultyStore<S> {
+    inner: S,
+    name: String,
+    faults: Rc<TransactionFaults>,
+}
+
+impl<S> Store for FaultyStore<S>
+where
+    S: Store,
+    S::Error: InjectedError,
+{
+    type Error = S::Error;
+    type Index<'a>
+        = FaultyIndex<S::Index<'a>>
+    where
+        Self: 'a;
+    type Cursor<'a>
+        = FaultyCursor<S::Cursor<'a>>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, S::Error> {
+        self.faults.check(Operation::Get, &self.name)?;
+        self.inner.get(key).await
+    }
+
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, S::Error> {
+        self.faults.check(Operation::GetAll, &self.name)?;
+        self.inner.get_all(range).await
+    }
+
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, S::Error> {
+        self.faults.check(Operation::Count, &self.name)?;
+        self.inner.count(range).await
+    }
+
+    fn index(&self, name: &str) -> Result<Self::Index<'_>, S::Error> {
+        Ok(FaultyIndex {
+            inner: self.inner.index(name)?,
+            store: self.name.clone(),
+            faults: self.faults.clone(),
+        })
+    }
+
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, S::Error> {
+        self.faults.check(Operation::Cursor, &self.name)?;
+
+        Ok(FaultyCursor {
+            inner: self.inner.open_cursor(range)?,
+            store: self.name.clone(),
+            faults: self.faults.clone(),
+        })
+    }
+
+    async fn add<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), S::Error> {
+        self.faults.check(Operation::Add, &self.name)?;
+        self.inner.add(key, value).await
+    }
+
+    async fn put<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), S::Error> {
+        self.faults.check(Operation::Put, &self.name)?;
+        self.inner.put(key, value).await
+    }
+
+    async fn delete(&self, key: &impl Serialize) -> Result<(), S::Error> {
+        self.faults.check(Operation::Delete, &self.name)?;
+        self.inner.delete(key).await
+    }
+
+    async fn clear(&self) -> Result<(), S::Error> {
+        self.faults.check(Operation::Clear, &self.name)?;
+        self.inner.clear().await
+    }
+}
+
+/// An index of a [`FaultyStore`].
+#[derive(Debug)]
+pub struct FaultyIndex<I> {
+    inner: I,
+    store: String,
+    faults: Rc<TransactionFaults>,
+}
+
+impl<I> Index for FaultyIndex<I>
+where
+    I: Index,
+    I::Error: InjectedError,
+{
+    type Error = I::Error;
+    type Cursor<'a>
+        = FaultyCursor<I::Cursor<'a>>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, I::Error> {
+        self.faults.check(Operation::Get, &self.store)?;
+        self.inner.get(key).await
+    }
+
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, I::Error> {
+        self.faults.check(Operation::GetAll, &self.store)?;
+        self.inner.get_all(range).await
+    }
+
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, I::Error> {
+        self.faults.check(Operation::Count, &self.store)?;
+        self.inner.count(range).await
+    }
+
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, I::Error> {
+        self.faults.check(Operation::Cursor, &self.store)?;
+
+        Ok(FaultyCursor {
+            inner: self.inner.open_cursor(range)?,
+            store: self.store.clone(),
+            faults: self.faults.clone(),
+        })
+    }
+}
+
+/// A cursor of a [`FaultyStore`] or [`FaultyIndex`].
+#[derive(Debug)]
+pub struct FaultyCursor<C> {
+    inner: C,
+    store: String,
+    faults: Rc<TransactionFaults>,
+}
+
+impl<C> Cursor for FaultyCursor<C>
+where
+    C: Cursor,
+    C::Error: InjectedError,
+{
+    type Error = C::Error;
+
+    async fn next(&mut self) -> Result<bool, C::Error> {
+        self.faults.check(Operation::Cursor, &self.store)?;
+        self.inner.next().await
+    }
+
+    fn key<K: DeserializeOwned>(&self) -> Result<K, C::Error> {
+        self.inner.key()
+    }
+
+    fn primary_key<K: DeserializeOwned>(&self) -> Result<K, C::Error> {
+        self.inner.primary_key()
+    }
+
+    fn value<V: DeserializeOwned>(&self) -> Result<V, C::Error> {
+        self.inner.value()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use super::{Faults, FaultyDatabase, Operation, VersionChangeEvent};
+    use crate::{
+        memory::{ErrorKind, MemoryDb, MemoryFactory},
+        storage::{Database, Store, Transaction},
+        TransactionMode,
+    };
+    use futures::{executor::block_on, FutureExt};
+    use std::{cell::RefCell, rc::Rc};
+
+    fn open(factory: &MemoryFactory, faults: &Faults) -> FaultyDatabase<MemoryDb> {
+        block_on(faults.open(factory.open("test", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+            db.create_object_store("other").unwrap();
+        })))
+        .expect("Failed to open the database")
+    }
+
+    #[test]
+    fn failed_requests_abort() {
+        let faults = Faults::new();
+        let db = open(&MemoryFactory::new(), &faults);
+
+        faults.fail_nth(Operation::Put, Some("test"), 1, ErrorKind::QuotaExceeded);
+
+        block_on(async {
+            let transaction = db.transaction(TransactionMode::ReadWrite);
+            let other = transaction.object_store("other").unwrap();
+            let store = transaction.object_store("test").unwrap();
+
+            other.put(&"a", &1).await.unwrap();
+            store.put(&"a", &1).await.unwrap();
+
+            let error = store.put(&"b", &2).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::QuotaExceeded);
+
+            let error = store.get::<u32>(&"a").await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::TransactionInactive);
+
+            let error = transaction.done().await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::QuotaExceeded);
+
+            // The rule was used up and the writes were rolled back.
+            let transaction = db.transaction(TransactionMode::ReadWrite);
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get::<u32>(&"a").await.unwrap(), None);
+            store.put(&"b", &2).await.unwrap();
+            transaction.done().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn abort_after_operations() {
+        let faults = Faults::new();
+        let db = open(&MemoryFactory::new(), &faults);
+
+        faults.abort_after(Some(2));
+
+        block_on(async {
+            let transaction = db.transaction(TransactionMode::ReadWrite);
+            let store = transaction.object_store("test").unwrap();
+
+            store.put(&"a", &1).await.unwrap();
+            store.put(&"b", &2).await.unwrap();
+
+            let error = store.put(&"c", &3).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Abort);
+
+            // Dropping the transaction rolls it back as well.
+            drop(store);
+            drop(transaction);
+
+            faults.reset();
+
+            let transaction = db.transaction(TransactionMode::Readonly);
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.count(None).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn faults_abort_right_away() {
+        let faults = Faults::new();
+        let db = open(&MemoryFactory::new(), &faults);
+
+        faults.fail_nth(Operation::Put, None, 1, ErrorKind::QuotaExceeded);
+
+        block_on(async {
+            let transaction = db.transaction(TransactionMode::ReadWrite);
+            let store = transaction.object_store("test").unwrap();
+
+            store.put(&"a", &1).await.unwrap();
+            assert!(store.put(&"b", &2).await.is_err());
+
+            // A newer transaction would auto-commit the faulted one if it
+            // was still active.
+            let other = db.transaction(TransactionMode::Readonly);
+            let other_store = other.object_store("test").unwrap();
+            assert_eq!(other_store.get::<u32>(&"a").await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn failed_commit() {
+        let faults = Faults::new();
+        let db = open(&MemoryFactory::new(), &faults);
+
+        faults.fail(Operation::Commit, None, ErrorKind::Unknown);
+
+        block_on(async {
+            let transaction = db.transaction(TransactionMode::ReadWrite);
+            let store = transaction.object_store("test").unwrap();
+            store.put(&"a", &1).await.unwrap();
+
+            let error = transaction.done().await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Unknown);
+
+            let transaction = db.transaction(TransactionMode::Readonly);
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get::<u32>(&"a").await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn blocked_upgrades_and_version_changes() {
+        let factory = MemoryFactory::new();
+        let faults = Faults::new();
+        let db = open(&factory, &faults);
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let handler_events = events.clone();
+        db.on_version_change(move |event| handler_events.borrow_mut().push(event));
+
+        faults.block_upgrades(true);
+
+        // The open waits until upgrades are unblocked.
+        let mut upgraded = Box::pin(faults.open(factory.open("test", 2, |_, _| ())));
+        assert!((&mut upgraded).now_or_never().is_none());
+        assert_eq!(db.version(), 1);
+
+        faults.block_upgrades(false);
+        assert_eq!(block_on(upgraded).unwrap().version(), 2);
+
+        faults.emit_version_change("test", Some(2));
+        faults.emit_version_change("unrelated", None);
+
+        assert_eq!(
+            *events.borrow(),
+            vec![VersionChangeEvent {
+                old_version: 1,
+                new_version: Some(2)
+            }]
+        );
+    }
+}
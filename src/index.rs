@@ -0,0 +1,283 @@
+use std::marker::PhantomData;
+
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+use crate::{
+    codec::{serialize_key, Codec, Decode, Json},
+    cursor::Cursor,
+    key_range::{query, KeyRange},
+    object_store::{count_from_js, KeyPath, ObjectStore},
+    request::IndexedDbRequest,
+};
+
+/// An index of an object store.
+///
+/// Indexes are created during an upgrade using
+/// [`ObjectStoreDuringUpgrade::create_index`], the entries of an index are
+/// ordered by the index key and then by the primary key. Values are decoded
+/// using the codec `C` of the object store the index belongs to.
+///
+/// [`ObjectStoreDuringUpgrade::create_index`]: crate::ObjectStoreDuringUpgrade::create_index
+#[derive(Debug)]
+pub struct Index<'a, C: Codec = Json> {
+    inner: web_sys::IdbIndex,
+    store: PhantomData<&'a ObjectStore<C>>,
+}
+
+impl<'a, C: Codec> Index<'a, C> {
+    pub(crate) fn new(inner: web_sys::IdbIndex) -> Self {
+        Self {
+            inner,
+            store: PhantomData,
+        }
+    }
+
+    /// The name of the index.
+    pub fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    /// The path to the key of the index inside of the stored values.
+    pub fn key_path(&self) -> KeyPath {
+        self.inner.key_path().unwrap_or(JsValue::NULL).into()
+    }
+
+    /// Does the index reject values with an index key that is already used by
+    /// another value.
+    pub fn unique(&self) -> bool {
+        self.inner.unique()
+    }
+
+    /// Get the first value with the given index key.
+    ///
+    /// Like in [`ObjectStore::get`], a stored `null` is treated like a missing
+    /// value.
+    ///
+    /// [`ObjectStore::get`]: crate::ObjectStore::get
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("users", 1, |_, db| {
+    /// #   let store = db.create_object_store("users").unwrap();
+    /// #   store.create_index("by_email", "email", true).unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::Readonly);
+    /// let store = transaction.object_store("users").unwrap();
+    /// let index = store.index("by_email").unwrap();
+    ///
+    /// let user: Option<serde_json::Value> = index.get(&"me@example.com").await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get<V>(&self, key: &impl Serialize) -> Result<Option<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        self.get_js(key)
+            .await?
+            .filter(|value| !value.is_null())
+            .map(C::decode)
+            .transpose()
+    }
+
+    /// Get all the values with index keys inside of the given range, all the
+    /// values of the index if no range is given.
+    pub async fn get_all<V>(&self, range: Option<&KeyRange>) -> Result<Vec<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        self.get_all_js(range)
+            .await?
+            .into_iter()
+            .map(C::decode)
+            .collect()
+    }
+
+    /// Count the values with index keys inside of the given range, all the
+    /// values of the index if no range is given.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<u32, JsValue> {
+        let request = IndexedDbRequest::new(self.inner.count_with_key(&query(range)?)?);
+
+        count_from_js(request.await?)
+    }
+
+    /// Open a cursor that iterates over the entries of the index with index
+    /// keys inside of the given range, all the entries of the index if no
+    /// range is given.
+    ///
+    /// The key of the cursor is the index key, [`Cursor::primary_key`]
+    /// returns the key of the value in the object store.
+    pub fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Cursor<'_, C>, JsValue> {
+        Ok(Cursor::new(
+            self.inner.open_cursor_with_range(&query(range)?)?,
+        ))
+    }
+
+    /// Get the underlying `web_sys` index.
+    pub fn as_raw(&self) -> &web_sys::IdbIndex {
+        &self.inner
+    }
+
+    pub(crate) async fn get_js(&self, key: &impl Serialize) -> Result<Option<JsValue>, JsValue> {
+        let request = IndexedDbRequest::new(self.inner.get(&serialize_key(key)?)?);
+        let value = request.await?;
+
+        if value.is_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(value))
+        }
+    }
+
+    pub(crate) async fn get_all_js(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<JsValue>, JsValue> {
+        let request = IndexedDbRequest::new(self.inner.get_all_with_key(&query(range)?)?);
+
+        Ok(js_sys::Array::from(&request.await?).to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{IndexedDb, KeyPath, KeyRange, TransactionMode};
+    use serde_json::{json, Value};
+    use std::convert::TryFrom;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn indexes_and_ranges() {
+        let db = IndexedDb::open("indexes", 1, |_, db| {
+            let store = db.create_object_store("users").unwrap();
+            store.create_index("by_email", "email", true).unwrap();
+            store.create_index("by_age", "age", false).unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("users").unwrap();
+
+        for (id, email, age) in &[(1, "a@example.com", 30), (2, "b@example.com", 20)] {
+            store
+                .put(id, &json!({ "email": email, "age": age }))
+                .await
+                .unwrap();
+        }
+
+        transaction.done().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("users").unwrap();
+        assert!(store
+            .add(&3, &json!({ "email": "a@example.com", "age": 40 }))
+            .await
+            .is_err());
+        assert!(transaction.done().await.is_err());
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("users").unwrap();
+        assert_eq!(
+            store
+                .count(Some(&KeyRange::try_from(1..2).unwrap()))
+                .await
+                .unwrap(),
+            1
+        );
+
+        let index = store.index("by_age").unwrap();
+        assert!(!index.unique());
+
+        let users: Vec<Value> = index
+            .get_all(Some(&KeyRange::try_from(..25).unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(users, vec![json!({ "email": "b@example.com", "age": 20 })]);
+
+        let mut cursor = index.open_cursor(None).unwrap();
+        let mut keys = Vec::new();
+
+        while cursor.next().await.unwrap() {
+            keys.push((
+                cursor.key::<u32>().unwrap(),
+                cursor.primary_key::<u32>().unwrap(),
+            ));
+        }
+
+        assert_eq!(keys, vec![(20, 2), (30, 1)]);
+
+        let user: Value = store
+            .index("by_email")
+            .unwrap()
+            .get(&"a@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user["age"], 30);
+        assert_eq!(
+            store
+                .index("by_email")
+                .unwrap()
+                .count(Some(&KeyRange::only(&"c@example.com").unwrap()))
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn compound_index() {
+        let db = IndexedDb::open("compound_index", 1, |_, db| {
+            let store = db.create_object_store("users").unwrap();
+            store
+                .create_index("by_name", &["last", "first"][..], false)
+                .unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("users").unwrap();
+
+        for (id, first, last) in &[(1, "Jane", "Doe"), (2, "John", "Doe"), (3, "Ann", "Roe")] {
+            store
+                .put(id, &json!({ "first": first, "last": last }))
+                .await
+                .unwrap();
+        }
+
+        transaction.done().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("users").unwrap();
+        let index = store.index("by_name").unwrap();
+        assert_eq!(
+            index.key_path(),
+            KeyPath::Multi(vec!["last".to_owned(), "first".to_owned()])
+        );
+
+        let user: Value = index.get(&("Doe", "John")).await.unwrap().unwrap();
+        assert_eq!(user, json!({ "first": "John", "last": "Doe" }));
+
+        let mut cursor = index.open_cursor(None).unwrap();
+        let mut keys = Vec::new();
+
+        while cursor.next().await.unwrap() {
+            keys.push(cursor.key::<(String, String)>().unwrap());
+        }
+
+        assert_eq!(
+            keys,
+            vec![
+                ("Doe".to_owned(), "Jane".to_owned()),
+                ("Doe".to_owned(), "John".to_owned()),
+                ("Roe".to_owned(), "Ann".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    ops::{Bound, Range, RangeFrom, RangeInclusive, RangeTo, RangeToInclusive},
+};
+
+use serde::Serialize;
+#[cfg(not(target_arch = "wasm32"))]
+use serde_json::Value;
+use wasm_bindgen::{JsCast, JsValue};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::codec::serialize_key;
+
+/// A range of keys that restricts which entries a query returns.
+///
+/// Key ranges can be created using the constructors of this type or converted
+/// from the Rust range types. Both fail if a key can't be serialized:
+///
+/// ```
+/// use indexeddb::KeyRange;
+/// use std::convert::TryFrom;
+///
+/// let range = KeyRange::bound(&"a", &"c", false, true).unwrap();
+/// let same = KeyRange::try_from("a".."c").unwrap();
+///
+/// assert_eq!(range, same);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    pub(crate) lower: Bound<BoundKey>,
+    pub(crate) upper: Bound<BoundKey>,
+}
+
+/// A key of a range bound, serialized once when the range is created. Wasm
+/// builds serialize it like the keys of requests, native builds into the JSON
+/// value the in-memory emulation orders.
+#[cfg(target_arch = "wasm32")]
+pub(crate) type BoundKey = JsValue;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type BoundKey = Value;
+
+/// Error returned when a key of a [`KeyRange`] can't be serialized.
+///
+/// The error converts into the errors of both backends, so it can be
+/// propagated with `?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidKey {
+    message: String,
+}
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid key: {}", self.message)
+    }
+}
+
+impl std::error::Error for InvalidKey {}
+
+impl From<InvalidKey> for JsValue {
+    fn from(error: InvalidKey) -> Self {
+        error.to_string().into()
+    }
+}
+
+fn serialize<K: Serialize + ?Sized>(key: &K) -> Result<BoundKey, InvalidKey> {
+    #[cfg(target_arch = "wasm32")]
+    let key = serde_wasm_bindgen::to_value(key);
+    #[cfg(not(target_arch = "wasm32"))]
+    let key = serde_json::to_value(key);
+
+    key.map_err(|e| InvalidKey {
+        message: e.to_string(),
+    })
+}
+
+fn bound<K: Serialize + ?Sized>(key: &K, open: bool) -> Result<Bound<BoundKey>, InvalidKey> {
+    let key = serialize(key)?;
+
+    Ok(if open {
+        Bound::Excluded(key)
+    } else {
+        Bound::Included(key)
+    })
+}
+
+impl KeyRange {
+    /// A range containing all keys.
+    pub fn all() -> Self {
+        Self {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    /// A range containing only the given key.
+    pub fn only<K: Serialize + ?Sized>(key: &K) -> Result<Self, InvalidKey> {
+        let key = serialize(key)?;
+
+        Ok(Self {
+            lower: Bound::Included(key.clone()),
+            upper: Bound::Included(key),
+        })
+    }
+
+    /// A range containing all keys bigger than the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The lower bound of the range.
+    ///
+    /// * `open` - Should the lower bound itself be excluded from the range.
+    pub fn lower_bound<K: Serialize + ?Sized>(key: &K, open: bool) -> Result<Self, InvalidKey> {
+        Ok(Self {
+            lower: bound(key, open)?,
+            upper: Bound::Unbounded,
+        })
+    }
+
+    /// A range containing all keys smaller than the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The upper bound of the range.
+    ///
+    /// * `open` - Should the upper bound itself be excluded from the range.
+    pub fn upper_bound<K: Serialize + ?Sized>(key: &K, open: bool) -> Result<Self, InvalidKey> {
+        Ok(Self {
+            lower: Bound::Unbounded,
+            upper: bound(key, open)?,
+        })
+    }
+
+    /// A range containing all keys between the given keys.
+    pub fn bound<K: Serialize + ?Sized>(
+        lower: &K,
+        upper: &K,
+        lower_open: bool,
+        upper_open: bool,
+    ) -> Result<Self, InvalidKey> {
+        Ok(Self {
+            lower: bound(lower, lower_open)?,
+            upper: bound(upper, upper_open)?,
+        })
+    }
+}
+
+impl KeyRange {
+    /// Convert the range into the query argument of an IndexedDB request,
+    /// `undefined` if the range contains all keys.
+    pub(crate) fn to_js(&self) -> Result<JsValue, JsValue> {
+        let range = match (&self.lower, &self.upper) {
+            (Bound::Unbounded, Bound::Unbounded) => return Ok(JsValue::UNDEFINED),
+            (Bound::Included(lower), Bound::Included(upper)) if lower == upper => {
+                web_sys::IdbKeyRange::only(&key_to_js(lower)?)?
+            }
+            (lower, Bound::Unbounded) => {
+                let (key, open) = bound_to_js(lower)?;
+                web_sys::IdbKeyRange::lower_bound_with_open(&key, open)?
+            }
+            (Bound::Unbounded, upper) => {
+                let (key, open) = bound_to_js(upper)?;
+                web_sys::IdbKeyRange::upper_bound_with_open(&key, open)?
+            }
+            (lower, upper) => {
+                let (lower, lower_open) = bound_to_js(lower)?;
+                let (upper, upper_open) = bound_to_js(upper)?;
+                web_sys::IdbKeyRange::bound_with_lower_open_and_upper_open(
+                    &lower, &upper, lower_open, upper_open,
+                )?
+            }
+        };
+
+        Ok(range.into())
+    }
+
+    /// Does the range contain the given key.
+    pub(crate) fn includes_js(&self, key: &JsValue) -> Result<bool, JsValue> {
+        let range = self.to_js()?;
+
+        if range.is_undefined() {
+            Ok(true)
+        } else {
+            range.unchecked_into::<web_sys::IdbKeyRange>().includes(key)
+        }
+    }
+}
+
+/// Convert a range bound into a key and a flag telling if the bound is open.
+fn bound_to_js(bound: &Bound<BoundKey>) -> Result<(JsValue, bool), JsValue> {
+    match bound {
+        Bound::Included(key) => Ok((key_to_js(key)?, false)),
+        Bound::Excluded(key) => Ok((key_to_js(key)?, true)),
+        Bound::Unbounded => unreachable!("unbounded ranges don't need a key"),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn key_to_js(key: &JsValue) -> Result<JsValue, JsValue> {
+    Ok(key.clone())
+}
+
+/// The browser types only run in wasm builds, on native targets the keys
+/// are JSON values.
+#[cfg(not(target_arch = "wasm32"))]
+fn key_to_js(key: &Value) -> Result<JsValue, JsValue> {
+    serialize_key(key)
+}
+
+/// Convert an optional key range into the query argument of a request.
+pub(crate) fn query(range: Option<&KeyRange>) -> Result<JsValue, JsValue> {
+    range.map_or(Ok(JsValue::UNDEFINED), KeyRange::to_js)
+}
+
+impl<K: Serialize> TryFrom<Range<K>> for KeyRange {
+    type Error = InvalidKey;
+
+    fn try_from(range: Range<K>) -> Result<Self, Self::Error> {
+        Self::bound(&range.start, &range.end, false, true)
+    }
+}
+
+impl<K: Serialize> TryFrom<RangeInclusive<K>> for KeyRange {
+    type Error = InvalidKey;
+
+    fn try_from(range: RangeInclusive<K>) -> Result<Self, Self::Error> {
+        Self::bound(range.start(), range.end(), false, false)
+    }
+}
+
+impl<K: Serialize> TryFrom<RangeFrom<K>> for KeyRange {
+    type Error = InvalidKey;
+
+    fn try_from(range: RangeFrom<K>) -> Result<Self, Self::Error> {
+        Self::lower_bound(&range.start, false)
+    }
+}
+
+impl<K: Serialize> TryFrom<RangeTo<K>> for KeyRange {
+    type Error = InvalidKey;
+
+    fn try_from(range: RangeTo<K>) -> Result<Self, Self::Error> {
+        Self::upper_bound(&range.end, true)
+    }
+}
+
+impl<K: Serialize> TryFrom<RangeToInclusive<K>> for KeyRange {
+    type Error = InvalidKey;
+
+    fn try_from(range: RangeToInclusive<K>) -> Result<Self, Self::Error> {
+        Self::upper_bound(&range.end, false)
+    }
+}
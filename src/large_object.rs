@@ -0,0 +1,536 @@
+//! Storage for binary objects that are too large to be stored as a single
+//! value.
+//!
+//! A large object is split into fixed size chunks, every chunk is stored as a
+//! separate record in its own transaction. A manifest describing the chunks
+//! is written once all the chunks are stored, readers never see partially
+//! written objects.
+//!
+//! Every write reserves a new generation for its chunks before it starts, so
+//! concurrent writes of the same object don't overwrite each other's chunks,
+//! the write that finishes last wins. A write that is interrupted, e.g.
+//! because the tab was closed, leaves its chunks behind. They are removed
+//! when the object is deleted.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use indexeddb::{IndexedDb, large_object::LargeObjectStore};
+//! # use futures::{executor::block_on, stream, StreamExt};
+//! # block_on(async {
+//! let db = IndexedDb::open("media", 1, |_, db| {
+//!     db.create_object_store("attachments").unwrap();
+//! }).await .expect("Failed to open indexed DB");
+//!
+//! let store = LargeObjectStore::new(&db, "attachments");
+//!
+//! let data = stream::iter(vec![vec![0u8; 1024], vec![1u8; 1024]]);
+//! store.write("attachment", data).await.unwrap();
+//!
+//! let mut chunks = store.read("attachment");
+//!
+//! while let Some(chunk) = chunks.next().await {
+//!     let chunk = chunk.unwrap();
+//! }
+//! # });
+//! ```
+
+use std::{future::Future, io};
+
+use bytes::Bytes;
+use futures::{
+    io::AsyncRead,
+    stream::{self, LocalBoxStream, Stream, StreamExt, TryStreamExt},
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    codec::{serialize_key, Raw},
+    object_store::bytes_from_js,
+    IndexedDb, ReadWrite, TransactionObjectStore,
+};
+
+/// The default size of a chunk, 1 MiB.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Description of a stored large object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The size of the object in bytes.
+    pub size: u64,
+    /// The size of a single chunk, the last chunk may be smaller.
+    pub chunk_size: u64,
+    /// The number of chunks the object was split into.
+    pub chunks: u32,
+    /// Generation of the object, every write uses a new generation so the
+    /// chunks of concurrent writes and of different versions don't collide.
+    generation: u32,
+}
+
+/// The writes of an object that are in progress, stored under the key
+/// `[key]`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Writes {
+    /// The generation the next write will use.
+    next_generation: u32,
+    /// The generations that are being written.
+    pending: Vec<u32>,
+}
+
+impl Manifest {
+    fn chunk_key(&self, key: &str, index: u32) -> (String, u32, u32) {
+        chunk_key(key, self.generation, index)
+    }
+}
+
+fn chunk_key(key: &str, generation: u32, index: u32) -> (String, u32, u32) {
+    (key.to_owned(), generation, index)
+}
+
+fn writes_key(key: &str) -> (String,) {
+    (key.to_owned(),)
+}
+
+/// Delete the chunks of the given generations of an object, or the chunks of
+/// all its generations if none are given.
+fn delete_chunks(
+    store: &TransactionObjectStore<'_, ReadWrite>,
+    key: &str,
+    generation: Option<u32>,
+) -> Result<(), JsValue> {
+    let (lower, upper) = match generation {
+        Some(generation) => (generation, generation),
+        None => (0, u32::MAX),
+    };
+
+    let range = web_sys::IdbKeyRange::bound(
+        &serialize_key(&chunk_key(key, lower, 0))?,
+        &serialize_key(&chunk_key(key, upper, u32::MAX))?,
+    )?;
+
+    // Failed requests abort the transaction, `done` reports them.
+    store.as_raw().delete(&range)?;
+    Ok(())
+}
+
+/// A store for large binary objects.
+///
+/// The object store that is used needs to store keys *out-of-tree*. Manifests
+/// are stored under the key of the object, chunks and the writes in progress
+/// under array keys containing the object key.
+#[derive(Debug, Clone)]
+pub struct LargeObjectStore {
+    db: IndexedDb,
+    store: String,
+    chunk_size: usize,
+}
+
+impl LargeObjectStore {
+    /// Create a new large object store using the object store with the given
+    /// name.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database containing the object store.
+    ///
+    /// * `store` - The name of the object store where the objects will be
+    ///   saved.
+    pub fn new(db: &IndexedDb, store: &str) -> Self {
+        Self {
+            db: db.clone(),
+            store: store.to_owned(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Set the size of the chunks new objects will be split into.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the given `chunk_size` is 0.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "the chunk size must be >= 1");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Get the manifest of the object with the given key.
+    pub async fn manifest(&self, key: &str) -> Result<Option<Manifest>, JsValue> {
+        let transaction = self.db.readonly_transaction();
+        let store = transaction.object_store(&self.store)?;
+
+        store.get(&key).await
+    }
+
+    /// Store the bytes of the given stream under the given key, replacing any
+    /// existing object.
+    ///
+    /// Only a single chunk of the object is held in wasm memory at a time.
+    /// Fails if the object is deleted before the write is done.
+    pub async fn write<S, B>(&self, key: &str, data: S) -> Result<Manifest, JsValue>
+    where
+        S: Stream<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        self.write_with(key, |generation| {
+            self.write_stream_chunks(key, generation, data)
+        })
+        .await
+    }
+
+    /// Store the given `Blob` under the given key, replacing any existing
+    /// object.
+    ///
+    /// The chunks are stored as slices of the blob, the content of the blob is
+    /// never copied into wasm memory.
+    pub async fn write_blob(&self, key: &str, blob: &web_sys::Blob) -> Result<Manifest, JsValue> {
+        self.write_with(key, |generation| {
+            self.write_blob_chunks(key, generation, blob)
+        })
+        .await
+    }
+
+    async fn write_stream_chunks<S, B>(
+        &self,
+        key: &str,
+        generation: u32,
+        data: S,
+    ) -> Result<Manifest, JsValue>
+    where
+        S: Stream<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let mut data = Box::pin(data);
+        let mut buffer = Vec::with_capacity(self.chunk_size);
+        let mut size = 0;
+        let mut chunks = 0;
+
+        while let Some(bytes) = data.next().await {
+            let mut bytes = bytes.as_ref();
+
+            while !bytes.is_empty() {
+                let len = (self.chunk_size - buffer.len()).min(bytes.len());
+                buffer.extend_from_slice(&bytes[..len]);
+                bytes = &bytes[len..];
+
+                if buffer.len() == self.chunk_size {
+                    let chunk = js_sys::Uint8Array::from(buffer.as_slice());
+                    self.write_chunk(chunk_key(key, generation, chunks), &chunk)
+                        .await?;
+
+                    size += buffer.len() as u64;
+                    chunks += 1;
+                    buffer.clear();
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            let chunk = js_sys::Uint8Array::from(buffer.as_slice());
+            self.write_chunk(chunk_key(key, generation, chunks), &chunk)
+                .await?;
+
+            size += buffer.len() as u64;
+            chunks += 1;
+        }
+
+        Ok(Manifest {
+            size,
+            chunk_size: self.chunk_size as u64,
+            chunks,
+            generation,
+        })
+    }
+
+    async fn write_blob_chunks(
+        &self,
+        key: &str,
+        generation: u32,
+        blob: &web_sys::Blob,
+    ) -> Result<Manifest, JsValue> {
+        let size = blob.size() as u64;
+        let chunk_size = self.chunk_size as u64;
+        let mut chunks = 0;
+        let mut start = 0;
+
+        while start < size {
+            let end = (start + chunk_size).min(size);
+            let chunk = blob.slice_with_f64_and_f64(start as f64, end as f64)?;
+            self.write_chunk(chunk_key(key, generation, chunks), &chunk)
+                .await?;
+
+            chunks += 1;
+            start = end;
+        }
+
+        Ok(Manifest {
+            size,
+            chunk_size,
+            chunks,
+            generation,
+        })
+    }
+
+    /// Reserve a generation, write the chunks using `write_chunks` and commit
+    /// the manifest. The chunks are deleted again if anything fails.
+    async fn write_with<F, Fut>(&self, key: &str, write_chunks: F) -> Result<Manifest, JsValue>
+    where
+        F: FnOnce(u32) -> Fut,
+        Fut: Future<Output = Result<Manifest, JsValue>>,
+    {
+        let generation = self.reserve(key).await?;
+
+        let result = match write_chunks(generation).await {
+            Ok(manifest) => self.commit(key, &manifest).await.map(|()| manifest),
+            Err(e) => Err(e),
+        };
+
+        if result.is_err() {
+            // The error of the write is more interesting than the one of the
+            // clean up, the chunks of a failed clean up are removed when the
+            // object is deleted.
+            let _ = self.cancel(key, generation).await;
+        }
+
+        result
+    }
+
+    /// Read the object with the given key as a stream of chunks.
+    ///
+    /// Every chunk is read in its own transaction. The stream yields an error
+    /// if the object doesn't exist or was replaced while it was read.
+    pub fn read(&self, key: &str) -> LocalBoxStream<'static, Result<Bytes, JsValue>> {
+        let this = self.clone();
+        let key = key.to_owned();
+
+        stream::once(async move {
+            let manifest = this
+                .manifest(&key)
+                .await?
+                .ok_or_else(|| JsValue::from(format!("no object stored under \"{}\"", key)))?;
+
+            let chunks = stream::iter(0..manifest.chunks).then(move |index| {
+                let this = this.clone();
+                let chunk_key = manifest.chunk_key(&key, index);
+
+                async move { this.read_chunk(chunk_key).await }
+            });
+
+            Ok::<_, JsValue>(chunks)
+        })
+        .try_flatten()
+        .boxed_local()
+    }
+
+    /// Read the object with the given key using an `AsyncRead`.
+    ///
+    /// See [`LargeObjectStore::read`].
+    pub fn reader(&self, key: &str) -> impl AsyncRead + Unpin {
+        self.read(key)
+            .map_err(|e| io::Error::other(format!("{:?}", e)))
+            .into_async_read()
+    }
+
+    /// Delete the object with the given key.
+    ///
+    /// All the chunks of the object are deleted, including the ones left
+    /// behind by interrupted writes. Writes of the object that are still in
+    /// progress fail.
+    ///
+    /// Deleting an object that doesn't exist isn't an error.
+    pub async fn delete(&self, key: &str) -> Result<(), JsValue> {
+        let transaction = self.db.readwrite_transaction();
+        let store = transaction.object_store(&self.store)?;
+
+        store.delete(&key).await?;
+        delete_chunks(&store, key, None)?;
+
+        // Writes in progress notice that they were cancelled, the next
+        // generation is kept so they can't collide with new writes.
+        if let Some(writes) = store.get::<Writes>(&writes_key(key)).await? {
+            if writes.pending.is_empty() {
+                store.delete(&writes_key(key)).await?;
+            } else {
+                let writes = Writes {
+                    pending: Vec::new(),
+                    ..writes
+                };
+                store.put(&writes_key(key), &writes).await?;
+            }
+        }
+
+        transaction.done().await
+    }
+
+    async fn write_chunk(
+        &self,
+        key: (String, u32, u32),
+        chunk: &impl JsCast,
+    ) -> Result<(), JsValue> {
+        let transaction = self.db.readwrite_transaction();
+        let store = transaction.object_store_with_codec::<Raw>(&self.store)?;
+
+        store.put(&key, chunk).await?;
+        transaction.done().await
+    }
+
+    async fn read_chunk(&self, key: (String, u32, u32)) -> Result<Bytes, JsValue> {
+        let transaction = self.db.readonly_transaction();
+        let store = transaction.object_store(&self.store)?;
+
+        let chunk = store
+            .get_raw(&key)
+            .await?
+            .ok_or_else(|| JsValue::from("chunk of the object is missing"))?;
+
+        let chunk = match chunk.dyn_into::<web_sys::Blob>() {
+            Ok(blob) => bytes_from_js(JsFuture::from(blob.array_buffer()).await?)?,
+            Err(chunk) => bytes_from_js(chunk)?,
+        };
+
+        Ok(chunk.to_vec().into())
+    }
+
+    /// Reserve the generation of a new write.
+    ///
+    /// The reservation is made in a read/write transaction, concurrent writes
+    /// always get different generations.
+    async fn reserve(&self, key: &str) -> Result<u32, JsValue> {
+        let transaction = self.db.readwrite_transaction();
+        let store = transaction.object_store(&self.store)?;
+
+        let manifest: Option<Manifest> = store.get(&key).await?;
+        let mut writes: Writes = store.get(&writes_key(key)).await?.unwrap_or_default();
+
+        let generation = manifest
+            .map(|m| m.generation.wrapping_add(1))
+            .unwrap_or(0)
+            .max(writes.next_generation);
+
+        writes.next_generation = generation.wrapping_add(1);
+        writes.pending.push(generation);
+        store.put(&writes_key(key), &writes).await?;
+
+        transaction.done().await?;
+        Ok(generation)
+    }
+
+    /// Store the manifest of a fully written object and delete the chunks of
+    /// the object it replaces.
+    ///
+    /// Fails if the object was deleted while its chunks were written.
+    async fn commit(&self, key: &str, manifest: &Manifest) -> Result<(), JsValue> {
+        let transaction = self.db.readwrite_transaction();
+        let store = transaction.object_store(&self.store)?;
+
+        let mut writes: Writes = store.get(&writes_key(key)).await?.unwrap_or_default();
+        let position = writes
+            .pending
+            .iter()
+            .position(|generation| *generation == manifest.generation)
+            .ok_or_else(|| JsValue::from("the object was deleted while it was written"))?;
+
+        writes.pending.remove(position);
+        store.put(&writes_key(key), &writes).await?;
+
+        if let Some(previous) = store.get::<Manifest>(&key).await? {
+            delete_chunks(&store, key, Some(previous.generation))?;
+        }
+
+        store.put(&key, manifest).await?;
+
+        transaction.done().await
+    }
+
+    /// Delete the chunks of a failed write and release its generation.
+    async fn cancel(&self, key: &str, generation: u32) -> Result<(), JsValue> {
+        let transaction = self.db.readwrite_transaction();
+        let store = transaction.object_store(&self.store)?;
+
+        delete_chunks(&store, key, Some(generation))?;
+
+        if let Some(mut writes) = store.get::<Writes>(&writes_key(key)).await? {
+            writes.pending.retain(|pending| *pending != generation);
+            store.put(&writes_key(key), &writes).await?;
+        }
+
+        transaction.done().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LargeObjectStore;
+    use crate::IndexedDb;
+    use futures::{future, io::AsyncReadExt, stream, TryStreamExt};
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn chunked_round_trip() {
+        let db = IndexedDb::open("large_object", 1, |_, db| {
+            db.create_object_store("objects").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let store = LargeObjectStore::new(&db, "objects").with_chunk_size(4);
+        let data: Vec<u8> = (0..10).collect();
+
+        let manifest = store
+            .write("object", stream::iter(vec![&data[..3], &data[3..]]))
+            .await
+            .unwrap();
+        assert_eq!(manifest.size, 10);
+        assert_eq!(manifest.chunks, 3);
+
+        let chunks: Vec<_> = store.read("object").try_collect().await.unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), data);
+
+        let manifest = store
+            .write("object", stream::iter(vec![&data[..2]]))
+            .await
+            .unwrap();
+        assert_eq!(manifest.chunks, 1);
+
+        let mut read = Vec::new();
+        store.reader("object").read_to_end(&mut read).await.unwrap();
+        assert_eq!(read, &data[..2]);
+
+        store.delete("object").await.unwrap();
+        assert!(store.manifest("object").await.unwrap().is_none());
+    }
+
+    #[wasm_bindgen_test]
+    async fn concurrent_writes() {
+        let db = IndexedDb::open("large_object_concurrent", 1, |_, db| {
+            db.create_object_store("objects").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let store = LargeObjectStore::new(&db, "objects").with_chunk_size(2);
+        store.delete("object").await.unwrap();
+
+        let (first, second) = future::join(
+            store.write("object", stream::iter(vec![[1u8; 6]])),
+            store.write("object", stream::iter(vec![[2u8; 6]])),
+        )
+        .await;
+        assert_ne!(first.unwrap(), second.unwrap());
+
+        // The chunks of the write that finished first were replaced as a
+        // whole.
+        let chunks: Vec<_> = store.read("object").try_collect().await.unwrap();
+        let data = chunks.concat();
+        assert!(data == [1; 6] || data == [2; 6]);
+
+        store.delete("object").await.unwrap();
+
+        let transaction = db.readonly_transaction();
+        let objects = transaction.object_store("objects").unwrap();
+        assert_eq!(objects.count(None).await.unwrap(), 0);
+    }
+}
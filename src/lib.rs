@@ -4,8 +4,17 @@
 //! most modern Web browsers support.
 //!
 //! This crate wraps the low level web-sys bindings for IndexedDB converting the
-//! API to a Rust Future based API. The crate will not work outside of a
-//! browser.
+//! API to a Rust Future based API. [`IndexedDb`] and the types it hands out
+//! only work in a browser, they can be used from the main thread as well as
+//! from dedicated, shared and service workers.
+//!
+//! The [`memory`] module is a separate implementation of IndexedDB that works
+//! outside of a browser, it doesn't share any code with the browser types and
+//! isn't compiled for wasm targets.
+//! Both implement the traits of the [`storage`] module, storage code that is
+//! written against these traits runs in a browser and in a plain `cargo test`.
+//! Native tests only exercise the memory backend, the browser types are
+//! tested with `wasm-bindgen-test`.
 //!
 //! # Example
 //!
@@ -52,13 +61,55 @@
 #[macro_use]
 mod macros;
 
+mod change_feed;
+pub mod codec;
+mod cursor;
 mod db;
+mod error;
+mod export;
+mod factory;
+pub mod fault;
+mod index;
+mod key_range;
+pub mod large_object;
+mod live_query;
+mod locks;
+pub mod lru;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod memory;
+mod migrate;
 mod object_store;
+mod pool;
+mod quota;
 mod request;
+pub mod storage;
 mod transaction;
+pub mod ttl;
+
+// The unit tests run in a browser window, building them with
+// `RUSTFLAGS="--cfg worker_tests"` runs them in a dedicated worker instead.
+#[cfg(all(test, not(worker_tests)))]
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+#[cfg(all(test, worker_tests))]
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_dedicated_worker);
 
 pub use crate::{
+    change_feed::{Change, ChangeEvent, ChangeKind, ChangeStream},
+    cursor::Cursor,
     db::{DbDuringUpgrade, IndexedDb},
-    object_store::{ObjectStore, ObjectStoreDuringUpgrade, TransactionObjectStore},
-    transaction::{Transaction, TransactionMode},
+    error::ErrorKind,
+    export::ExportFormat,
+    factory::Factory,
+    index::Index,
+    key_range::{InvalidKey, KeyRange},
+    live_query::{LiveQuery, LiveQueryStream},
+    locks::{LockMode, Locks},
+    object_store::{KeyPath, ObjectStore, ObjectStoreDuringUpgrade, TransactionObjectStore},
+    pool::ConnectionPool,
+    quota::StorageEstimate,
+    request::{PendingRequest, PendingRequestFuture},
+    transaction::{
+        Dynamic, Mode, ReadOnly, ReadWrite, StaticMode, Transaction, TransactionMode,
+        VersionChange, WriteMode,
+    },
 };
@@ -0,0 +1,225 @@
+use std::{fmt, marker::PhantomData, pin::Pin};
+
+use futures::{
+    stream::{self, LocalBoxStream},
+    task::{Context, Poll},
+    FutureExt, Stream, StreamExt,
+};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    change_feed::{ChangeEvent, ChangeKind, ChangeStream},
+    codec::{Decode, Json},
+    db::IndexedDb,
+    key_range::KeyRange,
+    transaction::TransactionMode,
+};
+
+/// A query of the values of an object store or index that is watched for
+/// changes, see [`IndexedDb::live_query`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveQuery {
+    store: String,
+    index: Option<String>,
+    range: Option<KeyRange>,
+}
+
+impl LiveQuery {
+    /// Query all the values of the object store with the given name.
+    pub fn store(name: &str) -> Self {
+        Self {
+            store: name.to_owned(),
+            index: None,
+            range: None,
+        }
+    }
+
+    /// Query the values using the index with the given name of the object
+    /// store, the range of the query applies to the index keys.
+    ///
+    /// Writes don't tell which index keys they touched, the query of an index
+    /// is run again after every write to the object store.
+    pub fn index(mut self, name: &str) -> Self {
+        self.index = Some(name.to_owned());
+        self
+    }
+
+    /// Only query the values with keys inside of the given range.
+    pub fn range(mut self, range: KeyRange) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Could the query return different values after the given writes.
+    fn is_affected_by(&self, event: &ChangeEvent) -> bool {
+        event
+            .changes
+            .iter()
+            .filter(|change| change.store() == self.store)
+            .any(|change| match (&self.index, &self.range, change.kind()) {
+                (Some(_), _, _) | (None, None, _) | (_, _, ChangeKind::Clear) => true,
+                (None, Some(range), _) => change
+                    .keys_raw()
+                    .iter()
+                    // Re-run the query if the key can't be compared.
+                    .any(|key| range.includes_js(key).unwrap_or(true)),
+            })
+    }
+
+    async fn run<V>(&self, db: &IndexedDb) -> Result<Vec<V>, JsValue>
+    where
+        Json: Decode<V>,
+    {
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store(&self.store)?;
+
+        match &self.index {
+            Some(index) => store.index(index)?.get_all(self.range.as_ref()).await,
+            None => store.get_all(self.range.as_ref()).await,
+        }
+    }
+}
+
+/// A stream of the results of a [`LiveQuery`].
+///
+/// The first item is the current result of the query, a new item is emitted
+/// every time a committed write could have changed the result. Writes that
+/// happen while the query runs are coalesced into a single new result.
+pub struct LiveQueryStream<V> {
+    inner: LocalBoxStream<'static, Result<Vec<V>, JsValue>>,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V> Stream for LiveQueryStream<V> {
+    type Item = Result<Vec<V>, JsValue>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl<V> fmt::Debug for LiveQueryStream<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LiveQueryStream").finish()
+    }
+}
+
+impl IndexedDb {
+    /// Watch the result of a query.
+    ///
+    /// The query is run again whenever a transaction that wrote to an
+    /// overlapping key range committed, in this or in another tab or worker.
+    /// Only writes made through handles with an enabled change feed are
+    /// noticed, see [`IndexedDb::with_change_feed`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, KeyRange, LiveQuery};
+    /// # use futures::{executor::block_on, StreamExt};
+    /// # use serde_json::Value;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("messages", 1, |_, db| {
+    /// #   let store = db.create_object_store("messages").unwrap();
+    /// #   store.create_index("by_unread", "unread", false).unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let unread = KeyRange::lower_bound(&1, false).unwrap();
+    /// let query = LiveQuery::store("messages").index("by_unread").range(unread);
+    /// let mut unread = db.live_query::<Value>(query).unwrap();
+    ///
+    /// while let Some(messages) = unread.next().await {
+    ///     let messages = messages.unwrap();
+    ///     // Update the UI.
+    /// }
+    /// # });
+    /// ```
+    pub fn live_query<V>(&self, query: LiveQuery) -> Result<LiveQueryStream<V>, JsValue>
+    where
+        Json: Decode<V>,
+        V: 'static,
+    {
+        // Subscribe before the first run, so no write is missed.
+        let changes = self.changes()?;
+
+        struct State {
+            db: IndexedDb,
+            query: LiveQuery,
+            changes: ChangeStream,
+            first: bool,
+        }
+
+        let state = State {
+            db: self.clone(),
+            query,
+            changes,
+            first: true,
+        };
+
+        let inner = stream::unfold(state, |mut state| async move {
+            if state.first {
+                state.first = false;
+            } else {
+                loop {
+                    let event = state.changes.next().await?;
+
+                    if state.query.is_affected_by(&event) {
+                        break;
+                    }
+                }
+
+                // Coalesce the writes that are already queued.
+                while let Some(Some(_)) = state.changes.next().now_or_never() {}
+            }
+
+            let result = state.query.run(&state.db).await;
+
+            Some((result, state))
+        })
+        .boxed_local();
+
+        Ok(LiveQueryStream {
+            inner,
+            value: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{IndexedDb, KeyRange, LiveQuery, TransactionMode};
+    use futures::StreamExt;
+    use std::convert::TryFrom;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn rerun_on_overlapping_writes() {
+        let db = IndexedDb::open("live_query", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB")
+        .with_change_feed()
+        .unwrap();
+
+        let mut values = db
+            .live_query::<u32>(
+                LiveQuery::store("test").range(KeyRange::try_from(10u32..20).unwrap()),
+            )
+            .unwrap();
+
+        assert_eq!(values.next().await.unwrap().unwrap(), Vec::<u32>::new());
+
+        // Outside of the range, doesn't re-run the query.
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.put(&1u32, &1u32).await.unwrap();
+        transaction.done().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.put(&15u32, &15u32).await.unwrap();
+        transaction.done().await.unwrap();
+
+        assert_eq!(values.next().await.unwrap().unwrap(), vec![15]);
+    }
+}
@@ -0,0 +1,600 @@
+use std::{collections::BTreeMap, future::Future};
+
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+    pin_mut,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{db::IndexedDb, transaction::TransactionMode};
+
+/// The name of the database that holds the leases of the fallback.
+const LEASE_DATABASE: &str = "indexeddb-locks";
+const LEASE_STORE: &str = "leases";
+
+/// How long a lease is valid without being renewed, in milliseconds.
+const LEASE_DURATION: f64 = 10_000.0;
+/// How long to wait before trying to get a lease again, in milliseconds.
+const LEASE_RETRY: i32 = 50;
+/// How often a held lease is renewed, in milliseconds.
+const LEASE_RENEWAL: i32 = (LEASE_DURATION / 3.0) as i32;
+/// How long to wait before retrying a failed renewal, in milliseconds.
+const LEASE_RENEWAL_RETRY: i32 = 500;
+
+/// The mode a lock is requested in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Only a single holder can hold the lock.
+    Exclusive,
+    /// Any number of holders can hold the lock, as long as nobody holds it
+    /// exclusively.
+    Shared,
+}
+
+impl LockMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            LockMode::Exclusive => "exclusive",
+            LockMode::Shared => "shared",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Backend {
+    WebLocks(JsValue),
+    Lease(IndexedDb),
+}
+
+/// Locks that are shared by all the tabs and workers of an origin.
+///
+/// Multi-step operations that span several transactions, like a migration
+/// or a sync with a server, can be serialized across tabs by running them
+/// while holding a lock. Locks are released when the operation finished or
+/// its future was dropped.
+///
+/// The locks use the Web Locks API. If the API isn't available, leases are
+/// stored in an IndexedDB database instead. Waiting for a lease polls the
+/// database and a lease expires if the tab holding it is closed without
+/// releasing it. A lease that expires while its operation runs, e.g. because
+/// the tab was suspended, doesn't cancel the operation, but `with_lock`
+/// returns an error once it finished since another tab might have run
+/// concurrently.
+#[derive(Debug, Clone)]
+pub struct Locks {
+    backend: Backend,
+}
+
+impl Locks {
+    /// Get the locks of the current global scope.
+    pub async fn new() -> Result<Self, JsValue> {
+        let manager = js_sys::Reflect::get(&js_sys::global(), &"navigator".into())
+            .and_then(|navigator| js_sys::Reflect::get(&navigator, &"locks".into()))
+            .unwrap_or(JsValue::UNDEFINED);
+
+        if manager.is_undefined() || manager.is_null() {
+            Self::with_leases().await
+        } else {
+            Ok(Self {
+                backend: Backend::WebLocks(manager),
+            })
+        }
+    }
+
+    /// Get locks that are always implemented using leases stored in
+    /// IndexedDB, even if the Web Locks API is available.
+    ///
+    /// Locks of the Web Locks API and leases don't exclude each other, all
+    /// the tabs need to use the same kind of locks.
+    pub async fn with_leases() -> Result<Self, JsValue> {
+        let db = IndexedDb::open(LEASE_DATABASE, 1, |_, db| {
+            db.create_object_store(LEASE_STORE)
+                .expect("Couldn't create the lease store");
+        })
+        .await?;
+
+        Ok(Self {
+            backend: Backend::Lease(db),
+        })
+    }
+
+    /// Run the given operation while holding the lock with the given name.
+    ///
+    /// Waits until the lock can be acquired.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the lock.
+    ///
+    /// * `mode` - The mode the lock is needed in.
+    ///
+    /// * `f` - The operation that is run while the lock is held.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{LockMode, Locks};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let locks = Locks::new().await.unwrap();
+    ///
+    /// locks.with_lock("sync", LockMode::Exclusive, || async {
+    ///     // Only a single tab syncs at a time.
+    /// }).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn with_lock<F, Fut>(
+        &self,
+        name: &str,
+        mode: LockMode,
+        f: F,
+    ) -> Result<Fut::Output, JsValue>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        let value = self.run(name, mode, false, f).await?;
+
+        Ok(value.expect("waiting for a lock always acquires it"))
+    }
+
+    /// Run the given operation while holding the lock with the given name,
+    /// if the lock is available right away.
+    ///
+    /// Returns `None` without running the operation if the lock is held in
+    /// a conflicting mode, see [`Locks::with_lock`].
+    pub async fn try_with_lock<F, Fut>(
+        &self,
+        name: &str,
+        mode: LockMode,
+        f: F,
+    ) -> Result<Option<Fut::Output>, JsValue>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        self.run(name, mode, true, f).await
+    }
+
+    async fn run<F, Fut>(
+        &self,
+        name: &str,
+        mode: LockMode,
+        if_available: bool,
+        f: F,
+    ) -> Result<Option<Fut::Output>, JsValue>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        match &self.backend {
+            Backend::WebLocks(manager) => web_lock(manager, name, mode, if_available, f).await,
+            Backend::Lease(db) => lease_lock(db, name, mode, if_available, f).await,
+        }
+    }
+}
+
+/// Resolves a promise when dropped.
+struct Release(js_sys::Function);
+
+impl Drop for Release {
+    fn drop(&mut self) {
+        let _ = self.0.call0(&JsValue::UNDEFINED);
+    }
+}
+
+/// Aborts a lock request when dropped, which has no effect once the lock was
+/// granted.
+struct AbortOnDrop(web_sys::AbortController);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn web_lock<F, Fut>(
+    manager: &JsValue,
+    name: &str,
+    mode: LockMode,
+    if_available: bool,
+    f: F,
+) -> Result<Option<Fut::Output>, JsValue>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future,
+{
+    // The lock is held until the promise returned by the callback settles.
+    let mut resolve = None;
+    let held = js_sys::Promise::new(&mut |resolve_held, _| resolve = Some(resolve_held));
+    let release = Release(resolve.expect("the promise executor is called right away"));
+
+    // The lock can be granted right before the future is dropped, the
+    // callback is called later on and has to outlive this function. It frees
+    // itself once it was called, the held promise is resolved by then.
+    let (granted, on_granted) = oneshot::channel();
+    let callback = Closure::once_into_js(move |lock: JsValue| {
+        let _ = granted.send(!lock.is_null());
+        JsValue::from(held)
+    });
+
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"mode".into(), &mode.as_str().into())?;
+    js_sys::Reflect::set(&options, &"ifAvailable".into(), &if_available.into())?;
+
+    // Stop waiting for the lock if the future is dropped, requests with
+    // `ifAvailable` don't wait and don't support a signal.
+    let _abort = if if_available {
+        None
+    } else {
+        let controller = web_sys::AbortController::new()?;
+        js_sys::Reflect::set(&options, &"signal".into(), &controller.signal())?;
+        Some(AbortOnDrop(controller))
+    };
+
+    let request: js_sys::Function = js_sys::Reflect::get(manager, &"request".into())?.dyn_into()?;
+    let request: js_sys::Promise = request
+        .call3(manager, &name.into(), &options, &callback)?
+        .dyn_into()?;
+    let request = JsFuture::from(request);
+    pin_mut!(request);
+
+    // The request fails without calling the callback e.g. for invalid names.
+    let granted = match future::select(on_granted, request.as_mut()).await {
+        Either::Left((granted, _)) => granted.unwrap_or(false),
+        Either::Right((result, _)) => {
+            result?;
+            false
+        }
+    };
+
+    if !granted {
+        drop(release);
+        request.await?;
+        return Ok(None);
+    }
+
+    let value = f().await;
+
+    drop(release);
+    request.await?;
+
+    Ok(Some(value))
+}
+
+/// The holders of a lease.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lease {
+    exclusive: Option<(String, f64)>,
+    shared: BTreeMap<String, f64>,
+}
+
+impl Lease {
+    fn prune(&mut self, now: f64) {
+        if self
+            .exclusive
+            .as_ref()
+            .is_some_and(|(_, expires)| *expires < now)
+        {
+            self.exclusive = None;
+        }
+
+        self.shared.retain(|_, expires| *expires >= now);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exclusive.is_none() && self.shared.is_empty()
+    }
+}
+
+/// Update the lease with the given name, `f` returns if the lease should be
+/// stored.
+async fn update_lease(
+    db: &IndexedDb,
+    name: &str,
+    f: impl FnOnce(&mut Lease) -> bool,
+) -> Result<bool, JsValue> {
+    let transaction = db.transaction(TransactionMode::ReadWrite);
+    let store = transaction.object_store(LEASE_STORE)?;
+
+    let mut lease: Lease = store.get(&name).await?.unwrap_or_default();
+    lease.prune(js_sys::Date::now());
+
+    let changed = f(&mut lease);
+
+    if changed {
+        if lease.is_empty() {
+            store.delete(&name).await?;
+        } else {
+            store.put(&name, &lease).await?;
+        }
+    }
+
+    transaction.done().await?;
+
+    Ok(changed)
+}
+
+async fn acquire_lease(
+    db: &IndexedDb,
+    name: &str,
+    owner: &str,
+    mode: LockMode,
+) -> Result<bool, JsValue> {
+    update_lease(db, name, |lease| {
+        let expires = js_sys::Date::now() + LEASE_DURATION;
+
+        match mode {
+            LockMode::Exclusive if lease.is_empty() => {
+                lease.exclusive = Some((owner.to_owned(), expires));
+                true
+            }
+            LockMode::Shared if lease.exclusive.is_none() => {
+                lease.shared.insert(owner.to_owned(), expires);
+                true
+            }
+            _ => false,
+        }
+    })
+    .await
+}
+
+async fn renew_lease(db: &IndexedDb, name: &str, owner: &str) -> Result<bool, JsValue> {
+    update_lease(db, name, |lease| {
+        let expires = js_sys::Date::now() + LEASE_DURATION;
+
+        match &mut lease.exclusive {
+            Some((holder, time)) if holder == owner => {
+                *time = expires;
+                true
+            }
+            _ => match lease.shared.get_mut(owner) {
+                Some(time) => {
+                    *time = expires;
+                    true
+                }
+                None => false,
+            },
+        }
+    })
+    .await
+}
+
+async fn release_lease(db: &IndexedDb, name: &str, owner: &str) -> Result<bool, JsValue> {
+    update_lease(db, name, |lease| {
+        if lease
+            .exclusive
+            .as_ref()
+            .is_some_and(|(holder, _)| holder == owner)
+        {
+            lease.exclusive = None;
+            true
+        } else {
+            lease.shared.remove(owner).is_some()
+        }
+    })
+    .await
+}
+
+/// Releases a lease when dropped, also if the operation was cancelled.
+struct LeaseGuard {
+    db: IndexedDb,
+    name: String,
+    owner: String,
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        let db = self.db.clone();
+        let name = std::mem::take(&mut self.name);
+        let owner = std::mem::take(&mut self.owner);
+
+        // The lease expires on its own if releasing it fails.
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = release_lease(&db, &name, &owner).await;
+        });
+    }
+}
+
+async fn lease_lock<F, Fut>(
+    db: &IndexedDb,
+    name: &str,
+    mode: LockMode,
+    if_available: bool,
+    f: F,
+) -> Result<Option<Fut::Output>, JsValue>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future,
+{
+    let owner = format!("{:x}", (js_sys::Math::random() * u64::MAX as f64) as u64);
+
+    while !acquire_lease(db, name, &owner, mode).await? {
+        if if_available {
+            return Ok(None);
+        }
+
+        sleep(LEASE_RETRY).await?;
+    }
+
+    let _guard = LeaseGuard {
+        db: db.clone(),
+        name: name.to_owned(),
+        owner: owner.clone(),
+    };
+
+    // Renew the lease while the operation runs, failed renewals are retried
+    // until the lease expires. Completes once the lease is lost.
+    let renew = async {
+        let mut expires = js_sys::Date::now() + LEASE_DURATION;
+        let mut delay = LEASE_RENEWAL;
+
+        loop {
+            if sleep(delay).await.is_err() {
+                break;
+            }
+
+            match renew_lease(db, name, &owner).await {
+                Ok(true) => {
+                    expires = js_sys::Date::now() + LEASE_DURATION;
+                    delay = LEASE_RENEWAL;
+                }
+                Ok(false) => break,
+                Err(_) if js_sys::Date::now() + f64::from(LEASE_RENEWAL_RETRY) < expires => {
+                    delay = LEASE_RENEWAL_RETRY;
+                }
+                Err(_) => break,
+            }
+        }
+    };
+
+    let operation = f();
+    pin_mut!(operation);
+    pin_mut!(renew);
+
+    match future::select(operation, renew).await {
+        Either::Left((value, _)) => Ok(Some(value)),
+        Either::Right(((), operation)) => {
+            // Cancelling the operation halfway would leave its work
+            // unfinished, it runs to completion without the lease.
+            operation.await;
+
+            Err("the lease expired while the lock was held".into())
+        }
+    }
+}
+
+/// Wait for the given number of milliseconds, in windows and workers.
+pub(crate) async fn sleep(ms: i32) -> Result<(), JsValue> {
+    let global = js_sys::global();
+    let set_timeout: js_sys::Function =
+        js_sys::Reflect::get(&global, &"setTimeout".into())?.dyn_into()?;
+
+    let mut result = Ok(JsValue::UNDEFINED);
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        result = set_timeout.call2(&global, &resolve, &ms.into());
+    });
+    result?;
+
+    JsFuture::from(promise).await.map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Backend, LockMode, Locks, LEASE_RENEWAL, LEASE_STORE};
+    use crate::TransactionMode;
+    use futures::{
+        future::{self, Either},
+        pin_mut,
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+    use wasm_bindgen_test::*;
+
+    async fn exercise(locks: Locks) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let run = |id: u32| {
+            let log = log.clone();
+            let locks = locks.clone();
+
+            async move {
+                locks
+                    .with_lock("exclusive", LockMode::Exclusive, || async {
+                        log.borrow_mut().push(id);
+                        super::sleep(20).await.unwrap();
+                        log.borrow_mut().push(id);
+                    })
+                    .await
+                    .unwrap()
+            }
+        };
+
+        futures::join!(run(1), run(2));
+
+        // The operations didn't interleave.
+        let log = log.borrow().clone();
+        assert!(log == vec![1, 1, 2, 2] || log == vec![2, 2, 1, 1]);
+
+        let nested = locks
+            .with_lock("held", LockMode::Exclusive, || async {
+                locks
+                    .try_with_lock("held", LockMode::Shared, || async {})
+                    .await
+                    .unwrap()
+            })
+            .await
+            .unwrap();
+        assert_eq!(nested, None);
+
+        let shared = locks
+            .with_lock("shared", LockMode::Shared, || async {
+                locks
+                    .try_with_lock("shared", LockMode::Shared, || async { 42 })
+                    .await
+                    .unwrap()
+            })
+            .await
+            .unwrap();
+        assert_eq!(shared, Some(42));
+    }
+
+    #[wasm_bindgen_test]
+    async fn web_locks() {
+        let locks = Locks::new().await.unwrap();
+        exercise(locks.clone()).await;
+
+        // An operation that stops waiting for the lock doesn't get it later.
+        locks
+            .with_lock("dropped", LockMode::Exclusive, || async {
+                let waiting = locks.with_lock("dropped", LockMode::Exclusive, || async {});
+                let timeout = super::sleep(20);
+                pin_mut!(waiting);
+                pin_mut!(timeout);
+
+                let result = future::select(waiting, timeout).await;
+                assert!(matches!(result, Either::Right(_)));
+            })
+            .await
+            .unwrap();
+
+        let available = locks
+            .try_with_lock("dropped", LockMode::Exclusive, || async {})
+            .await
+            .unwrap();
+        assert_eq!(available, Some(()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn leases() {
+        let locks = Locks::with_leases().await.unwrap();
+        exercise(locks.clone()).await;
+
+        let db = match &locks.backend {
+            Backend::Lease(db) => db.clone(),
+            Backend::WebLocks(_) => unreachable!(),
+        };
+
+        // Losing the lease doesn't cancel the operation, but is reported.
+        let finished = Cell::new(false);
+        let result = locks
+            .with_lock("lost", LockMode::Exclusive, || async {
+                let transaction = db.transaction(TransactionMode::ReadWrite);
+                let store = transaction.object_store(LEASE_STORE).unwrap();
+                store.delete(&"lost").await.unwrap();
+                transaction.done().await.unwrap();
+
+                super::sleep(LEASE_RENEWAL + 100).await.unwrap();
+                finished.set(true);
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert!(finished.get());
+    }
+}
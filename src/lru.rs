@@ -0,0 +1,473 @@
+//! A size-bounded cache that evicts the least recently used entries.
+//!
+//! Values are stored inside of an envelope that records when they were last
+//! accessed and how large they are. An index on the access time finds the
+//! entries to evict, it needs to be created during an upgrade using
+//! [`create_access_index`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use indexeddb::{IndexedDb, lru::{self, LruStore}};
+//! # use futures::executor::block_on;
+//! # block_on(async {
+//! let db = IndexedDb::open("thumbnails", 1, |_, db| {
+//!     let store = db.create_object_store("thumbnails").unwrap();
+//!     lru::create_access_index(&store).unwrap();
+//! }).await .expect("Failed to open indexed DB");
+//!
+//! let cache: LruStore = LruStore::new(&db, "thumbnails")
+//!     .with_max_entries(1000)
+//!     .with_max_bytes(50 * 1024 * 1024);
+//!
+//! cache.put(&"photo.jpg", &vec![0u8; 1024]).await.unwrap();
+//!
+//! let thumbnail: Option<Vec<u8>> = cache.get(&"photo.jpg").await.unwrap();
+//! # });
+//! ```
+
+use std::{cell::RefCell, marker::PhantomData};
+
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+use crate::{
+    codec::{serialize_key, Codec, Decode, Encode, Json},
+    db::IndexedDb,
+    object_store::ObjectStoreDuringUpgrade,
+    quota::approximate_size,
+    request::IndexedDbRequest,
+    transaction::TransactionMode,
+};
+
+/// The name of the index on the access time of the entries.
+pub const ACCESS_INDEX: &str = "lru_accessed";
+
+const ACCESSED: &str = "accessed";
+const SIZE: &str = "size";
+const VALUE: &str = "value";
+const TOTAL: &str = "total";
+
+/// The key of the record holding the total size of the entries, it isn't
+/// part of the access index.
+const META_KEY: (&str, &str) = ("indexeddb-lru", "meta");
+
+/// The number of entries that are looked up at once while evicting.
+const EVICT_BATCH_SIZE: u32 = 16;
+
+/// Create the index on the access time that the [`LruStore`] evicts entries
+/// with.
+pub fn create_access_index(store: &ObjectStoreDuringUpgrade<'_>) -> Result<(), JsValue> {
+    store.create_index(ACCESS_INDEX, ACCESSED, false)
+}
+
+/// When the access times of reads are written to the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteBack {
+    /// Every read updates the access time in its own read/write transaction.
+    Immediate,
+    /// Reads only use read only transactions, the access times are collected
+    /// and written once the given number of reads happened, by the next
+    /// [`LruStore::put`] or by [`LruStore::flush`].
+    Batched(usize),
+}
+
+fn property(value: &JsValue, name: &str) -> Result<JsValue, JsValue> {
+    js_sys::Reflect::get(value, &name.into())
+}
+
+fn number(value: &JsValue, name: &str) -> Result<f64, JsValue> {
+    Ok(property(value, name)?.as_f64().unwrap_or(0.0))
+}
+
+fn set(value: &JsValue, name: &str, property: &JsValue) -> Result<(), JsValue> {
+    js_sys::Reflect::set(value, &name.into(), property)?;
+    Ok(())
+}
+
+/// Get the envelope with the given key, keys are kept as JavaScript values
+/// so they don't need to be serialized again.
+async fn get(store: &web_sys::IdbObjectStore, key: &JsValue) -> Result<Option<JsValue>, JsValue> {
+    let envelope = IndexedDbRequest::new(store.get(key)?).await?;
+    Ok(Some(envelope).filter(|envelope| !envelope.is_undefined()))
+}
+
+async fn put(
+    store: &web_sys::IdbObjectStore,
+    key: &JsValue,
+    envelope: &JsValue,
+) -> Result<(), JsValue> {
+    IndexedDbRequest::new(store.put_with_key(envelope, key)?).await?;
+    Ok(())
+}
+
+/// A cache on top of an object store that is bounded by the number of its
+/// entries and/or their size in bytes.
+///
+/// Inserting an entry evicts the least recently used entries in the same
+/// transaction until the cache is within its bounds again, an entry that is
+/// bigger than the maximum size evicts everything including itself. Sizes are
+/// estimated from the stored JavaScript values, see
+/// [`ObjectStore::approximate_size`].
+///
+/// All the entries of the store need to be written through the `LruStore`.
+/// The store reserves the key `["indexeddb-lru", "meta"]` for the total size
+/// of the entries.
+///
+/// [`ObjectStore::approximate_size`]: crate::ObjectStore::approximate_size
+#[derive(Debug)]
+pub struct LruStore<C: Codec = Json> {
+    db: IndexedDb,
+    store: String,
+    max_entries: Option<u32>,
+    max_bytes: Option<u64>,
+    write_back: WriteBack,
+    // Keys and access times of reads that weren't written yet.
+    pending: RefCell<Vec<(JsValue, f64)>>,
+    codec: PhantomData<C>,
+}
+
+impl<C: Codec> LruStore<C> {
+    /// Create a cache using the object store with the given name.
+    ///
+    /// The cache is unbounded until a maximum number of entries or bytes is
+    /// set, access times are written immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database containing the object store.
+    ///
+    /// * `store` - The name of the object store where the entries will be
+    ///   saved.
+    pub fn new(db: &IndexedDb, store: &str) -> Self {
+        Self {
+            db: db.clone(),
+            store: store.to_owned(),
+            max_entries: None,
+            max_bytes: None,
+            write_back: WriteBack::Immediate,
+            pending: RefCell::new(Vec::new()),
+            codec: PhantomData,
+        }
+    }
+
+    /// Set the maximum number of entries.
+    pub fn with_max_entries(mut self, max_entries: u32) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Set the maximum total size of the entries in bytes.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set when the access times of reads are written.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the batch size of [`WriteBack::Batched`] is 0.
+    pub fn with_write_back(mut self, write_back: WriteBack) -> Self {
+        assert!(
+            write_back != WriteBack::Batched(0),
+            "the batch size must be >= 1"
+        );
+        self.write_back = write_back;
+        self
+    }
+
+    /// Get the value with the given key and mark it as recently used.
+    pub async fn get<V>(&self, key: &impl Serialize) -> Result<Option<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        let key = serialize_key(key)?;
+        let now = js_sys::Date::now();
+
+        let envelope = match self.write_back {
+            WriteBack::Immediate => {
+                let transaction = self.db.transaction(TransactionMode::ReadWrite);
+                let store = transaction.as_raw().object_store(&self.store)?;
+                let envelope = touch(&store, &key, now).await?;
+
+                transaction.done().await?;
+                envelope
+            }
+            WriteBack::Batched(batch_size) => {
+                let transaction = self.db.transaction(TransactionMode::Readonly);
+                let store = transaction.as_raw().object_store(&self.store)?;
+                let envelope = get(&store, &key).await?;
+                transaction.done().await?;
+
+                if envelope.is_some() {
+                    let pending = {
+                        let mut pending = self.pending.borrow_mut();
+                        pending.push((key, now));
+                        pending.len()
+                    };
+
+                    if pending >= batch_size {
+                        self.flush().await?;
+                    }
+                }
+
+                envelope
+            }
+        };
+
+        match envelope {
+            Some(envelope) => C::decode(property(&envelope, VALUE)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the given value under the given key, replacing any existing
+    /// value, and evict the least recently used entries if the cache grew
+    /// too large.
+    ///
+    /// Pending access times are written in the same transaction.
+    pub async fn put<V: ?Sized>(&self, key: &impl Serialize, value: &V) -> Result<(), JsValue>
+    where
+        C: Encode<V>,
+    {
+        let key = serialize_key(key)?;
+        let value = C::encode(value)?;
+        let now = js_sys::Date::now();
+
+        let transaction = self.db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.as_raw().object_store(&self.store)?;
+
+        self.write_pending(&store).await?;
+
+        let size = (approximate_size(&key) + approximate_size(&value)) as f64;
+        let previous = get(&store, &key).await?;
+        let mut total = total(&store).await? + size;
+
+        if let Some(previous) = previous {
+            total -= number(&previous, SIZE)?;
+        }
+
+        let envelope: JsValue = js_sys::Object::new().into();
+        set(&envelope, VALUE, &value)?;
+        set(&envelope, ACCESSED, &now.into())?;
+        set(&envelope, SIZE, &size.into())?;
+        put(&store, &key, &envelope).await?;
+
+        let total = self.evict(&store, total).await?;
+        set_total(&store, total).await?;
+
+        transaction.done().await
+    }
+
+    /// Delete the value with the given key.
+    pub async fn delete(&self, key: &impl Serialize) -> Result<(), JsValue> {
+        let key = serialize_key(key)?;
+
+        let transaction = self.db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.as_raw().object_store(&self.store)?;
+
+        if let Some(previous) = get(&store, &key).await? {
+            let total = total(&store).await? - number(&previous, SIZE)?;
+            IndexedDbRequest::new(store.delete(&key)?).await?;
+            set_total(&store, total).await?;
+        }
+
+        transaction.done().await
+    }
+
+    /// Delete all the entries.
+    pub async fn clear(&self) -> Result<(), JsValue> {
+        self.pending.borrow_mut().clear();
+
+        let transaction = self.db.transaction(TransactionMode::ReadWrite);
+        transaction
+            .object_store_with_codec::<C>(&self.store)?
+            .clear()
+            .await?;
+
+        transaction.done().await
+    }
+
+    /// The number of entries in the cache.
+    pub async fn len(&self) -> Result<u32, JsValue> {
+        let transaction = self.db.transaction(TransactionMode::Readonly);
+        let store = transaction.as_raw().object_store(&self.store)?;
+        let count = IndexedDbRequest::new(store.index(ACCESS_INDEX)?.count()?).await?;
+
+        Ok(count.as_f64().unwrap_or(0.0) as u32)
+    }
+
+    /// Does the cache have no entries?
+    pub async fn is_empty(&self) -> Result<bool, JsValue> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// The estimated size of all the entries in bytes.
+    pub async fn size(&self) -> Result<u64, JsValue> {
+        let transaction = self.db.transaction(TransactionMode::Readonly);
+        let store = transaction.as_raw().object_store(&self.store)?;
+
+        Ok(total(&store).await? as u64)
+    }
+
+    /// Write the collected access times of reads, see [`WriteBack::Batched`].
+    pub async fn flush(&self) -> Result<(), JsValue> {
+        if self.pending.borrow().is_empty() {
+            return Ok(());
+        }
+
+        let transaction = self.db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.as_raw().object_store(&self.store)?;
+        self.write_pending(&store).await?;
+
+        transaction.done().await
+    }
+
+    async fn write_pending(&self, store: &web_sys::IdbObjectStore) -> Result<(), JsValue> {
+        let pending = self.pending.take();
+
+        for (key, accessed) in pending {
+            touch(store, &key, accessed).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Evict the least recently used entries until the cache is within its
+    /// bounds, returns the new total size.
+    async fn evict(&self, store: &web_sys::IdbObjectStore, mut total: f64) -> Result<f64, JsValue> {
+        let index = store.index(ACCESS_INDEX)?;
+        let count = IndexedDbRequest::new(index.count()?).await?;
+        let mut count = count.as_f64().unwrap_or(0.0) as u32;
+
+        let too_large = |count: u32, total: f64| {
+            self.max_entries.is_some_and(|max| count > max)
+                || self.max_bytes.is_some_and(|max| total > max as f64)
+        };
+
+        // The index is ordered by the access time, the least recently used
+        // entries come first. Deleted entries are gone for the next batch.
+        while too_large(count, total) {
+            let keys =
+                index.get_all_keys_with_key_and_limit(&JsValue::UNDEFINED, EVICT_BATCH_SIZE)?;
+            let values = index.get_all_with_key_and_limit(&JsValue::UNDEFINED, EVICT_BATCH_SIZE)?;
+
+            let keys = js_sys::Array::from(&IndexedDbRequest::new(keys).await?);
+            let values = js_sys::Array::from(&IndexedDbRequest::new(values).await?);
+
+            if keys.length() == 0 {
+                break;
+            }
+
+            for (key, envelope) in keys.iter().zip(values.iter()) {
+                if !too_large(count, total) {
+                    break;
+                }
+
+                // Failed requests abort the transaction, `done` reports them.
+                store.delete(&key)?;
+                total -= number(&envelope, SIZE)?;
+                count -= 1;
+            }
+        }
+
+        Ok(total.max(0.0))
+    }
+}
+
+/// Update the access time of the entry with the given key, returns the
+/// envelope of the entry.
+async fn touch(
+    store: &web_sys::IdbObjectStore,
+    key: &JsValue,
+    accessed: f64,
+) -> Result<Option<JsValue>, JsValue> {
+    let envelope = match get(store, key).await? {
+        Some(envelope) => envelope,
+        None => return Ok(None),
+    };
+
+    if number(&envelope, ACCESSED)? < accessed {
+        set(&envelope, ACCESSED, &accessed.into())?;
+        put(store, key, &envelope).await?;
+    }
+
+    Ok(Some(envelope))
+}
+
+async fn total(store: &web_sys::IdbObjectStore) -> Result<f64, JsValue> {
+    match get(store, &serialize_key(&META_KEY)?).await? {
+        Some(meta) => number(&meta, TOTAL),
+        None => Ok(0.0),
+    }
+}
+
+async fn set_total(store: &web_sys::IdbObjectStore, total: f64) -> Result<(), JsValue> {
+    let meta: JsValue = js_sys::Object::new().into();
+    set(&meta, TOTAL, &total.into())?;
+
+    put(store, &serialize_key(&META_KEY)?, &meta).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{create_access_index, LruStore, WriteBack};
+    use crate::{locks::sleep, IndexedDb};
+    use wasm_bindgen_test::*;
+
+    async fn open(name: &str) -> IndexedDb {
+        IndexedDb::open(name, 1, |_, db| {
+            let store = db.create_object_store("cache").unwrap();
+            create_access_index(&store).unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB")
+    }
+
+    #[wasm_bindgen_test]
+    async fn evict_least_recently_used() {
+        let db = open("lru_entries").await;
+        let cache: LruStore = LruStore::new(&db, "cache").with_max_entries(2);
+        cache.clear().await.unwrap();
+
+        // Entries accessed in the same millisecond are evicted by key.
+        cache.put(&"a", &1).await.unwrap();
+        sleep(5).await.unwrap();
+        cache.put(&"b", &2).await.unwrap();
+        sleep(5).await.unwrap();
+        assert_eq!(cache.get::<u32>(&"a").await.unwrap(), Some(1));
+
+        cache.put(&"c", &3).await.unwrap();
+        assert_eq!(cache.len().await.unwrap(), 2);
+        assert_eq!(cache.get::<u32>(&"b").await.unwrap(), None);
+        assert_eq!(cache.get::<u32>(&"a").await.unwrap(), Some(1));
+
+        cache.delete(&"a").await.unwrap();
+        cache.delete(&"c").await.unwrap();
+        assert_eq!(cache.size().await.unwrap(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    async fn max_bytes_and_batched_write_back() {
+        let db = open("lru_bytes").await;
+        let cache: LruStore = LruStore::new(&db, "cache")
+            .with_max_bytes(60)
+            .with_write_back(WriteBack::Batched(10));
+        cache.clear().await.unwrap();
+
+        // Every entry takes up more than 20 bytes, only two of them fit.
+        cache.put(&"a", &"0123456789").await.unwrap();
+        sleep(5).await.unwrap();
+        cache.put(&"b", &"0123456789").await.unwrap();
+        sleep(5).await.unwrap();
+
+        // The pending access time of `a` is written by the next insert.
+        assert!(cache.get::<String>(&"a").await.unwrap().is_some());
+        cache.put(&"c", &"0123456789").await.unwrap();
+
+        assert_eq!(cache.len().await.unwrap(), 2);
+        assert!(cache.get::<String>(&"b").await.unwrap().is_none());
+        assert!(cache.size().await.unwrap() <= 60);
+    }
+}
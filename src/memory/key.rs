@@ -0,0 +1,196 @@
+use std::cmp::Ordering;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Error, ErrorKind};
+
+/// The largest integer that can be represented exactly by a `f64`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// A key of the in-memory backend.
+///
+/// Keys are ordered the same way IndexedDB orders them, numbers sort before
+/// dates, dates before strings, strings before binary keys and binary keys
+/// before arrays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Key {
+    /// A numeric key, `NaN` isn't a valid key.
+    Number(f64),
+    /// A date key, stored as milliseconds since the Unix epoch.
+    Date(f64),
+    /// A string key.
+    String(String),
+    /// A binary key.
+    Binary(Vec<u8>),
+    /// An array of keys.
+    Array(Vec<Key>),
+}
+
+impl Key {
+    /// The smallest possible key.
+    pub(crate) const MIN: Key = Key::Number(f64::NEG_INFINITY);
+
+    /// Convert a Rust value into a key.
+    ///
+    /// Numbers, strings and sequences of those are valid keys, any other
+    /// value fails with a `DataError`.
+    pub fn from_serialize<K: Serialize + ?Sized>(key: &K) -> Result<Self, Error> {
+        let value = serde_json::to_value(key)
+            .map_err(|e| Error::new(ErrorKind::Data, format!("invalid key: {}", e)))?;
+
+        Self::from_json(&value)
+    }
+
+    /// Convert the key back into a Rust value.
+    pub fn deserialize<K: DeserializeOwned>(&self) -> Result<K, Error> {
+        serde_json::from_value(self.to_json())
+            .map_err(|e| Error::new(ErrorKind::Data, format!("can't deserialize key: {}", e)))
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Number(n) => {
+                // Like in a browser, integers that a `f64` can't represent
+                // exactly are rejected instead of colliding with their
+                // neighbours.
+                let exact = match (n.as_u64(), n.as_i64()) {
+                    (Some(n), _) => n as f64 <= MAX_SAFE_INTEGER,
+                    (_, Some(n)) => (n as f64).abs() <= MAX_SAFE_INTEGER,
+                    _ => true,
+                };
+
+                match n.as_f64() {
+                    Some(_) if !exact => Err(Error::new(
+                        ErrorKind::Data,
+                        format!("the integer key {} can't be represented exactly", n),
+                    )),
+                    // Positive and negative zero are the same key.
+                    Some(0.0) => Ok(Key::Number(0.0)),
+                    Some(n) if !n.is_nan() => Ok(Key::Number(n)),
+                    _ => Err(Error::new(ErrorKind::Data, "invalid numeric key")),
+                }
+            }
+            Value::String(s) => Ok(Key::String(s.clone())),
+            Value::Array(a) => a
+                .iter()
+                .map(Self::from_json)
+                .collect::<Result<_, _>>()
+                .map(Key::Array),
+            _ => Err(Error::new(
+                ErrorKind::Data,
+                format!("{} is not a valid key", value),
+            )),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Key::Number(n) | Key::Date(n) => {
+                // Integers are converted back to integers so they can be
+                // deserialized into integer types.
+                if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER {
+                    Value::from(*n as i64)
+                } else {
+                    serde_json::Number::from_f64(*n)
+                        .map(Value::Number)
+                        .unwrap_or(Value::Null)
+                }
+            }
+            Key::String(s) => Value::String(s.clone()),
+            Key::Binary(b) => Value::Array(b.iter().map(|b| Value::from(*b)).collect()),
+            Key::Array(a) => Value::Array(a.iter().map(Key::to_json).collect()),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Key::Number(_) => 0,
+            Key::Date(_) => 1,
+            Key::String(_) => 2,
+            Key::Binary(_) => 3,
+            Key::Array(_) => 4,
+        }
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Key::Number(a), Key::Number(b)) | (Key::Date(a), Key::Date(b)) => a.total_cmp(b),
+            (Key::String(a), Key::String(b)) => {
+                // IndexedDB compares strings by their UTF-16 code units.
+                a.encode_utf16().cmp(b.encode_utf16())
+            }
+            (Key::Binary(a), Key::Binary(b)) => a.cmp(b),
+            (Key::Array(a), Key::Array(b)) => a.cmp(b),
+            (a, b) => a.type_rank().cmp(&b.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+#[cfg(test)]
+mod test {
+    use super::Key;
+
+    #[test]
+    fn ordering() {
+        let mut keys = vec![
+            Key::from_serialize(&vec!["a"]).unwrap(),
+            Key::Binary(vec![1]),
+            Key::from_serialize("b").unwrap(),
+            Key::from_serialize("a").unwrap(),
+            Key::Date(0.0),
+            Key::from_serialize(&10).unwrap(),
+            Key::from_serialize(&-1.5).unwrap(),
+        ];
+        keys.sort();
+
+        assert_eq!(
+            keys,
+            vec![
+                Key::Number(-1.5),
+                Key::Number(10.0),
+                Key::Date(0.0),
+                Key::String("a".to_owned()),
+                Key::String("b".to_owned()),
+                Key::Binary(vec![1]),
+                Key::Array(vec![Key::String("a".to_owned())]),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert!(Key::from_serialize(&true).is_err());
+        assert!(Key::from_serialize(&Option::<u32>::None).is_err());
+        assert!(Key::from_serialize(&std::collections::HashMap::<String, u32>::new()).is_err());
+        assert!(Key::from_serialize(&(1u64 << 53)).is_err());
+        assert!(Key::from_serialize(&-(1i64 << 53)).is_err());
+        assert!(Key::from_serialize(&u64::MAX).is_err());
+        assert!(Key::from_serialize(&((1u64 << 53) - 1)).is_ok());
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = Key::from_serialize(&("user", 42u32)).unwrap();
+        let (name, id): (String, u32) = key.deserialize().unwrap();
+
+        assert_eq!(name, "user");
+        assert_eq!(id, 42);
+    }
+}
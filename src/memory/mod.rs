@@ -0,0 +1,1661 @@
+//! An emulation of IndexedDB that works outside of a browser.
+//!
+//! The types of this module mirror the API of the browser types, e.g.
+//! [`MemoryDb`] offers the same methods as [`IndexedDb`], but they are
+//! separate types. Storage code that should run in a browser as well as in a
+//! plain `cargo test` on native targets is written against the traits of the
+//! [`storage`](crate::storage) module, which are implemented by both:
+//!
+//! ```
+//! use indexeddb::{
+//!     memory::MemoryFactory,
+//!     storage::{Database, Store, Transaction},
+//!     TransactionMode,
+//! };
+//!
+//! async fn save<D: Database>(db: &D, name: &str) -> Result<(), D::Error> {
+//!     let transaction = db.transaction(TransactionMode::ReadWrite);
+//!     transaction.object_store("names")?.put(&1, &name).await?;
+//!     transaction.done().await
+//! }
+//!
+//! # futures::executor::block_on(async {
+//! let db = MemoryFactory::new()
+//!     .open("test", 1, |_, db| {
+//!         db.create_object_store("names").unwrap();
+//!     })
+//!     .await
+//!     .unwrap();
+//!
+//! save(&db, "Alice").await.unwrap();
+//! # });
+//! ```
+//!
+//! Databases are held in memory. With the `sled` feature enabled a factory
+//! created with `MemoryFactory::with_sled` additionally writes its databases
+//! through to a local directory, which allows desktop builds to share the
+//! storage code of a web app. Reads are still served from memory, so the
+//! whole content of the stored databases has to fit into RAM.
+//!
+//! The emulation follows the semantics of IndexedDB:
+//!
+//! * Keys are ordered like IndexedDB orders them, see [`Key`].
+//! * Transactions see a consistent snapshot of the stores in their scope,
+//!   writes only become visible to other transactions once they are committed.
+//! * Transactions are committed when [`MemoryTransaction::done`] is called or
+//!   when they are dropped. Like in a browser a transaction that is left alone
+//!   auto-commits, this happens as soon as a newer transaction with an
+//!   overlapping scope makes its first request. Requests made on an
+//!   auto-committed transaction fail with a `TransactionInactiveError`.
+//! * A failing write, e.g. adding a key that already exists, aborts the whole
+//!   transaction.
+//! * Databases are versioned, opening a database with a newer version commits
+//!   the running transactions and runs the upgrade callback, opening it with
+//!   an older version fails with a `VersionError`.
+//! * Object stores can have indexes, a unique index rejects writes that would
+//!   add an index key twice with a `ConstraintError`.
+//!
+//! # Example
+//!
+//! ```
+//! # use futures::executor::block_on;
+//! use indexeddb::memory::MemoryDb;
+//!
+//! # block_on(async {
+//! let db = MemoryDb::open("test", 1, |_, db| {
+//!    db.create_object_store("test").unwrap();
+//! }).await.expect("Failed to open the database");
+//!
+//! let transaction = db.readwrite_transaction();
+//! let store = transaction.object_store("test").unwrap();
+//!
+//! store.add(&"Hello", &"world").await.unwrap();
+//! transaction.done().await.unwrap();
+//!
+//! let transaction = db.readonly_transaction();
+//! let store = transaction.object_store("test").unwrap();
+//!
+//! let value: String = store.get(&"Hello").await.unwrap().unwrap();
+//! assert_eq!(value, "world");
+//! # });
+//! ```
+//!
+//! [`IndexedDb`]: crate::IndexedDb
+
+mod error;
+mod key;
+#[cfg(feature = "sled")]
+mod persistent;
+// Only the `sled` feature provides a storage implementation.
+#[cfg_attr(not(feature = "sled"), allow(dead_code))]
+mod storage;
+mod store;
+mod transaction;
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::{Rc, Weak},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use self::{
+    error::Error,
+    key::Key,
+    transaction::{MemoryCursor, MemoryIndex, MemoryObjectStore, MemoryTransaction},
+};
+use self::{
+    storage::{IndexSchema, Schema, Storage, StoredRecords},
+    store::StoreData,
+    transaction::{bounds, from_value, to_value, TransactionState},
+};
+pub use crate::ErrorKind;
+use crate::{
+    transaction::{Dynamic, ReadOnly, ReadWrite, StaticMode, TransactionMode},
+    KeyPath, KeyRange,
+};
+
+#[derive(Debug)]
+pub(crate) struct DatabaseState {
+    name: String,
+    version: u32,
+    stores: BTreeMap<String, StoreData>,
+    storage: Option<Rc<dyn Storage>>,
+    transactions: Vec<Weak<RefCell<TransactionState>>>,
+    next_transaction: u64,
+}
+
+impl DatabaseState {
+    fn new(name: &str, storage: Option<Rc<dyn Storage>>) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: 0,
+            stores: BTreeMap::new(),
+            storage,
+            transactions: Vec::new(),
+            next_transaction: 0,
+        }
+    }
+
+    /// Load a database from the given storage and rebuild its indexes.
+    #[cfg_attr(not(feature = "sled"), allow(dead_code))]
+    fn load(storage: &Rc<dyn Storage>, name: &str) -> Result<Option<Self>, Error> {
+        let (schema, mut records) = match storage.load(name)? {
+            Some(loaded) => loaded,
+            None => return Ok(None),
+        };
+
+        let mut state = Self::new(name, Some(storage.clone()));
+        state.version = schema.version;
+
+        for (store_name, indexes) in schema.stores {
+            let mut store = StoreData::default();
+
+            for (index_name, index) in indexes {
+                store.create_index(&index_name, index.key_path, index.unique)?;
+            }
+
+            for (key, value) in records.remove(&store_name).unwrap_or_default() {
+                store.insert(key, value, true)?;
+            }
+
+            state.stores.insert(store_name, store);
+        }
+
+        Ok(Some(state))
+    }
+
+    fn schema(&self) -> Schema {
+        let stores = self
+            .stores
+            .iter()
+            .map(|(name, store)| {
+                let indexes = store
+                    .indexes
+                    .iter()
+                    .map(|(name, index)| {
+                        let schema = IndexSchema {
+                            key_path: index.key_path.clone(),
+                            unique: index.unique,
+                        };
+
+                        (name.clone(), schema)
+                    })
+                    .collect();
+
+                (name.clone(), indexes)
+            })
+            .collect();
+
+        Schema {
+            version: self.version,
+            stores,
+        }
+    }
+
+    /// Commit the transactions that are still running.
+    ///
+    /// In a browser an upgrade waits until the other connections closed, so
+    /// their transactions finish before the stores change.
+    fn finish_transactions(state: &SharedDatabase) {
+        let transactions: Vec<_> = state
+            .borrow()
+            .transactions
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        for transaction in transactions {
+            transaction.borrow_mut().commit();
+        }
+    }
+
+    /// The current records of the given stores, stores that don't exist
+    /// anymore have no records.
+    fn records(&self, stores: &BTreeSet<String>) -> StoredRecords {
+        stores
+            .iter()
+            .map(|name| {
+                let records = self
+                    .stores
+                    .get(name)
+                    .map(|store| {
+                        store
+                            .records
+                            .iter()
+                            .map(|(key, value)| (key.clone(), value.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                (name.clone(), records)
+            })
+            .collect()
+    }
+}
+
+pub(crate) type SharedDatabase = Rc<RefCell<DatabaseState>>;
+
+thread_local! {
+    static FACTORY: MemoryFactory = MemoryFactory::new();
+}
+
+/// A factory holding a set of in-memory databases.
+///
+/// Databases opened through the same factory share their data, cloning the
+/// factory yields a handle to the same set of databases.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFactory {
+    databases: Rc<RefCell<BTreeMap<String, SharedDatabase>>>,
+    storage: Option<Rc<dyn Storage>>,
+}
+
+impl MemoryFactory {
+    /// Create a new factory without any databases.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a factory that persists its databases in a `sled` database in
+    /// the given directory.
+    ///
+    /// Every committed read/write transaction and every upgrade is written
+    /// to disk before it becomes visible.
+    ///
+    /// # Memory usage
+    ///
+    /// `sled` only makes the databases durable, it doesn't hold them. All
+    /// databases stored in the directory are loaded into memory when the
+    /// factory is created, and reads, key ranges and cursors are served from
+    /// that copy. The stored data must fit into memory, use IndexedDB in a
+    /// browser or a database engine for larger data sets.
+    ///
+    /// A directory can only be used by a single factory at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::memory::MemoryFactory;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let factory = MemoryFactory::with_sled("data").expect("Failed to open the storage");
+    ///
+    /// let db = factory.open("test", 1, |_, db| {
+    ///     db.create_object_store("test").unwrap();
+    /// }).await.expect("Failed to open the database");
+    /// # });
+    /// ```
+    #[cfg(feature = "sled")]
+    pub fn with_sled(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let storage: Rc<dyn Storage> = Rc::new(persistent::SledStorage::open(path.as_ref())?);
+        let mut databases = BTreeMap::new();
+
+        for name in storage.database_names()? {
+            if let Some(state) = DatabaseState::load(&storage, &name)? {
+                databases.insert(name, Rc::new(RefCell::new(state)));
+            }
+        }
+
+        Ok(Self {
+            databases: Rc::new(RefCell::new(databases)),
+            storage: Some(storage),
+        })
+    }
+
+    /// Get the default factory of the current thread.
+    ///
+    /// This is the factory that [`MemoryDb::open`] uses.
+    pub fn thread_default() -> Self {
+        FACTORY.with(Clone::clone)
+    }
+
+    /// Get the names of the databases of this factory.
+    pub fn database_names(&self) -> Vec<String> {
+        self.databases.borrow().keys().cloned().collect()
+    }
+
+    /// Open a database with the given name.
+    ///
+    /// See [`MemoryDb::open`] for a description of the arguments.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the given `version` is 0.
+    pub async fn open(
+        &self,
+        name: &str,
+        version: u32,
+        on_upgrade_needed: impl Fn(u32, &MemoryDbDuringUpgrade),
+    ) -> Result<MemoryDb, Error> {
+        if version == 0 {
+            panic!("indexeddb version must be >= 1");
+        }
+
+        let state = self
+            .databases
+            .borrow_mut()
+            .entry(name.to_owned())
+            .or_insert_with(|| {
+                Rc::new(RefCell::new(DatabaseState::new(name, self.storage.clone())))
+            })
+            .clone();
+
+        let db = MemoryDb { state };
+        let old_version = db.state.borrow().version;
+
+        if version < old_version {
+            return Err(Error::new(
+                ErrorKind::Version,
+                format!(
+                    "the requested version {} is lower than the existing version {}",
+                    version, old_version
+                ),
+            ));
+        }
+
+        if version > old_version {
+            DatabaseState::finish_transactions(&db.state);
+
+            // The stores are persistent data structures, keeping the old ones
+            // to roll back an aborted upgrade is cheap.
+            let old_stores = db.state.borrow().stores.clone();
+            db.state.borrow_mut().version = version;
+
+            let upgrade = MemoryDbDuringUpgrade {
+                db: db.clone(),
+                rewritten: RefCell::new(BTreeSet::new()),
+                aborted: RefCell::new(None),
+            };
+            on_upgrade_needed(old_version, &upgrade);
+
+            let result = match upgrade.aborted.take() {
+                Some(error) => Err(error),
+                None => {
+                    let state = db.state.borrow();
+
+                    match &state.storage {
+                        Some(storage) => {
+                            let rewritten = state.records(&upgrade.rewritten.borrow());
+                            storage.store_schema(name, &state.schema(), &rewritten)
+                        }
+                        None => Ok(()),
+                    }
+                }
+            };
+
+            // An upgrade that was aborted or couldn't be stored is rolled
+            // back, the stores are persistent maps so the snapshot is cheap.
+            if let Err(error) = result {
+                let mut state = db.state.borrow_mut();
+                state.version = old_version;
+                state.stores = old_stores;
+
+                // Aborting the creation of a database deletes it again.
+                if old_version == 0 {
+                    self.databases.borrow_mut().remove(name);
+                }
+
+                return Err(error);
+            }
+        }
+
+        Ok(db)
+    }
+
+    /// Delete the database with the given name.
+    ///
+    /// Deleting a database that doesn't exist isn't an error.
+    pub async fn delete_database(&self, name: &str) -> Result<(), Error> {
+        if let Some(storage) = &self.storage {
+            storage.delete(name)?;
+        }
+
+        self.databases.borrow_mut().remove(name);
+        Ok(())
+    }
+}
+
+/// A handle on an in-memory database during an upgrade.
+#[derive(Debug)]
+pub struct MemoryDbDuringUpgrade {
+    db: MemoryDb,
+    // The stores whose stored records need to be replaced at the end of the
+    // upgrade.
+    rewritten: RefCell<BTreeSet<String>>,
+    // Set once the upgrade was aborted, the upgrade is rolled back at its end.
+    aborted: RefCell<Option<Error>>,
+}
+
+impl MemoryDbDuringUpgrade {
+    /// Get the name of this database.
+    pub fn name(&self) -> String {
+        self.db.name()
+    }
+
+    /// The new version of the database.
+    pub fn version(&self) -> u64 {
+        self.db.version()
+    }
+
+    /// Abort the upgrade.
+    ///
+    /// Like aborting the `versionchange` transaction in a browser, the
+    /// database keeps its old version and schema and opening it fails with
+    /// an `AbortError`. Later changes of the upgrade fail.
+    pub fn abort(&self) {
+        self.abort_with(Error::new(ErrorKind::Abort, "the upgrade was aborted"));
+    }
+
+    fn abort_with(&self, error: Error) {
+        self.aborted.borrow_mut().get_or_insert(error);
+    }
+
+    fn check_active(&self) -> Result<(), Error> {
+        match &*self.aborted.borrow() {
+            Some(_) => Err(Error::new(
+                ErrorKind::TransactionInactive,
+                "the upgrade was aborted",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Create a new object store.
+    ///
+    /// Fails with a `ConstraintError` if a store with the given name already
+    /// exists.
+    ///
+    /// * `name` - The name that the object store should be created with.
+    pub fn create_object_store<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<MemoryObjectStoreDuringUpgrade<'a>, Error> {
+        self.check_active()?;
+
+        if self.store_exists(name) {
+            return Err(Error::new(
+                ErrorKind::Constraint,
+                format!("an object store called \"{}\" already exists", name),
+            ));
+        }
+
+        self.db
+            .state
+            .borrow_mut()
+            .stores
+            .insert(name.to_owned(), StoreData::default());
+        self.rewritten.borrow_mut().insert(name.to_owned());
+
+        Ok(MemoryObjectStoreDuringUpgrade {
+            name: RefCell::new(name.to_owned()),
+            db: self,
+        })
+    }
+
+    /// Get an existing object store to create or delete its indexes and to
+    /// read and write its entries.
+    ///
+    /// Fails with a `NotFoundError` if there is no store with the given name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::executor::block_on;
+    /// use futures::FutureExt;
+    /// use indexeddb::memory::MemoryFactory;
+    ///
+    /// # block_on(async {
+    /// let factory = MemoryFactory::new();
+    ///
+    /// let db = factory.open("users", 1, |_, db| {
+    ///     db.create_object_store("users").unwrap();
+    /// }).await.expect("Failed to open the database");
+    /// # drop(db);
+    ///
+    /// let db = factory.open("users", 2, |_, db| {
+    ///     let store = db.object_store("users").unwrap();
+    ///     store.create_index("by_email", "email", true).unwrap();
+    ///
+    ///     // The requests complete right away.
+    ///     let user = serde_json::json!({ "email": "a@example.com" });
+    ///     store.put(&1, &user).now_or_never().unwrap().unwrap();
+    /// }).await.expect("Failed to open the database");
+    /// # });
+    /// ```
+    pub fn object_store(&self, name: &str) -> Result<MemoryObjectStoreDuringUpgrade<'_>, Error> {
+        self.check_active()?;
+
+        if !self.store_exists(name) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no object store called \"{}\"", name),
+            ));
+        }
+
+        Ok(MemoryObjectStoreDuringUpgrade {
+            name: RefCell::new(name.to_owned()),
+            db: self,
+        })
+    }
+
+    /// Is there already a store with the given name?
+    pub fn store_exists(&self, name: &str) -> bool {
+        self.db.state.borrow().stores.contains_key(name)
+    }
+
+    /// Delete the object store with the given name together with its
+    /// entries and indexes.
+    ///
+    /// Fails with a `NotFoundError` if there is no store with the given name.
+    pub fn delete_object_store(&self, name: &str) -> Result<(), Error> {
+        self.check_active()?;
+
+        self.db
+            .state
+            .borrow_mut()
+            .stores
+            .remove(name)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("no object store called \"{}\"", name),
+                )
+            })?;
+        self.rewritten.borrow_mut().insert(name.to_owned());
+
+        Ok(())
+    }
+}
+
+/// An in-memory object store during an upgrade.
+///
+/// Like [`ObjectStoreDuringUpgrade`], the entries of the store can be read
+/// and written to migrate them. The requests complete right away, the upgrade
+/// callback can take their result with [`FutureExt::now_or_never`]. Like in a
+/// browser a failed write aborts the upgrade.
+///
+/// [`ObjectStoreDuringUpgrade`]: crate::ObjectStoreDuringUpgrade
+/// [`FutureExt::now_or_never`]: futures::FutureExt::now_or_never
+#[derive(Debug)]
+pub struct MemoryObjectStoreDuringUpgrade<'a> {
+    name: RefCell<String>,
+    db: &'a MemoryDbDuringUpgrade,
+}
+
+impl<'a> MemoryObjectStoreDuringUpgrade<'a> {
+    /// The name of the object store.
+    pub fn name(&self) -> String {
+        self.name.borrow().clone()
+    }
+
+    /// Create a new index on this object store.
+    ///
+    /// Fails with a `ConstraintError` if an index with the given name already
+    /// exists or if the existing values violate the unique constraint.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the index.
+    ///
+    /// * `key_path` - The path to the key of the index inside of the stored
+    ///   values, values without a valid key at the path aren't indexed.
+    ///
+    /// * `unique` - Should the index reject values with an index key that is
+    ///   already used by another value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::executor::block_on;
+    /// use indexeddb::memory::MemoryDb;
+    ///
+    /// # block_on(async {
+    /// let db = MemoryDb::open("users", 1, |_, db| {
+    ///     let store = db.create_object_store("users").unwrap();
+    ///     store.create_index("by_email", "email", true).unwrap();
+    /// }).await.expect("Failed to open the database");
+    /// # });
+    /// ```
+    pub fn create_index(
+        &self,
+        name: &str,
+        key_path: impl Into<KeyPath>,
+        unique: bool,
+    ) -> Result<(), Error> {
+        self.with_store(|store| store.create_index(name, key_path.into(), unique))
+    }
+
+    /// Delete the index with the given name.
+    pub fn delete_index(&self, name: &str) -> Result<(), Error> {
+        self.with_store(|store| {
+            store.indexes.remove(name).map(|_| ()).ok_or_else(|| {
+                Error::new(ErrorKind::NotFound, format!("no index called \"{}\"", name))
+            })
+        })
+    }
+
+    /// Get the names of the indexes of this object store.
+    pub fn index_names(&self) -> Vec<String> {
+        self.with_store(|store| Ok(store.indexes.keys().cloned().collect()))
+            .unwrap_or_default()
+    }
+
+    /// Rename this object store.
+    ///
+    /// Fails with a `ConstraintError` if another store with the new name
+    /// already exists.
+    pub fn rename(&self, new_name: &str) -> Result<(), Error> {
+        self.db.check_active()?;
+
+        let mut name = self.name.borrow_mut();
+
+        if *name == new_name {
+            return Ok(());
+        }
+
+        if self.db.store_exists(new_name) {
+            return Err(Error::new(
+                ErrorKind::Constraint,
+                format!("an object store called \"{}\" already exists", new_name),
+            ));
+        }
+
+        let mut state = self.db.db.state.borrow_mut();
+        let store = state.stores.remove(&*name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("no object store called \"{}\"", name),
+            )
+        })?;
+
+        state.stores.insert(new_name.to_owned(), store);
+
+        let mut rewritten = self.db.rewritten.borrow_mut();
+        rewritten.insert(name.clone());
+        rewritten.insert(new_name.to_owned());
+        *name = new_name.to_owned();
+
+        Ok(())
+    }
+
+    /// Rename the index with the given name.
+    ///
+    /// Fails with a `ConstraintError` if another index of this store already
+    /// has the new name.
+    pub fn rename_index(&self, name: &str, new_name: &str) -> Result<(), Error> {
+        self.with_store(|store| {
+            if name != new_name && store.indexes.contains_key(new_name) {
+                return Err(Error::new(
+                    ErrorKind::Constraint,
+                    format!("an index called \"{}\" already exists", new_name),
+                ));
+            }
+
+            let index = store.indexes.remove(name).ok_or_else(|| {
+                Error::new(ErrorKind::NotFound, format!("no index called \"{}\"", name))
+            })?;
+
+            store.indexes.insert(new_name.to_owned(), index);
+            Ok(())
+        })
+    }
+
+    /// Delete this object store.
+    pub fn delete(self) -> Result<(), Error> {
+        self.db.delete_object_store(&self.name.borrow())
+    }
+
+    /// Get the value with the given key.
+    pub async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, Error> {
+        let key = Key::from_serialize(key)?;
+
+        self.with_store(|store| Ok(store.records.get(&key).cloned()))?
+            .map(from_value)
+            .transpose()
+    }
+
+    /// Get all the values with keys inside of the given range in key order,
+    /// all the values of the store if no range is given.
+    pub async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, Error> {
+        let bounds = bounds(range)?;
+
+        self.with_store(|store| {
+            store
+                .records
+                .range(bounds)
+                .map(|(_, value)| from_value(value.clone()))
+                .collect()
+        })
+    }
+
+    /// Count the values with keys inside of the given range, all the values
+    /// of the store if no range is given.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<u32, Error> {
+        let bounds = bounds(range)?;
+
+        self.with_store(|store| Ok(store.records.range(bounds).count() as u32))
+    }
+
+    /// Add the given value under the given key to the object store.
+    ///
+    /// Fails with a `ConstraintError` if a value with the given key already
+    /// exists in the store.
+    pub async fn add<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), Error> {
+        let key = Key::from_serialize(key)?;
+        let value = to_value(value)?;
+
+        self.write(|store| store.insert(key, value, false))
+    }
+
+    /// Store the given value under the given key in the object store,
+    /// replacing any existing value.
+    pub async fn put<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), Error> {
+        let key = Key::from_serialize(key)?;
+        let value = to_value(value)?;
+
+        self.write(|store| store.insert(key, value, true))
+    }
+
+    /// Delete the value with the given key from the object store,
+    /// [`delete`](Self::delete) deletes the store itself.
+    pub async fn delete_value(&self, key: &impl Serialize) -> Result<(), Error> {
+        let key = Key::from_serialize(key)?;
+
+        self.write(|store| {
+            store.remove(&key);
+            Ok(())
+        })
+    }
+
+    /// Delete all the values of the object store.
+    pub async fn clear(&self) -> Result<(), Error> {
+        self.write(|store| {
+            store.clear();
+            Ok(())
+        })
+    }
+
+    /// Change the records of the store, the stored records of the store are
+    /// replaced at the end of the upgrade.
+    fn write<R>(&self, f: impl FnOnce(&mut StoreData) -> Result<R, Error>) -> Result<R, Error> {
+        let result = self
+            .with_store(f)
+            .inspect_err(|e| self.db.abort_with(e.clone()))?;
+        self.db.rewritten.borrow_mut().insert(self.name());
+
+        Ok(result)
+    }
+
+    fn with_store<R>(
+        &self,
+        f: impl FnOnce(&mut StoreData) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        self.db.check_active()?;
+
+        let mut state = self.db.db.state.borrow_mut();
+        let name = self.name.borrow();
+        let store = state.stores.get_mut(&*name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("no object store called \"{}\"", name),
+            )
+        })?;
+
+        f(store)
+    }
+}
+
+/// A handle to an opened in-memory database.
+#[derive(Debug, Clone)]
+pub struct MemoryDb {
+    state: SharedDatabase,
+}
+
+impl MemoryDb {
+    /// Open a database with the given name using the default factory of the
+    /// current thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    ///
+    /// * `version` - The current version of the database, if the database
+    ///   already existed but the given version is newer the `on_upgrade_needed`
+    ///   callback will be triggered. This needs to be a positive number bigger
+    ///   than zero.
+    ///
+    /// * `on_upgrade_needed` - Callback that will be called if the database
+    ///   needs to be upgraded, this includes the initial creation of the
+    ///   database.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the given `version` is 0.
+    pub async fn open(
+        name: &str,
+        version: u32,
+        on_upgrade_needed: impl Fn(u32, &MemoryDbDuringUpgrade),
+    ) -> Result<MemoryDb, Error> {
+        MemoryFactory::thread_default()
+            .open(name, version, on_upgrade_needed)
+            .await
+    }
+
+    /// Get the name of this database.
+    pub fn name(&self) -> String {
+        self.state.borrow().name.clone()
+    }
+
+    /// The current version of the database.
+    pub fn version(&self) -> u64 {
+        self.state.borrow().version as u64
+    }
+
+    /// Get the names of the object stores in this database.
+    pub fn object_store_names(&self) -> Vec<String> {
+        self.state.borrow().stores.keys().cloned().collect()
+    }
+
+    /// Start a database transaction, see [`IndexedDb::transaction`].
+    ///
+    /// [`IndexedDb::transaction`]: crate::IndexedDb::transaction
+    pub fn transaction(&self, mode: TransactionMode) -> MemoryTransaction<'_, Dynamic> {
+        MemoryTransaction::new(self.begin(mode))
+    }
+
+    /// Start a read only database transaction, see
+    /// [`IndexedDb::readonly_transaction`].
+    ///
+    /// [`IndexedDb::readonly_transaction`]: crate::IndexedDb::readonly_transaction
+    pub fn readonly_transaction(&self) -> MemoryTransaction<'_, ReadOnly> {
+        self.typed_transaction()
+    }
+
+    /// Start a read/write database transaction, see
+    /// [`IndexedDb::readwrite_transaction`].
+    ///
+    /// [`IndexedDb::readwrite_transaction`]: crate::IndexedDb::readwrite_transaction
+    pub fn readwrite_transaction(&self) -> MemoryTransaction<'_, ReadWrite> {
+        self.typed_transaction()
+    }
+
+    fn typed_transaction<M: StaticMode>(&self) -> MemoryTransaction<'_, M> {
+        MemoryTransaction::new(self.begin(M::MODE))
+    }
+
+    fn begin(&self, mode: TransactionMode) -> Rc<RefCell<TransactionState>> {
+        let mut db = self.state.borrow_mut();
+
+        let id = db.next_transaction;
+        db.next_transaction += 1;
+
+        let scope = db.stores.keys().cloned().collect();
+        let transaction = TransactionState::new(id, self.state.clone(), mode, scope);
+
+        db.transactions.retain(|t| t.strong_count() > 0);
+        db.transactions.push(Rc::downgrade(&transaction));
+
+        transaction
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        storage::{Change, Schema, Storage, StoredRecords},
+        Error, ErrorKind, MemoryFactory,
+    };
+    use crate::{KeyRange, TransactionMode};
+    use futures::{executor::block_on, FutureExt};
+    use serde_json::json;
+    use std::{collections::BTreeMap, convert::TryFrom, rc::Rc};
+
+    fn open(factory: &MemoryFactory) -> super::MemoryDb {
+        block_on(factory.open("test", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        }))
+        .expect("Failed to open the database")
+    }
+
+    #[test]
+    fn versioned_upgrades() {
+        block_on(async {
+            let factory = MemoryFactory::new();
+
+            let db = factory
+                .open("test", 1, |old_version, db| {
+                    assert_eq!(old_version, 0);
+                    db.create_object_store("test").unwrap();
+                    assert!(db.create_object_store("test").is_err());
+                })
+                .await
+                .unwrap();
+            assert_eq!(db.version(), 1);
+            assert_eq!(db.object_store_names(), vec!["test".to_owned()]);
+
+            let db = factory
+                .open("test", 2, |old_version, db| {
+                    assert_eq!(old_version, 1);
+                    assert!(db.store_exists("test"));
+                    db.create_object_store("other").unwrap().delete().unwrap();
+                })
+                .await
+                .unwrap();
+            assert_eq!(db.version(), 2);
+            assert_eq!(db.object_store_names(), vec!["test".to_owned()]);
+
+            factory
+                .open("test", 2, |_, _| panic!("No upgrade is needed"))
+                .await
+                .unwrap();
+
+            let error = factory.open("test", 1, |_, _| ()).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Version);
+        });
+    }
+
+    #[test]
+    fn rename_object_stores() {
+        block_on(async {
+            let factory = MemoryFactory::new();
+
+            let db = factory
+                .open("test", 1, |_, db| {
+                    let store = db.create_object_store("draft").unwrap();
+                    store.create_index("by_draft", "draft", false).unwrap();
+                    db.create_object_store("other").unwrap();
+
+                    store.rename("users").unwrap();
+                    store.rename_index("by_draft", "by_email").unwrap();
+                    assert_eq!(store.index_names(), vec!["by_email".to_owned()]);
+
+                    let error = store.rename("other").unwrap_err();
+                    assert_eq!(error.kind(), ErrorKind::Constraint);
+
+                    db.delete_object_store("other").unwrap();
+                    assert!(db.delete_object_store("other").is_err());
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(db.object_store_names(), vec!["users".to_owned()]);
+
+            factory
+                .open("test", 2, |_, db| {
+                    let store = db.object_store("users").unwrap();
+                    store.delete_index("by_email").unwrap();
+                    assert!(db.object_store("other").is_err());
+                })
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn upgrades_commit_running_transactions() {
+        let factory = MemoryFactory::new();
+        let db = open(&factory);
+
+        let transaction = db.readwrite_transaction();
+        let store = transaction.object_store("test").unwrap();
+        block_on(store.put(&1, &1)).unwrap();
+
+        let upgraded = block_on(factory.open("test", 2, |_, db| {
+            db.object_store("test").unwrap().rename("renamed").unwrap();
+        }))
+        .unwrap();
+
+        // The transaction finished before the upgrade, its write was moved
+        // along with the store.
+        let error = block_on(store.put(&2, &2)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TransactionInactive);
+        block_on(transaction.done()).unwrap();
+
+        let transaction = upgraded.readonly_transaction();
+        let store = transaction.object_store("renamed").unwrap();
+        assert_eq!(block_on(store.get(&1)).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn migrate_during_upgrade() {
+        let factory = MemoryFactory::new();
+        let db = open(&factory);
+
+        let transaction = db.readwrite_transaction();
+        let store = transaction.object_store("test").unwrap();
+        block_on(store.put(&1, &json!({ "name": "Alice" }))).unwrap();
+        block_on(store.put(&2, &json!({ "name": "Bob" }))).unwrap();
+        block_on(transaction.done()).unwrap();
+
+        let db = block_on(factory.open("test", 2, |_, db| {
+            let store = db.object_store("test").unwrap();
+
+            let users: Vec<serde_json::Value> =
+                store.get_all(None).now_or_never().unwrap().unwrap();
+            assert_eq!(users.len(), 2);
+
+            for (id, user) in (1..).zip(users) {
+                let user = json!({ "name": user["name"], "email": format!("{}@example.com", id) });
+                store.put(&id, &user).now_or_never().unwrap().unwrap();
+            }
+
+            store.create_index("by_email", "email", true).unwrap();
+            store.delete_value(&2).now_or_never().unwrap().unwrap();
+            store.add(&3, &json!({})).now_or_never().unwrap().unwrap();
+            assert_eq!(store.count(None).now_or_never().unwrap().unwrap(), 2);
+        }))
+        .unwrap();
+
+        let transaction = db.readonly_transaction();
+        let store = transaction.object_store("test").unwrap();
+        let by_email = store.index("by_email").unwrap();
+
+        let user: serde_json::Value = block_on(by_email.get(&"1@example.com")).unwrap().unwrap();
+        assert_eq!(user["name"], "Alice");
+        assert_eq!(block_on(store.get::<serde_json::Value>(&2)).unwrap(), None);
+    }
+
+    #[test]
+    fn aborted_upgrades_roll_back() {
+        let factory = MemoryFactory::new();
+        let db = open(&factory);
+
+        let transaction = db.readwrite_transaction();
+        let store = transaction.object_store("test").unwrap();
+        block_on(store.put(&1, &1)).unwrap();
+        block_on(transaction.done()).unwrap();
+
+        // A failed write aborts the upgrade.
+        let error = block_on(factory.open("test", 2, |_, db| {
+            db.create_object_store("other").unwrap();
+
+            let store = db.object_store("test").unwrap();
+            store.create_index("by_value", "", false).unwrap();
+            store.put(&2, &2).now_or_never().unwrap().unwrap();
+
+            let error = store.add(&1, &1).now_or_never().unwrap().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Constraint);
+
+            let error = db.create_object_store("more").unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::TransactionInactive);
+        }))
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Constraint);
+
+        assert_eq!(db.version(), 1);
+        assert_eq!(db.object_store_names(), vec!["test".to_owned()]);
+
+        let transaction = db.readonly_transaction();
+        let store = transaction.object_store("test").unwrap();
+        assert!(store.index("by_value").is_err());
+        assert_eq!(block_on(store.count(None)).unwrap(), 1);
+
+        // Aborting the creation of a database deletes it.
+        let error = block_on(factory.open("created", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+            db.abort();
+        }))
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Abort);
+        assert_eq!(factory.database_names(), vec!["test".to_owned()]);
+    }
+
+    /// A storage whose upgrades always fail.
+    #[derive(Debug)]
+    struct FailingStorage;
+
+    impl Storage for FailingStorage {
+        fn database_names(&self) -> Result<Vec<String>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn load(&self, _: &str) -> Result<Option<(Schema, StoredRecords)>, Error> {
+            Ok(None)
+        }
+
+        fn store_schema(&self, _: &str, _: &Schema, _: &StoredRecords) -> Result<(), Error> {
+            Err(Error::new(ErrorKind::Unknown, "the disk is full"))
+        }
+
+        fn commit(&self, _: &str, _: &[Change]) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn delete(&self, _: &str) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unstored_upgrades_roll_back() {
+        let factory = MemoryFactory {
+            storage: Some(Rc::new(FailingStorage)),
+            ..MemoryFactory::new()
+        };
+
+        let error = block_on(factory.open("test", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        }))
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unknown);
+        assert!(factory.database_names().is_empty());
+
+        // Only store the upgrades of a database that already exists.
+        let factory = MemoryFactory::new();
+        let db = open(&factory);
+        db.state.borrow_mut().storage = Some(Rc::new(FailingStorage));
+
+        let error = block_on(factory.open("test", 2, |_, db| {
+            db.delete_object_store("test").unwrap();
+        }))
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unknown);
+        assert_eq!(db.version(), 1);
+        assert_eq!(db.object_store_names(), vec!["test".to_owned()]);
+    }
+
+    #[test]
+    fn failed_requests_abort_the_transaction() {
+        let db = open(&MemoryFactory::new());
+
+        block_on(async {
+            let transaction = db.readwrite_transaction();
+            let store = transaction.object_store("test").unwrap();
+
+            store.add(&"a", &1).await.unwrap();
+            store.add(&"b", &2).await.unwrap();
+
+            let error = store.add(&"a", &3).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Constraint);
+
+            let error = store.put(&"c", &4).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::TransactionInactive);
+
+            let error = transaction.done().await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Constraint);
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get::<u32>(&"a").await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn abort_and_readonly() {
+        let db = open(&MemoryFactory::new());
+
+        block_on(async {
+            let transaction = db.transaction(TransactionMode::Readonly);
+            let store = transaction.object_store("test").unwrap();
+
+            let error = store.put(&"a", &1).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::ReadOnly);
+            transaction.done().await.unwrap();
+
+            let transaction = db.readwrite_transaction();
+            let store = transaction.object_store("test").unwrap();
+            store.put(&"a", &1).await.unwrap();
+            assert_eq!(store.get(&"a").await.unwrap(), Some(1));
+            transaction.abort().await.unwrap();
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get::<u32>(&"a").await.unwrap(), None);
+
+            let error = transaction.object_store("missing").unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+        });
+    }
+
+    #[test]
+    fn auto_commit() {
+        let db = open(&MemoryFactory::new());
+
+        block_on(async {
+            let first = db.readwrite_transaction();
+            let first_store = first.object_store("test").unwrap();
+            first_store.put(&"a", &1).await.unwrap();
+
+            {
+                // Dropping a transaction commits it.
+                let transaction = db.readonly_transaction();
+                let store = transaction.object_store("test").unwrap();
+
+                // The first transaction had no more pending requests so it
+                // was committed before this one started.
+                assert_eq!(store.get(&"a").await.unwrap(), Some(1));
+            }
+
+            let error = first_store.put(&"b", &2).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::TransactionInactive);
+            first.done().await.unwrap();
+
+            {
+                let transaction = db.readwrite_transaction();
+                let store = transaction.object_store("test").unwrap();
+                store.put(&"b", &2).await.unwrap();
+            }
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get(&"b").await.unwrap(), Some(2));
+        });
+    }
+
+    #[test]
+    fn snapshot_isolation() {
+        let db = open(&MemoryFactory::new());
+
+        block_on(async {
+            let reader = db.readonly_transaction();
+            let other_reader = db.readonly_transaction();
+            let store = reader.object_store("test").unwrap();
+            let other_store = other_reader.object_store("test").unwrap();
+
+            assert_eq!(store.get::<u32>(&"a").await.unwrap(), None);
+            // Read only transactions don't block each other.
+            assert_eq!(other_store.get::<u32>(&"a").await.unwrap(), None);
+            assert_eq!(store.get::<u32>(&"a").await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn cursor() {
+        let db = open(&MemoryFactory::new());
+
+        block_on(async {
+            let transaction = db.readwrite_transaction();
+            let store = transaction.object_store("test").unwrap();
+
+            store.put(&"b", &2).await.unwrap();
+            store.put(&10, &0).await.unwrap();
+            store.put(&"a", &1).await.unwrap();
+
+            let mut cursor = store.open_cursor().unwrap();
+            assert!(cursor.next().await.unwrap());
+            assert_eq!(cursor.key::<u32>().unwrap(), 10);
+
+            // Writes made while iterating are visible to the cursor.
+            store.put(&"c", &3).await.unwrap();
+
+            let mut entries = Vec::new();
+
+            while cursor.next().await.unwrap() {
+                entries.push((
+                    cursor.key::<String>().unwrap(),
+                    cursor.value::<u32>().unwrap(),
+                ));
+            }
+
+            assert_eq!(
+                entries,
+                vec![
+                    ("a".to_owned(), 1),
+                    ("b".to_owned(), 2),
+                    ("c".to_owned(), 3)
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn shared_factory() {
+        let factory = MemoryFactory::new();
+        let db = open(&factory);
+
+        block_on(async {
+            let transaction = db.readwrite_transaction();
+            let store = transaction.object_store("test").unwrap();
+            store.put(&"a", &1).await.unwrap();
+            transaction.done().await.unwrap();
+
+            let db = factory.clone().open("test", 1, |_, _| ()).await.unwrap();
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get(&"a").await.unwrap(), Some(1));
+
+            factory.delete_database("test").await.unwrap();
+            assert!(factory.database_names().is_empty());
+        });
+    }
+
+    #[test]
+    fn key_ranges() {
+        let db = open(&MemoryFactory::new());
+
+        block_on(async {
+            let transaction = db.readwrite_transaction();
+            let store = transaction.object_store("test").unwrap();
+
+            for i in 0..10 {
+                store.put(&i, &(i * 10)).await.unwrap();
+            }
+
+            let values: Vec<u32> = store
+                .get_all(Some(&KeyRange::try_from(2..5).unwrap()))
+                .await
+                .unwrap();
+            assert_eq!(values, vec![20, 30, 40]);
+            assert_eq!(
+                store
+                    .count(Some(&KeyRange::try_from(..=2).unwrap()))
+                    .await
+                    .unwrap(),
+                3
+            );
+            assert_eq!(store.count(None).await.unwrap(), 10);
+
+            let mut cursor = store
+                .open_cursor_with_range(&KeyRange::lower_bound(&7, true).unwrap())
+                .unwrap();
+            let mut keys = Vec::new();
+
+            while cursor.next().await.unwrap() {
+                keys.push(cursor.key::<u32>().unwrap());
+            }
+
+            assert_eq!(keys, vec![8, 9]);
+
+            let error = store
+                .count(Some(&KeyRange::bound(&5, &2, false, false).unwrap()))
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Data);
+
+            // Maps with non-string keys can't be serialized.
+            let key: BTreeMap<_, _> = vec![((1, 2), 3)].into_iter().collect();
+            let error = Error::from(KeyRange::only(&key).unwrap_err());
+            assert_eq!(error.kind(), ErrorKind::Data);
+        });
+    }
+
+    #[test]
+    fn indexes() {
+        let factory = MemoryFactory::new();
+        let db = block_on(factory.open("users", 1, |_, db| {
+            let store = db.create_object_store("users").unwrap();
+            store.create_index("by_email", "email", true).unwrap();
+            store
+                .create_index("by_city", "address.city", false)
+                .unwrap();
+            assert!(store.create_index("by_city", "city", false).is_err());
+        }))
+        .unwrap();
+
+        block_on(async {
+            let transaction = db.readwrite_transaction();
+            let store = transaction.object_store("users").unwrap();
+
+            let users = [
+                (1, "a@example.com", "Berlin"),
+                (2, "b@example.com", "Paris"),
+                (3, "c@example.com", "Berlin"),
+            ];
+
+            for (id, email, city) in &users {
+                let user = json!({ "email": email, "address": { "city": city } });
+                store.add(id, &user).await.unwrap();
+            }
+
+            // Values without a key at the key path aren't indexed.
+            store.add(&4, &json!({ "name": "d" })).await.unwrap();
+
+            let by_city = store.index("by_city").unwrap();
+            assert_eq!(by_city.count(None).await.unwrap(), 3);
+            assert_eq!(
+                by_city
+                    .count(Some(&KeyRange::only(&"Berlin").unwrap()))
+                    .await
+                    .unwrap(),
+                2
+            );
+
+            let mut cursor = by_city.open_cursor(None).unwrap();
+            let mut entries = Vec::new();
+
+            while cursor.next().await.unwrap() {
+                entries.push((
+                    cursor.key::<String>().unwrap(),
+                    cursor.primary_key::<u32>().unwrap(),
+                ));
+            }
+
+            assert_eq!(
+                entries,
+                vec![
+                    ("Berlin".to_owned(), 1),
+                    ("Berlin".to_owned(), 3),
+                    ("Paris".to_owned(), 2)
+                ]
+            );
+
+            let by_email = store.index("by_email").unwrap();
+            let user: serde_json::Value = by_email.get(&"b@example.com").await.unwrap().unwrap();
+            assert_eq!(user["address"]["city"], "Paris");
+
+            // Updating a value updates its index entries.
+            store
+                .put(
+                    &2,
+                    &json!({ "email": "b@example.com", "address": { "city": "Rome" } }),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                by_city
+                    .count(Some(&KeyRange::only(&"Paris").unwrap()))
+                    .await
+                    .unwrap(),
+                0
+            );
+
+            let error = store
+                .put(&5, &json!({ "email": "a@example.com" }))
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Constraint);
+
+            assert_eq!(
+                store.index("missing").unwrap_err().kind(),
+                ErrorKind::NotFound
+            );
+        });
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+
+        block_on(async {
+            {
+                let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+                let db = factory
+                    .open("users", 1, |_, db| {
+                        let store = db.create_object_store("users").unwrap();
+                        store.create_index("by_name", "name", false).unwrap();
+                        db.create_object_store("removed").unwrap();
+                    })
+                    .await
+                    .unwrap();
+
+                let transaction = db.readwrite_transaction();
+                let store = transaction.object_store("users").unwrap();
+                store.put(&1, &json!({ "name": "a" })).await.unwrap();
+                store.put(&2, &json!({ "name": "b" })).await.unwrap();
+                store.delete(&1).await.unwrap();
+                transaction.done().await.unwrap();
+
+                let transaction = db.readwrite_transaction();
+                let store = transaction.object_store("users").unwrap();
+                store.put(&3, &json!({ "name": "c" })).await.unwrap();
+                transaction.abort().await.unwrap();
+
+                let transaction = db.readwrite_transaction();
+                let store = transaction.object_store("removed").unwrap();
+                store.put(&1, &1).await.unwrap();
+                transaction.done().await.unwrap();
+
+                factory
+                    .open("users", 2, |_, db| {
+                        db.delete_object_store("removed").unwrap();
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+            assert_eq!(factory.database_names(), vec!["users".to_owned()]);
+
+            let db = factory
+                .open("users", 2, |_, _| panic!("No upgrade is needed"))
+                .await
+                .unwrap();
+            assert_eq!(db.object_store_names(), vec!["users".to_owned()]);
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("users").unwrap();
+            assert_eq!(store.count(None).await.unwrap(), 1);
+
+            let user: serde_json::Value = store
+                .index("by_name")
+                .unwrap()
+                .get(&"b")
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(user["name"], "b");
+            drop(store);
+            drop(transaction);
+            drop(db);
+
+            factory.delete_database("users").await.unwrap();
+            drop(factory);
+
+            let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+            assert!(factory.database_names().is_empty());
+        });
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_recreated_store() {
+        let dir = tempfile::tempdir().unwrap();
+
+        block_on(async {
+            {
+                let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+                let db = factory
+                    .open("test", 1, |_, db| {
+                        db.create_object_store("test").unwrap();
+                    })
+                    .await
+                    .unwrap();
+
+                let transaction = db.readwrite_transaction();
+                let store = transaction.object_store("test").unwrap();
+                store.put(&1, &1).await.unwrap();
+                transaction.done().await.unwrap();
+
+                factory
+                    .open("test", 2, |_, db| {
+                        db.delete_object_store("test").unwrap();
+                        db.create_object_store("test").unwrap();
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+            let db = factory.open("test", 2, |_, _| ()).await.unwrap();
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.count(None).await.unwrap(), 0);
+        });
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_renamed_store() {
+        let dir = tempfile::tempdir().unwrap();
+
+        block_on(async {
+            {
+                let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+                let db = factory
+                    .open("test", 1, |_, db| {
+                        db.create_object_store("draft").unwrap();
+                    })
+                    .await
+                    .unwrap();
+
+                let transaction = db.readwrite_transaction();
+                let store = transaction.object_store("draft").unwrap();
+                store.put(&1, &1).await.unwrap();
+                transaction.done().await.unwrap();
+
+                factory
+                    .open("test", 2, |_, db| {
+                        db.object_store("draft").unwrap().rename("users").unwrap();
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+            let db = factory.open("test", 2, |_, _| ()).await.unwrap();
+            assert_eq!(db.object_store_names(), vec!["users".to_owned()]);
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("users").unwrap();
+            assert_eq!(store.get(&1).await.unwrap(), Some(1));
+        });
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_migrated_store() {
+        let dir = tempfile::tempdir().unwrap();
+
+        block_on(async {
+            {
+                let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+                factory
+                    .open("test", 1, |_, db| {
+                        let store = db.create_object_store("test").unwrap();
+                        store.put(&1, &1).now_or_never().unwrap().unwrap();
+                    })
+                    .await
+                    .unwrap();
+
+                factory
+                    .open("test", 2, |_, db| {
+                        let store = db.object_store("test").unwrap();
+                        store.put(&2, &2).now_or_never().unwrap().unwrap();
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let factory = MemoryFactory::with_sled(dir.path()).unwrap();
+            let db = factory.open("test", 2, |_, _| ()).await.unwrap();
+
+            let transaction = db.readonly_transaction();
+            let store = transaction.object_store("test").unwrap();
+            assert_eq!(store.get_all::<u32>(None).await.unwrap(), vec![1, 2]);
+        });
+    }
+}
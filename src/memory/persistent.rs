@@ -0,0 +1,214 @@
+use std::{io, path::Path, thread, time::Duration};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    storage::{Change, Schema, Storage, StoredRecords},
+    Error, ErrorKind,
+};
+
+const TREE_PREFIX: &[u8] = b"database:";
+const SCHEMA_KEY: &[u8] = b"schema";
+const RECORD_PREFIX: &[u8] = b"r";
+const LOCK_ATTEMPTS: u32 = 100;
+
+/// Storage that persists databases in a `sled` database on disk.
+///
+/// Every IndexedDB database is stored in its own tree. The schema is stored
+/// under a fixed key, records under the name of their object store followed
+/// by their key.
+#[derive(Debug)]
+pub(crate) struct SledStorage {
+    db: sled::Db,
+}
+
+fn storage_error(error: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::Unknown, format!("storage error: {}", error))
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    serde_json::to_vec(value).map_err(storage_error)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(bytes).map_err(storage_error)
+}
+
+/// The prefix of the records of an object store.
+///
+/// The store name is encoded as a JSON string, which can't contain a null
+/// byte, so the null byte terminates the name.
+fn store_prefix(store: &str) -> Result<Vec<u8>, Error> {
+    let mut prefix = RECORD_PREFIX.to_vec();
+    prefix.extend(encode(store)?);
+    prefix.push(0);
+
+    Ok(prefix)
+}
+
+/// sled reports a directory that is locked by another instance only through
+/// the message of the error.
+fn is_lock_error(error: &io::Error) -> bool {
+    error.to_string().contains("could not acquire lock")
+}
+
+fn tree_name(database: &str) -> Vec<u8> {
+    [TREE_PREFIX, database.as_bytes()].concat()
+}
+
+impl SledStorage {
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        // Every commit is flushed explicitly, no background flusher needed.
+        let config = sled::Config::new().path(path).flush_every_ms(None);
+        let mut attempts = 0;
+
+        loop {
+            match config.open() {
+                Ok(db) => return Ok(Self { db }),
+                // The background threads of a storage that was just dropped
+                // can hold on to the lock of the directory for a moment.
+                Err(sled::Error::Io(e)) if is_lock_error(&e) && attempts < LOCK_ATTEMPTS => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => return Err(storage_error(e)),
+            }
+        }
+    }
+
+    fn tree(&self, database: &str) -> Result<sled::Tree, Error> {
+        self.db
+            .open_tree(tree_name(database))
+            .map_err(storage_error)
+    }
+
+    fn schema(tree: &sled::Tree) -> Result<Option<Schema>, Error> {
+        tree.get(SCHEMA_KEY)
+            .map_err(storage_error)?
+            .map(|s| decode(&s))
+            .transpose()
+    }
+
+    fn clear_store(tree: &sled::Tree, store: &str, batch: &mut sled::Batch) -> Result<(), Error> {
+        for entry in tree.scan_prefix(store_prefix(store)?) {
+            let (key, _) = entry.map_err(storage_error)?;
+            batch.remove(key);
+        }
+
+        Ok(())
+    }
+
+    fn apply(&self, tree: &sled::Tree, batch: sled::Batch) -> Result<(), Error> {
+        tree.apply_batch(batch).map_err(storage_error)?;
+        tree.flush().map_err(storage_error)?;
+
+        Ok(())
+    }
+}
+
+impl Storage for SledStorage {
+    fn database_names(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+
+        for tree_name in self.db.tree_names() {
+            if let Some(name) = tree_name.strip_prefix(TREE_PREFIX) {
+                let tree = self.db.open_tree(&tree_name).map_err(storage_error)?;
+
+                if Self::schema(&tree)?.is_some() {
+                    names.push(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn load(&self, database: &str) -> Result<Option<(Schema, StoredRecords)>, Error> {
+        let tree = self.tree(database)?;
+
+        let schema = match Self::schema(&tree)? {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        let mut records = StoredRecords::new();
+
+        for store in schema.stores.keys() {
+            let prefix = store_prefix(store)?;
+            let mut store_records = Vec::new();
+
+            for entry in tree.scan_prefix(&prefix) {
+                let (key, value) = entry.map_err(storage_error)?;
+                store_records.push((decode(&key[prefix.len()..])?, decode(&value)?));
+            }
+
+            records.insert(store.clone(), store_records);
+        }
+
+        Ok(Some((schema, records)))
+    }
+
+    fn store_schema(
+        &self,
+        database: &str,
+        schema: &Schema,
+        rewritten: &StoredRecords,
+    ) -> Result<(), Error> {
+        let tree = self.tree(database)?;
+        let mut batch = sled::Batch::default();
+
+        if let Some(old) = Self::schema(&tree)? {
+            for store in old.stores.keys() {
+                if !schema.stores.contains_key(store) && !rewritten.contains_key(store) {
+                    Self::clear_store(&tree, store, &mut batch)?;
+                }
+            }
+        }
+
+        // The batch applies the operations in order, the new records replace
+        // the cleared ones.
+        for (store, records) in rewritten {
+            Self::clear_store(&tree, store, &mut batch)?;
+
+            for (key, value) in records {
+                batch.insert(
+                    [store_prefix(store)?, encode(key)?].concat(),
+                    encode(value)?,
+                );
+            }
+        }
+
+        batch.insert(SCHEMA_KEY, encode(schema)?);
+
+        self.apply(&tree, batch)
+    }
+
+    fn commit(&self, database: &str, changes: &[Change]) -> Result<(), Error> {
+        let tree = self.tree(database)?;
+        let mut batch = sled::Batch::default();
+
+        for change in changes {
+            match change {
+                Change::Put { store, key, value } => {
+                    let record_key = [store_prefix(store)?, encode(key)?].concat();
+                    batch.insert(record_key, encode(value)?);
+                }
+                Change::Delete { store, key } => {
+                    batch.remove([store_prefix(store)?, encode(key)?].concat());
+                }
+                Change::Clear { store } => Self::clear_store(&tree, store, &mut batch)?,
+            }
+        }
+
+        self.apply(&tree, batch)
+    }
+
+    fn delete(&self, database: &str) -> Result<(), Error> {
+        self.db
+            .drop_tree(tree_name(database))
+            .map_err(storage_error)?;
+        self.db.flush().map_err(storage_error)?;
+
+        Ok(())
+    }
+}
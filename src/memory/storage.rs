@@ -0,0 +1,75 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Error, Key};
+use crate::KeyPath;
+
+/// A change of a record made by a committed transaction.
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    Put {
+        store: String,
+        key: Key,
+        value: Value,
+    },
+    Delete {
+        store: String,
+        key: Key,
+    },
+    Clear {
+        store: String,
+    },
+}
+
+/// The schema of a database, its version and the definitions of its object
+/// stores and indexes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Schema {
+    pub(crate) version: u32,
+    pub(crate) stores: BTreeMap<String, BTreeMap<String, IndexSchema>>,
+}
+
+/// The definition of an index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IndexSchema {
+    pub(crate) key_path: KeyPath,
+    pub(crate) unique: bool,
+}
+
+/// The records of a database grouped by object store.
+pub(crate) type StoredRecords = BTreeMap<String, Vec<(Key, Value)>>;
+
+/// Durable storage for the databases of a factory.
+///
+/// The engine keeps the whole content of an opened database in memory, the
+/// storage receives the schema after every upgrade and the changes of every
+/// committed read/write transaction. Both have to be applied atomically.
+pub(crate) trait Storage: fmt::Debug {
+    /// Get the names of the stored databases.
+    fn database_names(&self) -> Result<Vec<String>, Error>;
+
+    /// Load the schema and records of the database with the given name.
+    fn load(&self, database: &str) -> Result<Option<(Schema, StoredRecords)>, Error>;
+
+    /// Store a new schema, the records of object stores that aren't part of
+    /// the schema anymore are removed.
+    ///
+    /// The stored records of the stores in `rewritten` are replaced by the
+    /// given ones. An upgrade rewrites the stores it created or deleted, a
+    /// store that was deleted and created again under the same name must not
+    /// get its old records back.
+    fn store_schema(
+        &self,
+        database: &str,
+        schema: &Schema,
+        rewritten: &StoredRecords,
+    ) -> Result<(), Error>;
+
+    /// Apply the changes of a committed transaction.
+    fn commit(&self, database: &str, changes: &[Change]) -> Result<(), Error>;
+
+    /// Delete the database with the given name.
+    fn delete(&self, database: &str) -> Result<(), Error>;
+}
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, ops::Bound};
+
+use im_rc::{OrdMap, OrdSet};
+use serde_json::Value;
+
+use super::{Error, ErrorKind, Key};
+use crate::{KeyPath, KeyRange};
+
+/// The records and indexes of an object store.
+///
+/// The collections are persistent data structures, cloning them to take a
+/// snapshot is cheap.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StoreData {
+    pub(crate) records: OrdMap<Key, Value>,
+    pub(crate) indexes: BTreeMap<String, IndexData>,
+}
+
+/// The entries of an index, pairs of index keys and primary keys.
+#[derive(Debug, Clone)]
+pub(crate) struct IndexData {
+    pub(crate) key_path: KeyPath,
+    pub(crate) unique: bool,
+    pub(crate) entries: OrdSet<(Key, Key)>,
+}
+
+impl IndexData {
+    pub(crate) fn new(key_path: KeyPath, unique: bool) -> Self {
+        Self {
+            key_path,
+            unique,
+            entries: OrdSet::new(),
+        }
+    }
+
+    /// Is the given index key already used by a record other than the one
+    /// with the given primary key.
+    fn conflicts(&self, index_key: &Key, primary_key: &Key) -> bool {
+        self.entries
+            .range((
+                Bound::Included((index_key.clone(), Key::MIN)),
+                Bound::Unbounded,
+            ))
+            .take_while(|(k, _)| k == index_key)
+            .any(|(_, p)| p != primary_key)
+    }
+}
+
+impl StoreData {
+    /// Store a record, updating all the indexes of the store.
+    ///
+    /// Fails with a `ConstraintError` if `overwrite` is false and the key
+    /// already exists or if a unique index would contain a key twice.
+    pub(crate) fn insert(&mut self, key: Key, value: Value, overwrite: bool) -> Result<(), Error> {
+        if !overwrite && self.records.contains_key(&key) {
+            return Err(Error::new(
+                ErrorKind::Constraint,
+                "a value with the given key already exists",
+            ));
+        }
+
+        let index_keys: Vec<(String, Option<Key>)> = self
+            .indexes
+            .iter()
+            .map(|(name, index)| (name.clone(), extract_key(&value, &index.key_path)))
+            .collect();
+
+        for (name, index_key) in &index_keys {
+            let index = &self.indexes[name];
+
+            if let Some(index_key) = index_key {
+                if index.unique && index.conflicts(index_key, &key) {
+                    return Err(Error::new(
+                        ErrorKind::Constraint,
+                        format!("the unique index \"{}\" already contains the key", name),
+                    ));
+                }
+            }
+        }
+
+        self.remove(&key);
+
+        for (name, index_key) in index_keys {
+            if let Some(index_key) = index_key {
+                if let Some(index) = self.indexes.get_mut(&name) {
+                    index.entries.insert((index_key, key.clone()));
+                }
+            }
+        }
+
+        self.records.insert(key, value);
+
+        Ok(())
+    }
+
+    /// Remove the record with the given key.
+    pub(crate) fn remove(&mut self, key: &Key) {
+        if let Some(value) = self.records.remove(key) {
+            for index in self.indexes.values_mut() {
+                if let Some(index_key) = extract_key(&value, &index.key_path) {
+                    index.entries.remove(&(index_key, key.clone()));
+                }
+            }
+        }
+    }
+
+    /// Remove all the records of the store.
+    pub(crate) fn clear(&mut self) {
+        self.records.clear();
+
+        for index in self.indexes.values_mut() {
+            index.entries.clear();
+        }
+    }
+
+    /// Create an index and fill it with the existing records.
+    pub(crate) fn create_index(
+        &mut self,
+        name: &str,
+        key_path: KeyPath,
+        unique: bool,
+    ) -> Result<(), Error> {
+        if self.indexes.contains_key(name) {
+            return Err(Error::new(
+                ErrorKind::Constraint,
+                format!("an index called \"{}\" already exists", name),
+            ));
+        }
+
+        let mut index = IndexData::new(key_path, unique);
+
+        for (key, value) in self.records.iter() {
+            if let Some(index_key) = extract_key(value, &index.key_path) {
+                if index.unique && index.conflicts(&index_key, key) {
+                    return Err(Error::new(
+                        ErrorKind::Constraint,
+                        format!("the existing records violate the unique index \"{}\"", name),
+                    ));
+                }
+
+                index.entries.insert((index_key, key.clone()));
+            }
+        }
+
+        self.indexes.insert(name.to_owned(), index);
+
+        Ok(())
+    }
+}
+
+/// Extract the key at the given key path out of a value.
+///
+/// Like in IndexedDB, values that don't contain a valid key at the key path
+/// aren't part of the index.
+pub(crate) fn extract_key(value: &Value, key_path: &KeyPath) -> Option<Key> {
+    match key_path {
+        KeyPath::None => None,
+        KeyPath::Single(path) => evaluate(value, path),
+        KeyPath::Multi(paths) => paths
+            .iter()
+            .map(|path| evaluate(value, path))
+            .collect::<Option<_>>()
+            .map(Key::Array),
+    }
+}
+
+fn evaluate(value: &Value, path: &str) -> Option<Key> {
+    let mut value = value;
+
+    if !path.is_empty() {
+        for property in path.split('.') {
+            value = value.get(property)?;
+        }
+    }
+
+    Key::from_json(value).ok()
+}
+
+/// Convert a key range into bounds of keys.
+pub(crate) fn key_bounds(range: &KeyRange) -> Result<(Bound<Key>, Bound<Key>), Error> {
+    let convert = |bound: &Bound<Value>| -> Result<Bound<Key>, Error> {
+        Ok(match bound {
+            Bound::Included(v) => Bound::Included(Key::from_json(v)?),
+            Bound::Excluded(v) => Bound::Excluded(Key::from_json(v)?),
+            Bound::Unbounded => Bound::Unbounded,
+        })
+    };
+
+    let bounds = (convert(&range.lower)?, convert(&range.upper)?);
+
+    if let (
+        Bound::Included(lower) | Bound::Excluded(lower),
+        Bound::Included(upper) | Bound::Excluded(upper),
+    ) = &bounds
+    {
+        let both_closed = matches!(bounds, (Bound::Included(_), Bound::Included(_)));
+
+        if lower > upper || (lower == upper && !both_closed) {
+            return Err(Error::new(
+                ErrorKind::Data,
+                "the lower bound of the key range is above its upper bound",
+            ));
+        }
+    }
+
+    Ok(bounds)
+}
@@ -0,0 +1,775 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    rc::Rc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use super::{
+    storage::Change,
+    store::{key_bounds, IndexData, StoreData},
+    Error, ErrorKind, Key, MemoryDb, SharedDatabase,
+};
+use crate::{
+    storage::AbortHandle,
+    transaction::{Dynamic, Mode, TransactionMode, WriteMode},
+    KeyRange,
+};
+
+type KeyBounds = (Bound<Key>, Bound<Key>);
+
+#[derive(Debug)]
+enum Status {
+    Active,
+    Committed,
+    Aborted(Error),
+}
+
+/// The shared state of a transaction, object stores and cursors of the
+/// transaction hold a reference to it.
+#[derive(Debug)]
+pub(crate) struct TransactionState {
+    id: u64,
+    db: SharedDatabase,
+    mode: TransactionMode,
+    scope: Vec<String>,
+    status: Status,
+    /// The working copies of the stores in the scope of the transaction, taken
+    /// when the transaction makes its first request.
+    stores: Option<BTreeMap<String, StoreData>>,
+    /// The writes of the transaction, recorded if the database is persisted.
+    changes: Vec<Change>,
+}
+
+type SharedTransaction = Rc<RefCell<TransactionState>>;
+
+impl TransactionState {
+    pub(crate) fn new(
+        id: u64,
+        db: SharedDatabase,
+        mode: TransactionMode,
+        scope: Vec<String>,
+    ) -> SharedTransaction {
+        Rc::new(RefCell::new(Self {
+            id,
+            db,
+            mode,
+            scope,
+            status: Status::Active,
+            stores: None,
+            changes: Vec::new(),
+        }))
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(self.status, Status::Active)
+    }
+
+    fn overlaps(&self, other: &TransactionState) -> bool {
+        self.scope.iter().any(|s| other.scope.contains(s))
+    }
+
+    /// Does this transaction need to finish before the other transaction can
+    /// start?
+    fn blocks(&self, other: &TransactionState) -> bool {
+        self.id < other.id
+            && self.is_active()
+            && (self.mode == TransactionMode::ReadWrite || other.mode == TransactionMode::ReadWrite)
+            && self.overlaps(other)
+    }
+
+    pub(crate) fn commit(&mut self) {
+        if !self.is_active() {
+            return;
+        }
+
+        if let (TransactionMode::ReadWrite, Some(stores)) = (self.mode, self.stores.take()) {
+            let db = self.db.clone();
+            let mut db = db.borrow_mut();
+
+            // Upgrades commit the running transactions before they change the
+            // stores, this only guards against losing writes.
+            if let Some(name) = stores.keys().find(|name| !db.stores.contains_key(*name)) {
+                let message = format!("the object store \"{}\" doesn't exist anymore", name);
+                self.abort(Error::new(ErrorKind::InvalidState, message));
+                return;
+            }
+
+            if let Some(storage) = &db.storage {
+                if !self.changes.is_empty() {
+                    if let Err(e) = storage.commit(&db.name, &self.changes) {
+                        self.abort(e);
+                        return;
+                    }
+                }
+            }
+
+            db.stores.extend(stores);
+        }
+
+        self.stores = None;
+        self.changes.clear();
+        self.status = Status::Committed;
+    }
+
+    fn abort(&mut self, error: Error) {
+        self.stores = None;
+        self.changes.clear();
+        self.status = Status::Aborted(error);
+    }
+
+    fn check_active(&self) -> Result<(), Error> {
+        if self.is_active() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::TransactionInactive,
+                "the transaction has finished",
+            ))
+        }
+    }
+}
+
+/// Make sure the transaction has taken its snapshot of the stores in its
+/// scope.
+///
+/// Older transactions that would block this one are committed first, this
+/// emulates the auto-commit that a browser performs once a transaction has no
+/// more pending requests.
+fn start(transaction: &SharedTransaction) -> Result<(), Error> {
+    {
+        let state = transaction.borrow();
+        state.check_active()?;
+
+        if state.stores.is_some() {
+            return Ok(());
+        }
+    }
+
+    let others: Vec<SharedTransaction> = transaction
+        .borrow()
+        .db
+        .borrow()
+        .transactions
+        .iter()
+        .filter_map(|t| t.upgrade())
+        .filter(|t| !Rc::ptr_eq(t, transaction))
+        .collect();
+
+    for other in others {
+        let mut other = other.borrow_mut();
+
+        if other.blocks(&transaction.borrow()) {
+            other.commit();
+        }
+    }
+
+    let mut state = transaction.borrow_mut();
+    let stores = {
+        let db = state.db.borrow();
+        state
+            .scope
+            .iter()
+            .filter_map(|name| db.stores.get(name).map(|s| (name.clone(), s.clone())))
+            .collect()
+    };
+    state.stores = Some(stores);
+
+    Ok(())
+}
+
+fn read<R>(
+    transaction: &SharedTransaction,
+    store: &str,
+    f: impl FnOnce(&StoreData) -> R,
+) -> Result<R, Error> {
+    start(transaction)?;
+
+    let state = transaction.borrow();
+    let data = state
+        .stores
+        .as_ref()
+        .and_then(|s| s.get(store))
+        .ok_or_else(|| not_found(store))?;
+
+    Ok(f(data))
+}
+
+/// Make a write request, the given change is recorded for the storage of the
+/// database once `f` succeeded.
+fn write<R>(
+    transaction: &SharedTransaction,
+    store: &str,
+    change: Change,
+    f: impl FnOnce(&mut StoreData) -> Result<R, Error>,
+) -> Result<R, Error> {
+    start(transaction)?;
+
+    let mut state = transaction.borrow_mut();
+
+    if state.mode == TransactionMode::Readonly {
+        return Err(Error::new(
+            ErrorKind::ReadOnly,
+            "the transaction is read only",
+        ));
+    }
+
+    let data = state
+        .stores
+        .as_mut()
+        .and_then(|s| s.get_mut(store))
+        .ok_or_else(|| not_found(store))?;
+
+    let result = f(data);
+
+    match &result {
+        // A failed request aborts the transaction unless the error is
+        // handled, which this crate doesn't do.
+        Err(e) => state.abort(e.clone()),
+        Ok(_) => {
+            if state.db.borrow().storage.is_some() {
+                state.changes.push(change);
+            }
+        }
+    }
+
+    result
+}
+
+/// Get the bounds of an optional key range, no range contains all keys.
+pub(crate) fn bounds(range: Option<&KeyRange>) -> Result<KeyBounds, Error> {
+    match range {
+        Some(range) => key_bounds(range),
+        None => Ok((Bound::Unbounded, Bound::Unbounded)),
+    }
+}
+
+/// Find the first entry of an index inside of the given bounds, starting
+/// after the given entry.
+fn next_index_entry(
+    index: &IndexData,
+    bounds: &KeyBounds,
+    after: Option<&(Key, Key)>,
+) -> Option<(Key, Key)> {
+    let start = match (after, &bounds.0) {
+        (Some(entry), _) => Bound::Excluded(entry.clone()),
+        (None, Bound::Included(key)) | (None, Bound::Excluded(key)) => {
+            Bound::Included((key.clone(), Key::MIN))
+        }
+        (None, Bound::Unbounded) => Bound::Unbounded,
+    };
+
+    index
+        .entries
+        .range((start, Bound::Unbounded))
+        .find(|(key, _)| match &bounds.0 {
+            Bound::Excluded(lower) => key > lower,
+            _ => true,
+        })
+        .filter(|(key, _)| bounds.contains(key))
+        .cloned()
+}
+
+/// Get the entries of an index inside of the given bounds, pairs of primary
+/// keys and values.
+fn index_entries<'d>(
+    data: &'d StoreData,
+    index: &str,
+    bounds: &KeyBounds,
+) -> Result<Vec<(Key, &'d Value)>, Error> {
+    let index = data
+        .indexes
+        .get(index)
+        .ok_or_else(|| index_not_found(index))?;
+    let mut entries = Vec::new();
+    let mut current = next_index_entry(index, bounds, None);
+
+    while let Some(entry) = current {
+        if let Some(value) = data.records.get(&entry.1) {
+            entries.push((entry.1.clone(), value));
+        }
+
+        current = next_index_entry(index, bounds, Some(&entry));
+    }
+
+    Ok(entries)
+}
+
+fn not_found(store: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("no object store called \"{}\" in the transaction", store),
+    )
+}
+
+fn index_not_found(index: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("no index called \"{}\" in the object store", index),
+    )
+}
+
+pub(crate) fn to_value<V: Serialize + ?Sized>(value: &V) -> Result<Value, Error> {
+    serde_json::to_value(value)
+        .map_err(|e| Error::new(ErrorKind::Data, format!("can't serialize value: {}", e)))
+}
+
+pub(crate) fn from_value<V: DeserializeOwned>(value: Value) -> Result<V, Error> {
+    serde_json::from_value(value)
+        .map_err(|e| Error::new(ErrorKind::Data, format!("can't deserialize value: {}", e)))
+}
+
+/// A transaction of an in-memory database.
+///
+/// Like [`Transaction`], the mode `M` of the transaction decides if the object
+/// stores of the transaction allow writes. The transaction is committed when
+/// it's dropped.
+///
+/// [`Transaction`]: crate::Transaction
+#[derive(Debug)]
+pub struct MemoryTransaction<'a, M: Mode = Dynamic> {
+    state: SharedTransaction,
+    db: PhantomData<&'a MemoryDb>,
+    mode: PhantomData<M>,
+}
+
+impl<'a, M: Mode> MemoryTransaction<'a, M> {
+    pub(crate) fn new(state: SharedTransaction) -> Self {
+        Self {
+            state,
+            db: PhantomData,
+            mode: PhantomData,
+        }
+    }
+
+    /// Get the object store with the given name.
+    ///
+    /// Fails with a `NotFoundError` if the store isn't part of the scope of
+    /// the transaction.
+    pub fn object_store(&self, name: &str) -> Result<MemoryObjectStore<'_, M>, Error> {
+        if !self.state.borrow().scope.iter().any(|s| s == name) {
+            return Err(not_found(name));
+        }
+
+        Ok(MemoryObjectStore {
+            transaction: self.state.clone(),
+            name: name.to_owned(),
+            lifetime: PhantomData,
+        })
+    }
+
+    /// Commit the transaction and wait for it to be done.
+    ///
+    /// Fails with the error that aborted the transaction, if it was aborted.
+    pub async fn done(self) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        state.commit();
+
+        match &state.status {
+            Status::Aborted(e) => Err(e.clone()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Abort the transaction cancelling all the writes that were done using
+    /// this transaction.
+    pub async fn abort(self) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        state
+            .check_active()
+            .map_err(|_| Error::new(ErrorKind::InvalidState, "the transaction has finished"))?;
+        state.abort(Error::new(ErrorKind::Abort, "the transaction was aborted"));
+
+        Ok(())
+    }
+
+    /// Get a handle that aborts the transaction without consuming it.
+    pub(crate) fn abort_handle(&self) -> AbortHandle {
+        let state = Rc::downgrade(&self.state);
+
+        AbortHandle::new(move || {
+            if let Some(state) = state.upgrade() {
+                let mut state = state.borrow_mut();
+
+                if state.is_active() {
+                    state.abort(Error::new(ErrorKind::Abort, "the transaction was aborted"));
+                }
+            }
+        })
+    }
+}
+
+impl<'a, M: Mode> Drop for MemoryTransaction<'a, M> {
+    fn drop(&mut self) {
+        self.state.borrow_mut().commit();
+    }
+}
+
+/// An object store of an in-memory database that is bound to a transaction.
+#[derive(Debug)]
+pub struct MemoryObjectStore<'a, M: Mode = Dynamic> {
+    transaction: SharedTransaction,
+    name: String,
+    lifetime: PhantomData<&'a MemoryTransaction<'a, M>>,
+}
+
+impl<'a, M: Mode> MemoryObjectStore<'a, M> {
+    /// The name of the object store.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Get the value with the given key.
+    pub async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, Error> {
+        let key = Key::from_serialize(key)?;
+
+        read(&self.transaction, &self.name, |data| {
+            data.records.get(&key).cloned()
+        })?
+        .map(from_value)
+        .transpose()
+    }
+
+    /// Get all the values with keys inside of the given range in key order,
+    /// all the values of the store if no range is given.
+    pub async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, Error> {
+        let bounds = bounds(range)?;
+
+        read(&self.transaction, &self.name, |data| {
+            data.records
+                .range(bounds)
+                .map(|(_, value)| from_value(value.clone()))
+                .collect()
+        })?
+    }
+
+    /// Count the values with keys inside of the given range, all the values
+    /// of the store if no range is given.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<u32, Error> {
+        let bounds = bounds(range)?;
+
+        read(&self.transaction, &self.name, |data| {
+            data.records.range(bounds).count() as u32
+        })
+    }
+
+    /// Get the index with the given name.
+    ///
+    /// Fails with a `NotFoundError` if the store has no such index.
+    pub fn index(&self, name: &str) -> Result<MemoryIndex<'_>, Error> {
+        let exists = {
+            let state = self.transaction.borrow();
+            let db = state.db.borrow();
+            db.stores
+                .get(&self.name)
+                .is_some_and(|s| s.indexes.contains_key(name))
+        };
+
+        if !exists {
+            return Err(index_not_found(name));
+        }
+
+        Ok(MemoryIndex {
+            transaction: self.transaction.clone(),
+            store: self.name.clone(),
+            name: name.to_owned(),
+            lifetime: PhantomData,
+        })
+    }
+
+    /// Open a cursor that iterates over all the entries of the store in key
+    /// order.
+    pub fn open_cursor(&self) -> Result<MemoryCursor<'_>, Error> {
+        self.cursor(None, None)
+    }
+
+    /// Open a cursor that iterates over the entries of the store with keys
+    /// inside of the given range in key order.
+    pub fn open_cursor_with_range(&self, range: &KeyRange) -> Result<MemoryCursor<'_>, Error> {
+        self.cursor(None, Some(range))
+    }
+
+    fn cursor(
+        &self,
+        index: Option<&str>,
+        range: Option<&KeyRange>,
+    ) -> Result<MemoryCursor<'_>, Error> {
+        self.transaction.borrow().check_active()?;
+
+        Ok(MemoryCursor {
+            transaction: self.transaction.clone(),
+            store: self.name.clone(),
+            index: index.map(ToOwned::to_owned),
+            bounds: bounds(range)?,
+            current: None,
+            done: false,
+            lifetime: PhantomData,
+        })
+    }
+}
+
+impl<'a, M: WriteMode> MemoryObjectStore<'a, M> {
+    /// Add the given value under the given key to the object store.
+    ///
+    /// Fails with a `ConstraintError` if a value with the given key already
+    /// exists in the store, this aborts the transaction.
+    pub async fn add<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), Error> {
+        let key = Key::from_serialize(key)?;
+        let value = to_value(value)?;
+
+        let change = self.put_change(&key, &value);
+
+        write(&self.transaction, &self.name, change, |data| {
+            data.insert(key, value, false)
+        })
+    }
+
+    /// Store the given value under the given key in the object store,
+    /// replacing any existing value.
+    pub async fn put<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), Error> {
+        let key = Key::from_serialize(key)?;
+        let value = to_value(value)?;
+
+        let change = self.put_change(&key, &value);
+
+        write(&self.transaction, &self.name, change, |data| {
+            data.insert(key, value, true)
+        })
+    }
+
+    /// Delete the value with the given key from the object store.
+    pub async fn delete(&self, key: &impl Serialize) -> Result<(), Error> {
+        let key = Key::from_serialize(key)?;
+
+        let change = Change::Delete {
+            store: self.name.clone(),
+            key: key.clone(),
+        };
+
+        write(&self.transaction, &self.name, change, |data| {
+            data.remove(&key);
+            Ok(())
+        })
+    }
+
+    /// Delete all the values of the object store.
+    pub async fn clear(&self) -> Result<(), Error> {
+        let change = Change::Clear {
+            store: self.name.clone(),
+        };
+
+        write(&self.transaction, &self.name, change, |data| {
+            data.clear();
+            Ok(())
+        })
+    }
+
+    fn put_change(&self, key: &Key, value: &Value) -> Change {
+        Change::Put {
+            store: self.name.clone(),
+            key: key.clone(),
+            value: value.clone(),
+        }
+    }
+}
+
+/// An index of an in-memory object store.
+///
+/// Indexes are created using
+/// [`MemoryObjectStoreDuringUpgrade::create_index`], the entries of an index
+/// are ordered by the index key and then by the primary key.
+///
+/// [`MemoryObjectStoreDuringUpgrade::create_index`]: super::MemoryObjectStoreDuringUpgrade::create_index
+#[derive(Debug)]
+pub struct MemoryIndex<'a> {
+    transaction: SharedTransaction,
+    store: String,
+    name: String,
+    lifetime: PhantomData<&'a ()>,
+}
+
+impl<'a> MemoryIndex<'a> {
+    /// The name of the index.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Get the first value with the given index key.
+    pub async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, Error> {
+        let key = Key::from_serialize(key)?;
+        let bounds = (Bound::Included(key.clone()), Bound::Included(key));
+
+        read(&self.transaction, &self.store, |data| {
+            Ok::<_, Error>(
+                index_entries(data, &self.name, &bounds)?
+                    .first()
+                    .map(|(_, value)| (*value).clone()),
+            )
+        })??
+        .map(from_value)
+        .transpose()
+    }
+
+    /// Get all the values with index keys inside of the given range, all the
+    /// values of the index if no range is given.
+    pub async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, Error> {
+        let bounds = bounds(range)?;
+
+        read(&self.transaction, &self.store, |data| {
+            index_entries(data, &self.name, &bounds)?
+                .into_iter()
+                .map(|(_, value)| from_value(value.clone()))
+                .collect()
+        })?
+    }
+
+    /// Count the values with index keys inside of the given range, all the
+    /// values of the index if no range is given.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<u32, Error> {
+        let bounds = bounds(range)?;
+
+        read(&self.transaction, &self.store, |data| {
+            Ok(index_entries(data, &self.name, &bounds)?.len() as u32)
+        })?
+    }
+
+    /// Open a cursor that iterates over the entries of the index with index
+    /// keys inside of the given range, all the entries of the index if no
+    /// range is given.
+    pub fn open_cursor(&self, range: Option<&KeyRange>) -> Result<MemoryCursor<'_>, Error> {
+        self.transaction.borrow().check_active()?;
+
+        Ok(MemoryCursor {
+            transaction: self.transaction.clone(),
+            store: self.store.clone(),
+            index: Some(self.name.clone()),
+            bounds: bounds(range)?,
+            current: None,
+            done: false,
+            lifetime: PhantomData,
+        })
+    }
+}
+
+/// An entry a cursor points to, its key, primary key and value.
+type CursorEntry = (Key, Key, Value);
+
+/// A cursor iterating over the entries of an in-memory object store or index.
+///
+/// Like in IndexedDB the cursor sees the writes that were made by its
+/// transaction while iterating.
+#[derive(Debug)]
+pub struct MemoryCursor<'a> {
+    transaction: SharedTransaction,
+    store: String,
+    index: Option<String>,
+    bounds: KeyBounds,
+    current: Option<CursorEntry>,
+    done: bool,
+    lifetime: PhantomData<&'a ()>,
+}
+
+impl<'a> MemoryCursor<'a> {
+    /// Move the cursor to the next entry.
+    ///
+    /// Returns `false` if there are no more entries left.
+    pub async fn next(&mut self) -> Result<bool, Error> {
+        if self.done {
+            return Ok(false);
+        }
+
+        let after = self
+            .current
+            .as_ref()
+            .map(|(key, primary_key, _)| (key.clone(), primary_key.clone()));
+
+        self.current = read(&self.transaction, &self.store, |data| match &self.index {
+            Some(name) => {
+                let index = data
+                    .indexes
+                    .get(name)
+                    .ok_or_else(|| index_not_found(name))?;
+                let mut after = after;
+
+                // Skip entries whose record was deleted by the transaction.
+                while let Some(entry) = next_index_entry(index, &self.bounds, after.as_ref()) {
+                    if let Some(value) = data.records.get(&entry.1) {
+                        return Ok::<_, Error>(Some((entry.0, entry.1, value.clone())));
+                    }
+
+                    after = Some(entry);
+                }
+
+                Ok(None)
+            }
+            None => {
+                let lower = match after {
+                    Some((key, _)) => Bound::Excluded(key),
+                    None => self.bounds.0.clone(),
+                };
+
+                Ok(data
+                    .records
+                    .range((lower, Bound::Unbounded))
+                    .next()
+                    .filter(|(k, _)| self.bounds.contains(*k))
+                    .map(|(k, v)| (k.clone(), k.clone(), v.clone())))
+            }
+        })??;
+
+        self.done = self.current.is_none();
+
+        Ok(!self.done)
+    }
+
+    /// The key of the current entry, the index key for cursors of an index.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn key<K: DeserializeOwned>(&self) -> Result<K, Error> {
+        self.current().0.deserialize()
+    }
+
+    /// The primary key of the current entry.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn primary_key<K: DeserializeOwned>(&self) -> Result<K, Error> {
+        self.current().1.deserialize()
+    }
+
+    /// The value of the current entry.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn value<V: DeserializeOwned>(&self) -> Result<V, Error> {
+        from_value(self.current().2.clone())
+    }
+
+    fn current(&self) -> &CursorEntry {
+        self.current
+            .as_ref()
+            .expect("the cursor doesn't point to an entry")
+    }
+}
@@ -0,0 +1,388 @@
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{
+    db::IndexedDb,
+    export::{open_with_schema, Header, CHUNK_SIZE},
+    factory::Factory,
+    locks::{LockMode, Locks},
+    object_store::KeyPath,
+    request::IndexedDbRequest,
+    transaction::TransactionMode,
+};
+
+/// The database that keeps track of the copies in progress.
+const JOURNAL_DATABASE: &str = "indexeddb-migrations";
+const JOURNAL_STORE: &str = "copies";
+
+/// How far a copy got, stored in the journal after every chunk.
+///
+/// The progress is stored as a JavaScript object, keys like dates or binary
+/// keys can't be represented in serde's data model.
+#[derive(Debug, Clone, Default)]
+struct Progress {
+    /// The index of the object store that is being copied.
+    store: u32,
+    /// The key of the last entry that was copied.
+    last_key: Option<JsValue>,
+    /// The copy is complete and the source is being deleted.
+    deleting: bool,
+}
+
+impl Progress {
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let progress = js_sys::Object::new();
+        let last_key = self.last_key.clone().unwrap_or(JsValue::UNDEFINED);
+
+        js_sys::Reflect::set(&progress, &"store".into(), &self.store.into())?;
+        js_sys::Reflect::set(&progress, &"lastKey".into(), &last_key)?;
+        js_sys::Reflect::set(&progress, &"deleting".into(), &self.deleting.into())?;
+
+        Ok(progress.into())
+    }
+
+    fn from_js(progress: &JsValue) -> Result<Self, JsValue> {
+        let last_key = js_sys::Reflect::get(progress, &"lastKey".into())?;
+
+        Ok(Self {
+            store: js_sys::Reflect::get(progress, &"store".into())?
+                .as_f64()
+                .unwrap_or_default() as u32,
+            last_key: Some(last_key).filter(|key| !key.is_undefined()),
+            deleting: js_sys::Reflect::get(progress, &"deleting".into())?.is_truthy(),
+        })
+    }
+}
+
+struct Journal {
+    db: IndexedDb,
+    key: (String, String),
+}
+
+impl Journal {
+    async fn open(factory: &Factory, from: &str, to: &str) -> Result<Self, JsValue> {
+        let db = factory
+            .open(JOURNAL_DATABASE, 1, |_, db| {
+                db.create_object_store(JOURNAL_STORE)
+                    .expect("Couldn't create the journal store");
+            })
+            .await?;
+
+        Ok(Self {
+            db,
+            key: (from.to_owned(), to.to_owned()),
+        })
+    }
+
+    async fn get(&self) -> Result<Option<Progress>, JsValue> {
+        let transaction = self.db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store(JOURNAL_STORE)?;
+
+        match store.get_raw(&self.key).await? {
+            Some(progress) => Progress::from_js(&progress).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, progress: &Progress) -> Result<(), JsValue> {
+        let transaction = self.db.transaction(TransactionMode::ReadWrite);
+        transaction
+            .object_store(JOURNAL_STORE)?
+            .put_raw(&self.key, &progress.to_js()?)
+            .await?;
+
+        transaction.done().await
+    }
+
+    async fn remove(&self) -> Result<(), JsValue> {
+        let transaction = self.db.transaction(TransactionMode::ReadWrite);
+        transaction
+            .object_store(JOURNAL_STORE)?
+            .delete(&self.key)
+            .await?;
+
+        transaction.done().await
+    }
+}
+
+impl Factory {
+    /// Copy the database `from` into a new database called `to`.
+    ///
+    /// The object stores and indexes are recreated in `to` at the version of
+    /// `from`, the entries are then copied in chunks using one transaction
+    /// per chunk. Once all the entries are copied the number of entries of
+    /// every store is compared. If they don't match the copy fails and `to`
+    /// is deleted again, calling this method again starts over.
+    ///
+    /// The progress is recorded in the `indexeddb-migrations` database. If
+    /// the copy is interrupted, e.g. because the tab was closed, calling this
+    /// method again with the same names resumes it. Copies and renames that
+    /// involve the same databases, as source or as target, are serialized
+    /// using [`Locks`] locks.
+    ///
+    /// Fails if `from` doesn't exist or if `to` already exists and isn't the
+    /// target of an interrupted copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The name of the database that is copied.
+    ///
+    /// * `to` - The name of the copy.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::Factory;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let factory = Factory::new().expect("IndexedDB isn't available");
+    ///
+    /// factory.copy_database("app", "app-backup").await.unwrap();
+    /// # });
+    /// ```
+    pub async fn copy_database(&self, from: &str, to: &str) -> Result<(), JsValue> {
+        self.migrate(from, to, false).await
+    }
+
+    /// Rename the database `from` to `to`.
+    ///
+    /// IndexedDB can't rename databases, so the database is copied like by
+    /// [`Factory::copy_database`] and `from` is deleted once the copy was
+    /// verified. An interrupted rename is resumed by calling this method
+    /// again, also if it was interrupted while deleting `from`.
+    ///
+    /// Connections to `from` need to be closed for it to be deleted, see
+    /// [`Factory::delete_database`].
+    pub async fn rename_database(&self, from: &str, to: &str) -> Result<(), JsValue> {
+        self.migrate(from, to, true).await
+    }
+
+    async fn migrate(&self, from: &str, to: &str, delete_source: bool) -> Result<(), JsValue> {
+        if from == to {
+            return Err("can't copy a database onto itself".into());
+        }
+
+        // Both databases are locked, always in the same order so migrations
+        // in opposite directions can't deadlock.
+        let mut names = [from, to];
+        names.sort_unstable();
+        let [first, second] = names.map(|name| format!("indexeddb-migration:{}", name));
+
+        let locks = Locks::new().await?;
+
+        locks
+            .with_lock(&first, LockMode::Exclusive, || async {
+                locks
+                    .with_lock(&second, LockMode::Exclusive, || async {
+                        self.migrate_locked(from, to, delete_source).await
+                    })
+                    .await
+            })
+            .await??
+    }
+
+    async fn migrate_locked(
+        &self,
+        from: &str,
+        to: &str,
+        delete_source: bool,
+    ) -> Result<(), JsValue> {
+        let journal = Journal::open(self, from, to).await?;
+        let progress = journal.get().await?;
+
+        if progress.as_ref().is_some_and(|progress| progress.deleting) {
+            self.delete_database(from).await?;
+            return journal.remove().await;
+        }
+
+        let source = self
+            .open_existing(from)
+            .await?
+            .ok_or_else(|| format!("the database \"{}\" doesn't exist", from))?;
+
+        let progress = match progress {
+            Some(progress) => progress,
+            None => {
+                if let Some(target) = self.open_existing(to).await? {
+                    target.close();
+                    source.close();
+                    return Err(format!("the database \"{}\" already exists", to).into());
+                }
+
+                let progress = Progress::default();
+                journal.put(&progress).await?;
+                progress
+            }
+        };
+
+        let header = source.schema()?;
+        let target =
+            open_with_schema(self, to, header.database_version as u32, &header.stores).await;
+
+        let result = match target {
+            Ok(target) => {
+                let result = match copy(&source, &target, &header, &journal, progress).await {
+                    Ok(()) => mismatch(&source, &target, &header).await,
+                    Err(e) => Err(e),
+                };
+                target.close();
+                result
+            }
+            Err(e) => Err(e),
+        };
+
+        source.close();
+
+        // Resuming a copy that doesn't match its source wouldn't fix it, the
+        // next attempt starts over.
+        if let Some(mismatch) = result? {
+            journal.remove().await?;
+            self.delete_database(to).await?;
+            return Err(mismatch.into());
+        }
+
+        if delete_source {
+            journal
+                .put(&Progress {
+                    deleting: true,
+                    ..Progress::default()
+                })
+                .await?;
+            self.delete_database(from).await?;
+        }
+
+        journal.remove().await
+    }
+}
+
+async fn copy(
+    source: &IndexedDb,
+    target: &IndexedDb,
+    header: &Header,
+    journal: &Journal,
+    mut progress: Progress,
+) -> Result<(), JsValue> {
+    while let Some(schema) = header.stores.get(progress.store as usize) {
+        let transaction = source.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store(&schema.name)?;
+        let store = store.as_raw();
+
+        let range = match &progress.last_key {
+            Some(key) => web_sys::IdbKeyRange::lower_bound_with_open(key, true)?.into(),
+            None => JsValue::UNDEFINED,
+        };
+        let keys = store.get_all_keys_with_key_and_limit(&range, CHUNK_SIZE)?;
+        let values = store.get_all_with_key_and_limit(&range, CHUNK_SIZE)?;
+
+        let keys: js_sys::Array = IndexedDbRequest::new(keys).await?.unchecked_into();
+        let values: js_sys::Array = IndexedDbRequest::new(values).await?.unchecked_into();
+        transaction.done().await?;
+
+        if keys.length() > 0 {
+            let transaction = target.transaction(TransactionMode::ReadWrite);
+            let store = transaction.as_raw().object_store(&schema.name)?;
+
+            // Failed requests abort the transaction, `done` reports them.
+            for (key, value) in keys.iter().zip(values.iter()) {
+                if schema.key_path == KeyPath::None {
+                    store.put_with_key(&value, &key)?;
+                } else {
+                    store.put(&value)?;
+                }
+            }
+
+            transaction.done().await?;
+        }
+
+        // Entries are put, copying a chunk again after an interruption
+        // doesn't change the result.
+        if keys.length() < CHUNK_SIZE {
+            progress.store += 1;
+            progress.last_key = None;
+        } else {
+            progress.last_key = Some(keys.get(keys.length() - 1));
+        }
+
+        journal.put(&progress).await?;
+    }
+
+    Ok(())
+}
+
+/// Compare the number of entries of every store of the copy with the source,
+/// returns a description of the first difference.
+async fn mismatch(
+    source: &IndexedDb,
+    target: &IndexedDb,
+    header: &Header,
+) -> Result<Option<String>, JsValue> {
+    let source_transaction = source.transaction(TransactionMode::Readonly);
+    let target_transaction = target.transaction(TransactionMode::Readonly);
+
+    for schema in &header.stores {
+        let expected = source_transaction
+            .object_store(&schema.name)?
+            .count(None)
+            .await?;
+        let actual = target_transaction
+            .object_store(&schema.name)?
+            .count(None)
+            .await?;
+
+        if expected != actual {
+            return Ok(Some(format!(
+                "the copy of the store \"{}\" has {} instead of {} entries",
+                schema.name, actual, expected
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Factory, IndexedDb, TransactionMode};
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn rename() {
+        let factory = Factory::new().unwrap();
+        factory.delete_database("rename_from").await.unwrap();
+        factory.delete_database("rename_to").await.unwrap();
+
+        let db = IndexedDb::open("rename_from", 2, |_, db| {
+            let store = db.create_object_store("test").unwrap();
+            store.create_index("by_value", "value", false).unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+
+        for i in 0..1100u32 {
+            store
+                .put(&i, &serde_json::json!({ "value": i }))
+                .await
+                .unwrap();
+        }
+
+        transaction.done().await.unwrap();
+        db.close();
+
+        factory
+            .rename_database("rename_from", "rename_to")
+            .await
+            .unwrap();
+
+        let db = IndexedDb::open("rename_to", 2, |_, _| {}).await.unwrap();
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("test").unwrap();
+        assert_eq!(store.count(None).await.unwrap(), 1100);
+        assert!(store.index("by_value").is_ok());
+        db.close();
+
+        // The source was deleted, copying it again fails.
+        assert!(factory.copy_database("rename_from", "other").await.is_err());
+    }
+}
@@ -1,20 +1,147 @@
-use std::{marker::PhantomData, ops::Deref};
+use std::{marker::PhantomData, ops::Deref, rc::Rc};
 
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::IdbRequest;
 
-use crate::{db::DbDuringUpgrade, request::IndexedDbRequest, transaction::Transaction};
+use crate::{
+    change_feed::{ChangeKind, ChangeLog},
+    codec::{serialize_key, Codec, Decode, Encode, Json, Raw},
+    cursor::Cursor,
+    db::DbDuringUpgrade,
+    index::Index,
+    key_range::{query, KeyRange},
+    quota::approximate_size,
+    request::{IndexedDbRequest, PendingRequest},
+    transaction::{Dynamic, Mode, Transaction, VersionChange, WriteMode},
+};
 
 /// An object store that was created during an upgrade.
 ///
 /// Object stores can only be created and deleted during database upgrades.
+///
+/// The store belongs to the `versionchange` transaction of the upgrade, its
+/// entries can be read and written like the ones of a
+/// [`TransactionObjectStore`].
 #[derive(Debug)]
 pub struct ObjectStoreDuringUpgrade<'a> {
-    pub(crate) inner: ObjectStore,
+    pub(crate) inner: TransactionObjectStore<'a, VersionChange>,
     pub(crate) db: &'a DbDuringUpgrade,
 }
 
 impl<'a> ObjectStoreDuringUpgrade<'a> {
+    pub(crate) fn new(inner: web_sys::IdbObjectStore, db: &'a DbDuringUpgrade) -> Self {
+        Self {
+            inner: TransactionObjectStore {
+                inner: ObjectStore::new(inner),
+                changes: None,
+                transaction: PhantomData,
+            },
+            db,
+        }
+    }
+
+    /// Create a new index on this object store.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the index.
+    ///
+    /// * `key_path` - The path to the key of the index inside of the stored
+    ///   values, values without a valid key at the path aren't indexed.
+    ///
+    /// * `unique` - Should the index reject values with an index key that is
+    ///   already used by another value.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let db = IndexedDb::open("users", 1, |_, db| {
+    ///     let store = db.create_object_store("users").unwrap();
+    ///     store.create_index("by_email", "email", true).unwrap();
+    /// }).await .expect("Failed to open indexed DB");
+    /// # });
+    /// ```
+    pub fn create_index(
+        &self,
+        name: &str,
+        key_path: impl Into<KeyPath>,
+        unique: bool,
+    ) -> Result<(), JsValue> {
+        let parameters = web_sys::IdbIndexParameters::new();
+        parameters.set_unique(unique);
+
+        match key_path.into() {
+            KeyPath::None => return Err("an index needs a key path".into()),
+            KeyPath::Single(path) => {
+                self.as_raw()
+                    .create_index_with_str_and_optional_parameters(name, &path, &parameters)?;
+            }
+            key_path @ KeyPath::Multi(_) => {
+                self.as_raw()
+                    .create_index_with_str_sequence_and_optional_parameters(
+                        name,
+                        &key_path.into(),
+                        &parameters,
+                    )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete the index with the given name.
+    pub fn delete_index(&self, name: &str) -> Result<(), JsValue> {
+        self.as_raw().delete_index(name)
+    }
+
+    /// Get the names of the indexes of this object store.
+    pub fn index_names(&self) -> Vec<String> {
+        to_collection!(self.as_raw().index_names() => Vec<String> : push)
+    }
+
+    /// Rename this object store.
+    ///
+    /// Fails with a `ConstraintError` if another store with the new name
+    /// already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_name` - The name the object store should have from now on.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::IndexedDb;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let db = IndexedDb::open("test", 1, |_, db| {
+    ///     let store = db.create_object_store("draft").unwrap();
+    ///     store.rename("users").unwrap();
+    /// }).await .expect("Failed to open indexed DB");
+    ///
+    /// assert_eq!(db.object_store_names(), vec!["users"]);
+    /// # });
+    /// ```
+    pub fn rename(&self, new_name: &str) -> Result<(), JsValue> {
+        // The setter of `web_sys` doesn't report the errors of the rename.
+        js_sys::Reflect::set(self.as_raw(), &"name".into(), &new_name.into())?;
+        Ok(())
+    }
+
+    /// Rename the index with the given name.
+    ///
+    /// Fails with a `ConstraintError` if another index of this store already
+    /// has the new name.
+    pub fn rename_index(&self, name: &str, new_name: &str) -> Result<(), JsValue> {
+        let index = self.as_raw().index(name)?;
+        js_sys::Reflect::set(&index, &"name".into(), &new_name.into())?;
+        Ok(())
+    }
+
     /// Delete this object store.
     pub fn delete(self) -> Result<(), JsValue> {
         self.db.delete_object_store(&self.name())
@@ -22,7 +149,7 @@ impl<'a> ObjectStoreDuringUpgrade<'a> {
 }
 
 impl<'a> Deref for ObjectStoreDuringUpgrade<'a> {
-    type Target = ObjectStore;
+    type Target = TransactionObjectStore<'a, VersionChange>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
@@ -30,14 +157,306 @@ impl<'a> Deref for ObjectStoreDuringUpgrade<'a> {
 }
 
 /// An object store that is bound to a transaction.
+///
+/// Methods that write to the store are only available if the mode `M` of the
+/// transaction allows writes. Values are converted using the codec `C`.
 #[derive(Debug)]
-pub struct TransactionObjectStore<'a> {
-    pub(crate) inner: ObjectStore,
-    pub(crate) transaction: PhantomData<&'a Transaction<'a>>,
+pub struct TransactionObjectStore<'a, M: Mode = Dynamic, C: Codec = Json> {
+    pub(crate) inner: ObjectStore<C>,
+    pub(crate) changes: Option<Rc<ChangeLog>>,
+    pub(crate) transaction: PhantomData<&'a Transaction<'a, M>>,
+}
+
+impl<'a, M: WriteMode, C: Codec> TransactionObjectStore<'a, M, C> {
+    /// Add the given value under the given key to the object store.
+    ///
+    /// Fails with a `ConstraintError` if a value with the given key already
+    /// exists in the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that should be used to save the associated value in
+    ///   the store.
+    ///
+    /// * `value` - The value that should saved in the store.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// let key = "Hello".to_owned();
+    /// let value = "world".to_owned();
+    ///
+    /// store.add(&key, &value).await.unwrap();
+    /// transaction.done().await;
+    ///
+    /// # });
+    /// ```
+    pub async fn add<V: ?Sized>(&self, key: &impl Serialize, value: &V) -> Result<(), JsValue>
+    where
+        C: Encode<V>,
+    {
+        let key = serialize_key(key)?;
+        let value = C::encode(value)?;
+
+        wait(self.add_request(key, &value)?).await
+    }
+
+    /// Store the given value under the given key in the object store,
+    /// replacing any existing value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that should be used to save the associated value in
+    ///   the store.
+    ///
+    /// * `value` - The value that should saved in the store.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// store.put(&"Hello", &"world").await.unwrap();
+    /// store.put(&"Hello", &"again").await.unwrap();
+    /// transaction.done().await;
+    /// # });
+    /// ```
+    pub async fn put<V: ?Sized>(&self, key: &impl Serialize, value: &V) -> Result<(), JsValue>
+    where
+        C: Encode<V>,
+    {
+        let key = serialize_key(key)?;
+        let value = C::encode(value)?;
+
+        wait(self.put_request(key, &value)?).await
+    }
+
+    /// Add the given JavaScript value under the given key to the object store
+    /// bypassing the codec of the store.
+    ///
+    /// Fails with a `ConstraintError` if a value with the given key already
+    /// exists in the store.
+    pub async fn add_raw(&self, key: &impl Serialize, value: &JsValue) -> Result<(), JsValue> {
+        wait(self.add_request(serialize_key(key)?, value)?).await
+    }
+
+    /// Store the given JavaScript value under the given key in the object
+    /// store bypassing the codec of the store.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// let blob = web_sys::Blob::new().unwrap();
+    /// store.put_raw(&"attachment", &blob).await.unwrap();
+    /// transaction.done().await;
+    /// # });
+    /// ```
+    pub async fn put_raw(&self, key: &impl Serialize, value: &JsValue) -> Result<(), JsValue> {
+        wait(self.put_request(serialize_key(key)?, value)?).await
+    }
+
+    /// Store the given `Blob` under the given key in the object store.
+    ///
+    /// Browsers store blobs without copying their content into wasm memory,
+    /// which makes them the preferred way to store large binary values.
+    pub async fn put_blob(
+        &self,
+        key: &impl Serialize,
+        blob: &web_sys::Blob,
+    ) -> Result<(), JsValue> {
+        self.put_raw(key, blob).await
+    }
+
+    /// Store the given bytes under the given key in the object store.
+    ///
+    /// The bytes are stored as a `Uint8Array` and can be read back using
+    /// [`ObjectStore::get_bytes`].
+    pub async fn put_bytes(&self, key: &impl Serialize, bytes: &[u8]) -> Result<(), JsValue> {
+        self.put_raw(key, &js_sys::Uint8Array::from(bytes)).await
+    }
+
+    /// Delete the value with the given key from the object store.
+    ///
+    /// Deleting a key that doesn't exist isn't an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the value that should be deleted.
+    pub async fn delete(&self, key: &impl Serialize) -> Result<(), JsValue> {
+        wait(self.delete_request(serialize_key(key)?)?).await
+    }
+
+    /// Delete all the values of the object store.
+    pub async fn clear(&self) -> Result<(), JsValue> {
+        wait(self.clear_request()?).await
+    }
+
+    /// Add the given value under the given key to the object store without
+    /// waiting for the request to finish.
+    ///
+    /// The returned handle can be awaited to get the outcome of the request
+    /// or be ignored, see [`PendingRequest`]. Fails right away only if the
+    /// key or the value couldn't be encoded or the transaction isn't active
+    /// anymore.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// let first = store.queue_add(&1, &"first").unwrap();
+    /// let _ = store.queue_add(&2, &"second").unwrap();
+    ///
+    /// first.await.unwrap();
+    /// transaction.done().await.unwrap();
+    /// # });
+    /// ```
+    pub fn queue_add<V: ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<PendingRequest<'_>, JsValue>
+    where
+        C: Encode<V>,
+    {
+        let key = serialize_key(key)?;
+        let value = C::encode(value)?;
+
+        Ok(PendingRequest::new(self.add_request(key, &value)?))
+    }
+
+    /// Store the given value under the given key in the object store without
+    /// waiting for the request to finish, see
+    /// [`TransactionObjectStore::queue_add`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// for i in 0..1000 {
+    ///     let _ = store.queue_put(&i, &(i * 2)).unwrap();
+    /// }
+    ///
+    /// // Fails if any of the writes failed.
+    /// transaction.done().await.unwrap();
+    /// # });
+    /// ```
+    pub fn queue_put<V: ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<PendingRequest<'_>, JsValue>
+    where
+        C: Encode<V>,
+    {
+        let key = serialize_key(key)?;
+        let value = C::encode(value)?;
+
+        Ok(PendingRequest::new(self.put_request(key, &value)?))
+    }
+
+    /// Delete the value with the given key from the object store without
+    /// waiting for the request to finish, see
+    /// [`TransactionObjectStore::queue_add`].
+    pub fn queue_delete(&self, key: &impl Serialize) -> Result<PendingRequest<'_>, JsValue> {
+        Ok(PendingRequest::new(
+            self.delete_request(serialize_key(key)?)?,
+        ))
+    }
+
+    /// Delete all the values of the object store without waiting for the
+    /// request to finish, see [`TransactionObjectStore::queue_add`].
+    pub fn queue_clear(&self) -> Result<PendingRequest<'_>, JsValue> {
+        Ok(PendingRequest::new(self.clear_request()?))
+    }
+
+    // The requests are recorded for the change feed when they are made, a
+    // failing request aborts the transaction before anything is published.
+
+    fn add_request(&self, key: JsValue, value: &JsValue) -> Result<IdbRequest, JsValue> {
+        let request = self.inner.inner.add_with_key(value, &key)?;
+        self.record(ChangeKind::Put, vec![key]);
+
+        Ok(request)
+    }
+
+    fn put_request(&self, key: JsValue, value: &JsValue) -> Result<IdbRequest, JsValue> {
+        let request = self.inner.inner.put_with_key(value, &key)?;
+        self.record(ChangeKind::Put, vec![key]);
+
+        Ok(request)
+    }
+
+    fn delete_request(&self, key: JsValue) -> Result<IdbRequest, JsValue> {
+        let request = self.inner.inner.delete(&key)?;
+        self.record(ChangeKind::Delete, vec![key]);
+
+        Ok(request)
+    }
+
+    fn clear_request(&self) -> Result<IdbRequest, JsValue> {
+        let request = self.inner.inner.clear()?;
+        self.record(ChangeKind::Clear, Vec::new());
+
+        Ok(request)
+    }
+
+    fn record(&self, kind: ChangeKind, keys: Vec<JsValue>) {
+        if let Some(changes) = &self.changes {
+            changes.record(self.inner.name(), kind, keys);
+        }
+    }
+}
+
+/// Wait for a write request to finish.
+async fn wait(request: IdbRequest) -> Result<(), JsValue> {
+    let _ = IndexedDbRequest::new(request).await?;
+
+    Ok(())
 }
 
-impl<'a> Deref for TransactionObjectStore<'a> {
-    type Target = ObjectStore;
+impl<'a, M: Mode, C: Codec> Deref for TransactionObjectStore<'a, M, C> {
+    type Target = ObjectStore<C>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
@@ -45,12 +464,23 @@ impl<'a> Deref for TransactionObjectStore<'a> {
 }
 
 /// Base object store that gathers all the common object store functionality.
+///
+/// Values are converted using the codec `C`, see the [`codec`](crate::codec)
+/// module.
 #[derive(Debug)]
-pub struct ObjectStore {
+pub struct ObjectStore<C: Codec = Json> {
     pub(crate) inner: web_sys::IdbObjectStore,
+    pub(crate) codec: PhantomData<C>,
 }
 
-impl<'a> ObjectStore {
+impl<C: Codec> ObjectStore<C> {
+    pub(crate) fn new(inner: web_sys::IdbObjectStore) -> Self {
+        Self {
+            inner,
+            codec: PhantomData,
+        }
+    }
+
     /// The name of the object store.
     pub fn name(&self) -> String {
         self.inner.name()
@@ -58,10 +488,13 @@ impl<'a> ObjectStore {
 
     /// Get the value with the given key.
     ///
+    /// A stored `null` is treated like a missing value, use
+    /// [`get_raw`](Self::get_raw) to tell them apart.
+    ///
     /// # Arguments
     ///
     /// * `key` - The key that should be used to find the associated value in
-    /// the store.
+    ///   the store.
     ///
     /// # Examples
     ///
@@ -84,32 +517,110 @@ impl<'a> ObjectStore {
     ///     .unwrap();
     /// # });
     /// ```
-    pub async fn get<V: for<'b> Deserialize<'b>>(
-        &self,
-        key: &impl Serialize,
-    ) -> Result<Option<V>, JsValue> {
-        let key = JsValue::from_serde(&key).expect("Can't serialize key");
-        let request = self.inner.get(&key)?;
+    pub async fn get<V>(&self, key: &impl Serialize) -> Result<Option<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        self.get_raw(key)
+            .await?
+            .filter(|value| !value.is_null())
+            .map(C::decode)
+            .transpose()
+    }
+
+    /// Get all the values with keys inside of the given range in key order,
+    /// all the values of the store if no range is given.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, KeyRange, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # use std::convert::TryFrom;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::Readonly);
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// let range = KeyRange::try_from(1..10).unwrap();
+    /// let values: Vec<String> = store.get_all(Some(&range)).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get_all<V>(&self, range: Option<&KeyRange>) -> Result<Vec<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        self.get_all_js(range)
+            .await?
+            .into_iter()
+            .map(C::decode)
+            .collect()
+    }
+
+    /// Count the values with keys inside of the given range, all the values
+    /// of the store if no range is given.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<u32, JsValue> {
+        let request = IndexedDbRequest::new(self.inner.count_with_key(&query(range)?)?);
+
+        count_from_js(request.await?)
+    }
+
+    /// Get the index with the given name.
+    ///
+    /// Fails with a `NotFoundError` if the store has no such index.
+    pub fn index(&self, name: &str) -> Result<Index<'_, C>, JsValue> {
+        Ok(Index::new(self.inner.index(name)?))
+    }
 
-        let request = IndexedDbRequest::new(request);
+    /// Get the JavaScript value with the given key bypassing the codec of the
+    /// store.
+    ///
+    /// This is useful to read values that were written by JavaScript code and
+    /// can't be decoded, like `Blob`s or class instances.
+    ///
+    /// Returns `None` only if there is no value with the given key, a stored
+    /// `null` is returned as `Some(JsValue::NULL)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that should be used to find the associated value in
+    ///   the store.
+    pub async fn get_raw(&self, key: &impl Serialize) -> Result<Option<JsValue>, JsValue> {
+        let key = serialize_key(key)?;
+        let request = IndexedDbRequest::new(self.inner.get(&key)?);
 
         let object = request.await?;
 
-        if object.is_undefined() || object.is_null() {
+        if object.is_undefined() {
             Ok(None)
         } else {
-            Ok(object.into_serde().expect("Can't deserialize value"))
+            Ok(Some(object))
         }
     }
 
-    /// Store the given value under the given key in the object store.
-    ///
-    /// # Arguments
+    /// Get the `Blob` stored under the given key.
     ///
-    /// * `key` - The key that should be used to save the associated value in
-    /// the store.
+    /// Fails if the stored value isn't a `Blob`.
+    pub async fn get_blob(&self, key: &impl Serialize) -> Result<Option<web_sys::Blob>, JsValue> {
+        self.get_raw(key).await?.map(Raw::decode).transpose()
+    }
+
+    /// Get the binary value stored under the given key.
     ///
-    /// * `value` - The value that should saved in the store.
+    /// Values stored as a `Uint8Array` or `ArrayBuffer` are supported, the
+    /// content isn't copied into wasm memory until the returned array is
+    /// read. Fails if the stored value isn't binary.
+    pub async fn get_bytes(
+        &self,
+        key: &impl Serialize,
+    ) -> Result<Option<js_sys::Uint8Array>, JsValue> {
+        self.get_raw(key).await?.map(bytes_from_js).transpose()
+    }
+
+    /// Open a cursor that iterates over all the entries of the store in key
+    /// order.
     ///
     /// # Examples
     ///
@@ -120,27 +631,60 @@ impl<'a> ObjectStore {
     /// # let db = IndexedDb::open("test", 1, |_, db| {
     /// #   db.create_object_store("test").unwrap();
     /// # }).await .expect("Failed to open indexed DB");
-    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let transaction = db.transaction(TransactionMode::Readonly);
     /// let store = transaction.object_store("test").unwrap();
     ///
-    /// let key = "Hello".to_owned();
-    /// let value = "world".to_owned();
-    ///
-    /// store.add(&key, &value).await.unwrap();
-    /// transaction.done().await;
+    /// let mut cursor = store.open_cursor().unwrap();
     ///
+    /// while cursor.next().await.unwrap() {
+    ///     let key: String = cursor.key().unwrap();
+    ///     let value: String = cursor.value().unwrap();
+    /// }
     /// # });
     /// ```
-    pub async fn add(&self, key: &impl Serialize, value: &impl Serialize) -> Result<(), JsValue> {
-        let key = JsValue::from_serde(key).expect("Can't serialize key");
-        let value = JsValue::from_serde(value).expect("Can't serialize value");
+    pub fn open_cursor(&self) -> Result<Cursor<'_, C>, JsValue> {
+        Ok(Cursor::new(self.inner.open_cursor()?))
+    }
 
-        let request = self.inner.add_with_key(&value, &key).unwrap();
+    /// Open a cursor that iterates over the entries of the store with keys
+    /// inside of the given range in key order.
+    pub fn open_cursor_with_range(&self, range: &KeyRange) -> Result<Cursor<'_, C>, JsValue> {
+        Ok(Cursor::new(
+            self.inner.open_cursor_with_range(&range.to_js()?)?,
+        ))
+    }
 
-        let request = IndexedDbRequest::new(request);
-        let _ = request.await?;
+    /// Estimate the number of bytes the keys and values of the store take up.
+    ///
+    /// This walks all the entries with a cursor, which takes a while for big
+    /// stores. The estimate doesn't include the overhead of the browser's
+    /// storage format and indexes, so it is only useful to compare stores with
+    /// each other, see [`Factory::estimate`] for the actual usage.
+    ///
+    /// [`Factory::estimate`]: crate::Factory::estimate
+    pub async fn approximate_size(&self) -> Result<u64, JsValue> {
+        let mut cursor = self.open_cursor()?;
+        let mut size = 0;
 
-        Ok(())
+        while cursor.next().await? {
+            size += approximate_size(&cursor.key_raw()) + approximate_size(&cursor.value_raw());
+        }
+
+        Ok(size)
+    }
+
+    /// Get the underlying `web_sys` object store.
+    pub fn as_raw(&self) -> &web_sys::IdbObjectStore {
+        &self.inner
+    }
+
+    pub(crate) async fn get_all_js(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<JsValue>, JsValue> {
+        let request = IndexedDbRequest::new(self.inner.get_all_with_key(&query(range)?)?);
+
+        Ok(js_sys::Array::from(&request.await?).to_vec())
     }
 
     /// The key path of the object store. No key path means keys are stored
@@ -151,15 +695,34 @@ impl<'a> ObjectStore {
     }
 }
 
-/// The path to the key in an object store.
-#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
-pub(crate) enum KeyPath {
+/// Convert the result of a count request into a number.
+pub(crate) fn count_from_js(count: JsValue) -> Result<u32, JsValue> {
+    count
+        .as_f64()
+        .map(|count| count as u32)
+        .ok_or_else(|| JsValue::from("a count request didn't return a number"))
+}
+
+/// Convert a stored binary value into a `Uint8Array`.
+pub(crate) fn bytes_from_js(value: JsValue) -> Result<js_sys::Uint8Array, JsValue> {
+    if let Some(buffer) = value.dyn_ref::<js_sys::ArrayBuffer>() {
+        Ok(js_sys::Uint8Array::new(buffer))
+    } else {
+        Raw::decode(value)
+    }
+}
+
+/// The path to the key in an object store or index.
+///
+/// A path is a sequence of property names separated by dots, e.g.
+/// `"address.city"`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum KeyPath {
     /// Keys are stored *out-of-tree*.
     None,
     /// The path to the single key.
     Single(String),
-    // This complains when I use it in the browser TODO investigate.
-    // /// The paths to all the parts of the key.
+    /// The paths to all the parts of the key.
     Multi(Vec<String>),
 }
 
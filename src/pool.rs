@@ -0,0 +1,346 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+};
+
+use futures::future::{FutureExt, LocalBoxFuture, Shared};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    db::{DbDuringUpgrade, IndexedDb},
+    factory::Factory,
+    request::EventListener,
+};
+
+thread_local! {
+    static GLOBAL: RefCell<Option<ConnectionPool>> = const { RefCell::new(None) };
+}
+
+type SharedOpen = Shared<LocalBoxFuture<'static, Result<IndexedDb, JsValue>>>;
+
+/// A connection of the pool, opened or still being opened.
+struct Connection {
+    requested: u32,
+    open: SharedOpen,
+    // Set once the connection was closed, the next request for the database
+    // reopens it.
+    closed: Rc<Cell<bool>>,
+    listener: Rc<RefCell<Option<EventListener>>>,
+}
+
+impl Connection {
+    fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    /// The version of the database, the requested one until it is open.
+    fn version(&self) -> u32 {
+        match self.open.peek() {
+            Some(Ok(db)) => db.version() as u32,
+            _ => self.requested,
+        }
+    }
+}
+
+struct PoolState {
+    factory: Factory,
+    connections: RefCell<HashMap<String, Connection>>,
+}
+
+/// A registry of database connections shared by all the parts of an
+/// application.
+///
+/// Opening a database through the pool returns the connection that was
+/// already opened for the database instead of opening a new one, concurrent
+/// requests share a single open request and upgrade.
+///
+/// Connections of the pool get out of the way of upgrades:
+///
+/// * Opening a newer version through the pool closes the connection that
+///   is open for the older version first, so the upgrade isn't blocked.
+///
+/// * A connection is closed when another connection, e.g. in another tab,
+///   wants to upgrade or delete the database. The next request for the
+///   database opens a new connection, at the version the other connection
+///   upgraded the database to if that is newer than the requested one.
+///
+/// Handles to a closed connection fail to start transactions, components
+/// should get their handle from the pool again after a version change
+/// instead of holding on to it.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Rc<PoolState>,
+}
+
+impl ConnectionPool {
+    /// Create a pool that opens databases using the given factory.
+    pub fn new(factory: Factory) -> Self {
+        Self {
+            inner: Rc::new(PoolState {
+                factory,
+                connections: RefCell::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Get the pool of the current global scope, which uses the factory of
+    /// the scope.
+    ///
+    /// Fails if IndexedDB isn't available in the current context.
+    pub fn global() -> Result<Self, JsValue> {
+        GLOBAL.with(|global| {
+            let mut global = global.borrow_mut();
+
+            if let Some(pool) = &*global {
+                return Ok(pool.clone());
+            }
+
+            let pool = ConnectionPool::new(Factory::new()?);
+            *global = Some(pool.clone());
+
+            Ok(pool)
+        })
+    }
+
+    /// Get a connection to the database with the given name and at least the
+    /// given version.
+    ///
+    /// Returns the pooled connection if one with the same or a newer version
+    /// is open, otherwise the database is opened like in
+    /// [`IndexedDb::open`]. After the pooled connection was closed for a
+    /// version change the database is reopened at its current version if
+    /// that is newer, requesting the older version doesn't fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    ///
+    /// * `version` - The version of the database that is needed.
+    ///
+    /// * `on_upgrade_needed` - Callback that will be called if the database
+    ///   needs to be upgraded, it isn't called if a pooled connection is
+    ///   returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::ConnectionPool;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let pool = ConnectionPool::global().unwrap();
+    ///
+    /// let db = pool.open("test", 1, |_, db| {
+    ///     db.create_object_store("test").unwrap();
+    /// }).await.expect("Failed to open indexed DB");
+    ///
+    /// // Same connection, the upgrade callback isn't called again.
+    /// let same = pool.open("test", 1, |_, _| ()).await.unwrap();
+    /// assert_eq!(db.as_raw(), same.as_raw());
+    /// # });
+    /// ```
+    pub async fn open(
+        &self,
+        name: &str,
+        version: u32,
+        on_upgrade_needed: impl Fn(u32, &DbDuringUpgrade) + 'static,
+    ) -> Result<IndexedDb, JsValue> {
+        let pooled = self
+            .inner
+            .connections
+            .borrow()
+            .get(name)
+            .filter(|connection| !connection.is_closed() && connection.version() >= version)
+            .map(|connection| connection.open.clone());
+
+        if let Some(open) = pooled {
+            return open.await;
+        }
+
+        // Close the older connection, it would block the upgrade otherwise.
+        let old = self.inner.connections.borrow_mut().remove(name);
+        let mut changed = false;
+
+        if let Some(old) = old {
+            changed = old.is_closed();
+
+            if let Ok(db) = old.open.await {
+                db.close();
+            }
+        }
+
+        let closed = Rc::new(Cell::new(false));
+        let listener = Rc::new(RefCell::new(None));
+
+        let open = {
+            let factory = self.inner.factory.clone();
+            let name = name.to_owned();
+            let closed = closed.clone();
+            let listener = listener.clone();
+
+            async move {
+                // Another connection might have upgraded the database beyond
+                // the requested version, opening that fails.
+                let current = if changed {
+                    factory.open_existing(&name).await?
+                } else {
+                    None
+                };
+
+                let db = match current {
+                    Some(db) if db.version() >= u64::from(version) => db,
+                    current => {
+                        if let Some(db) = current {
+                            db.close();
+                        }
+
+                        factory.open(&name, version, on_upgrade_needed).await?
+                    }
+                };
+
+                let handle = db.clone();
+                *listener.borrow_mut() = Some(EventListener::new(
+                    db.as_raw(),
+                    &["versionchange", "close"],
+                    move |_| {
+                        handle.close();
+                        closed.set(true);
+                    },
+                ));
+
+                Ok(db)
+            }
+        }
+        .boxed_local()
+        .shared();
+
+        self.inner.connections.borrow_mut().insert(
+            name.to_owned(),
+            Connection {
+                requested: version,
+                open: open.clone(),
+                closed,
+                listener,
+            },
+        );
+
+        let result = open.clone().await;
+
+        // Only remove the failed connection, a newer request may have
+        // replaced it already.
+        let failed = result.is_err()
+            && self
+                .inner
+                .connections
+                .borrow()
+                .get(name)
+                .is_some_and(|connection| connection.open.ptr_eq(&open));
+
+        if failed {
+            self.remove(name, |connection| connection.closed.set(true));
+        }
+
+        result
+    }
+
+    /// Close the pooled connection to the database with the given name.
+    ///
+    /// The next request for the database opens a new connection.
+    pub fn close(&self, name: &str) {
+        self.remove(name, |connection| {
+            if let Some(Ok(db)) = connection.open.peek() {
+                db.close();
+            }
+        });
+    }
+
+    /// Close all the pooled connections.
+    pub fn close_all(&self) {
+        let connections: Vec<_> = self.inner.connections.borrow_mut().drain().collect();
+
+        for (_, connection) in connections {
+            if let Some(Ok(db)) = connection.open.peek() {
+                db.close();
+            }
+        }
+    }
+
+    /// Get the names of the databases that have an open pooled connection.
+    pub fn database_names(&self) -> Vec<String> {
+        self.inner
+            .connections
+            .borrow()
+            .iter()
+            .filter(|(_, connection)| !connection.is_closed())
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    fn remove(&self, name: &str, f: impl FnOnce(&Connection)) {
+        let connection = self.inner.connections.borrow_mut().remove(name);
+
+        if let Some(connection) = connection {
+            f(&connection);
+            connection.listener.borrow_mut().take();
+        }
+    }
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("factory", &self.inner.factory)
+            .field("databases", &self.database_names())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConnectionPool;
+    use crate::{Factory, IndexedDb};
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn shared_connections() {
+        let pool = ConnectionPool::new(Factory::new().unwrap());
+
+        let (first, second) = futures::join!(
+            pool.open("pool", 1, |_, db| {
+                db.create_object_store("first").unwrap();
+            }),
+            pool.open("pool", 1, |_, _| panic!("upgraded twice")),
+        );
+        let first = first.unwrap();
+        assert_eq!(first.as_raw(), second.unwrap().as_raw());
+
+        // Upgrading through the pool closes the older connection.
+        let upgraded = pool
+            .open("pool", 2, |_, db| {
+                db.create_object_store("second").unwrap();
+            })
+            .await
+            .unwrap();
+        assert_eq!(upgraded.version(), 2);
+        assert_eq!(pool.open("pool", 1, |_, _| ()).await.unwrap().version(), 2);
+
+        // An upgrade outside of the pool isn't blocked by the pooled
+        // connection.
+        let outside = IndexedDb::open("pool", 3, |_, _| ()).await.unwrap();
+        outside.close();
+
+        // The connection is reopened at the new version.
+        let reopened = pool.open("pool", 2, |_, _| ()).await.unwrap();
+        assert_ne!(reopened.as_raw(), upgraded.as_raw());
+        assert_eq!(reopened.version(), 3);
+        assert_eq!(reopened.object_store_names(), vec!["first", "second"]);
+        assert_eq!(
+            pool.open("pool", 3, |_, _| ()).await.unwrap().as_raw(),
+            reopened.as_raw()
+        );
+
+        pool.close_all();
+        assert!(pool.database_names().is_empty());
+    }
+}
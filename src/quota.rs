@@ -0,0 +1,147 @@
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// How much storage the origin uses and how much it may use before writes
+/// start failing with a `QuotaExceededError`.
+///
+/// Browsers round and pad the numbers, they are estimates only.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StorageEstimate {
+    /// The number of bytes used by the origin.
+    pub usage: f64,
+    /// The number of bytes the origin may use.
+    pub quota: f64,
+}
+
+impl StorageEstimate {
+    /// The share of the quota that is used, between `0.0` and `1.0`.
+    pub fn used_fraction(&self) -> f64 {
+        if self.quota > 0.0 {
+            (self.usage / self.quota).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Get the storage manager of the current global scope, this works for
+/// windows and all kinds of workers.
+pub(crate) fn storage_manager() -> Result<web_sys::StorageManager, JsValue> {
+    let manager = js_sys::Reflect::get(&js_sys::global(), &"navigator".into())
+        .and_then(|navigator| js_sys::Reflect::get(&navigator, &"storage".into()))?;
+
+    if manager.is_undefined() || manager.is_null() {
+        return Err("The storage manager isn't available in this context".into());
+    }
+
+    Ok(manager.unchecked_into())
+}
+
+pub(crate) async fn estimate() -> Result<StorageEstimate, JsValue> {
+    let estimate: web_sys::StorageEstimate = JsFuture::from(storage_manager()?.estimate()?)
+        .await?
+        .unchecked_into();
+
+    Ok(StorageEstimate {
+        usage: estimate.get_usage().unwrap_or(0.0),
+        quota: estimate.get_quota().unwrap_or(0.0),
+    })
+}
+
+pub(crate) async fn persist() -> Result<bool, JsValue> {
+    let persisted = JsFuture::from(storage_manager()?.persist()?).await?;
+    Ok(persisted.is_truthy())
+}
+
+pub(crate) async fn persisted() -> Result<bool, JsValue> {
+    let persisted = JsFuture::from(storage_manager()?.persisted()?).await?;
+    Ok(persisted.is_truthy())
+}
+
+/// Estimate the number of bytes a structured clone of the value takes up.
+///
+/// Strings count two bytes per UTF-16 unit and numbers eight bytes, binary
+/// data counts its length. The overhead of the browser's storage format isn't
+/// known, so the result only allows to compare stores with each other.
+pub(crate) fn approximate_size(value: &JsValue) -> u64 {
+    if let Some(string) = value.as_string() {
+        return 2 * string.encode_utf16().count() as u64;
+    }
+
+    if value.as_f64().is_some() {
+        return 8;
+    }
+
+    if value.as_bool().is_some() || value.is_null() || value.is_undefined() {
+        return 1;
+    }
+
+    if let Some(buffer) = value.dyn_ref::<js_sys::ArrayBuffer>() {
+        return buffer.byte_length() as u64;
+    }
+
+    if js_sys::ArrayBuffer::is_view(value) {
+        return js_sys::Reflect::get(value, &"byteLength".into())
+            .ok()
+            .and_then(|length| length.as_f64())
+            .unwrap_or(0.0) as u64;
+    }
+
+    if let Some(blob) = value.dyn_ref::<web_sys::Blob>() {
+        return blob.size() as u64;
+    }
+
+    if value.is_instance_of::<js_sys::Date>() {
+        return 8;
+    }
+
+    if let Some(array) = value.dyn_ref::<js_sys::Array>() {
+        return array.iter().map(|value| approximate_size(&value)).sum();
+    }
+
+    if let Some(object) = value.dyn_ref::<js_sys::Object>() {
+        return js_sys::Object::entries(object)
+            .iter()
+            .map(|entry| approximate_size(&entry))
+            .sum();
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::approximate_size;
+    use crate::{Factory, TransactionMode};
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn store_size() {
+        assert_eq!(approximate_size(&JsValue::from_str("abc")), 6);
+        assert_eq!(
+            approximate_size(&js_sys::Uint8Array::new_with_length(100)),
+            100
+        );
+
+        let factory = Factory::new().unwrap();
+        let estimate = factory.estimate().await.unwrap();
+        assert!(estimate.quota > 0.0);
+
+        let db = factory
+            .open("quota", 1, |_, db| {
+                db.create_object_store("test").unwrap();
+            })
+            .await
+            .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.clear().await.unwrap();
+        assert_eq!(store.approximate_size().await.unwrap(), 0);
+
+        store.put_bytes(&"a", &[0; 1000]).await.unwrap();
+        assert!(store.approximate_size().await.unwrap() >= 1000);
+        transaction.done().await.unwrap();
+    }
+}
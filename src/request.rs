@@ -1,42 +1,174 @@
 use futures::{
-    task::{Context, Poll},
+    task::{Context, Poll, Waker},
     Future,
 };
-use std::{
-    fmt,
-    pin::Pin,
-    sync::{Arc, Mutex},
-};
+use std::{cell::RefCell, fmt, future::IntoFuture, marker::PhantomData, pin::Pin, rc::Rc};
 
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 
 use crate::db::{DbDuringUpgrade, IndexedDb};
 
-pub(crate) struct IndexedDbRequest {
-    inner: Arc<web_sys::IdbRequest>,
-    onsuccess: Mutex<Option<Closure<dyn FnMut()>>>,
-    onerror: Mutex<Option<Closure<dyn FnMut()>>>,
+/// The waker of the task that last polled a future, shared with the event
+/// handlers of the future.
+///
+/// Everything runs on a single thread, so the handlers only need a `RefCell`
+/// instead of a lock. The handlers are registered once when the future is
+/// created, polling only replaces the stored waker.
+#[derive(Clone, Default)]
+pub(crate) struct SharedWaker(Rc<RefCell<Option<Waker>>>);
+
+impl SharedWaker {
+    /// Store the waker of the polling task, unless it would wake the same
+    /// task as the stored one.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut stored = self.0.borrow_mut();
+
+        match &*stored {
+            Some(stored) if stored.will_wake(waker) => (),
+            _ => *stored = Some(waker.clone()),
+        }
+    }
+
+    /// Wake the task that last polled the future.
+    pub(crate) fn wake(&self) {
+        // Release the borrow first, the waker might poll the future right
+        // away.
+        let waker = self.0.borrow_mut().take();
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Listen for the given events on `target` and wake the task when one of
+    /// them is dispatched.
+    pub(crate) fn listen(
+        &self,
+        target: &web_sys::EventTarget,
+        events: &'static [&'static str],
+    ) -> EventListener {
+        let waker = self.clone();
+
+        EventListener::new(target, events, move |_| waker.wake())
+    }
 }
 
-impl IndexedDbRequest {
-    pub(crate) fn new(request: web_sys::IdbRequest) -> Self {
+impl fmt::Debug for SharedWaker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SharedWaker")
+            .field(&self.0.borrow().is_some())
+            .finish()
+    }
+}
+
+/// An event listener that is subscribed using `addEventListener`, other
+/// listeners and `on*` handlers of the target are left alone.
+///
+/// A single closure is registered for all the events, it is removed from the
+/// target when the listener is dropped.
+pub(crate) struct EventListener {
+    target: web_sys::EventTarget,
+    events: &'static [&'static str],
+    closure: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+impl EventListener {
+    pub(crate) fn new(
+        target: &web_sys::EventTarget,
+        events: &'static [&'static str],
+        callback: impl FnMut(web_sys::Event) + 'static,
+    ) -> Self {
+        let closure = Closure::wrap(Box::new(callback) as Box<dyn FnMut(web_sys::Event)>);
+
+        for event in events {
+            target
+                .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+                .expect("adding an event listener failed");
+        }
+
         Self {
-            inner: Arc::new(request),
-            onsuccess: Mutex::new(None),
-            onerror: Mutex::new(None),
+            target: target.clone(),
+            events,
+            closure,
+        }
+    }
+
+    /// Listen for the first of the given events, the closure frees itself
+    /// once it was called and doesn't need to be kept alive.
+    ///
+    /// The closure is leaked if none of the events is dispatched.
+    pub(crate) fn once(
+        target: &web_sys::EventTarget,
+        events: &'static [&'static str],
+        callback: impl FnOnce(web_sys::Event) + 'static,
+    ) {
+        let closure = Closure::once_into_js(callback);
+        let options = web_sys::AddEventListenerOptions::new();
+        options.set_once(true);
+
+        for event in events {
+            target
+                .add_event_listener_with_callback_and_add_event_listener_options(
+                    event,
+                    closure.unchecked_ref(),
+                    &options,
+                )
+                .expect("adding an event listener failed");
+        }
+    }
+}
+
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        // The closure is freed with the listener, a later event must not
+        // call into it.
+        for event in self.events {
+            let _ = self
+                .target
+                .remove_event_listener_with_callback(event, self.closure.as_ref().unchecked_ref());
         }
     }
+}
+
+impl fmt::Debug for EventListener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventListener")
+            .field("events", &self.events)
+            .finish()
+    }
+}
 
-    fn set_onsuccsess(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_onsuccess(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.onsuccess.lock().unwrap() = closure;
+/// Read the outcome of a request that is done.
+fn request_result(request: &web_sys::IdbRequest) -> Result<JsValue, JsValue> {
+    match request.result() {
+        Ok(val) => Ok(val),
+        Err(_) => match request.error() {
+            Ok(Some(e)) => Err(e.into()),
+            Ok(None) => unreachable!("internal error polling a done request"),
+            Err(e) => Err(e),
+        },
     }
+}
+
+/// The events that finish a request.
+const DONE_EVENTS: &[&str] = &["success", "error"];
+
+pub(crate) struct IndexedDbRequest {
+    inner: web_sys::IdbRequest,
+    waker: SharedWaker,
+    _on_done: EventListener,
+}
+
+impl IndexedDbRequest {
+    pub(crate) fn new(request: web_sys::IdbRequest) -> Self {
+        let waker = SharedWaker::default();
+        let on_done = waker.listen(&request, DONE_EVENTS);
 
-    fn set_onerror(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_onerror(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.onerror.lock().unwrap() = closure;
+        Self {
+            inner: request,
+            waker,
+            _on_done: on_done,
+        }
     }
 }
 
@@ -48,61 +180,108 @@ impl Future for IndexedDbRequest {
 
         match self.inner.ready_state() {
             ReadyState::Pending => {
-                let waker = cx.waker().to_owned();
+                self.waker.register(cx.waker());
+                Poll::Pending
+            }
+            ReadyState::Done => Poll::Ready(request_result(&self.inner)),
+            _ => panic!("unexpected ready state"),
+        }
+    }
+}
 
-                let onsuccess =
-                    Closure::wrap(Box::new(move || waker.clone().wake()) as Box<dyn FnMut()>);
-                self.set_onsuccsess(Some(onsuccess));
+/// A request that was made without waiting for it.
+///
+/// Requests of a transaction are executed in the order they were made, the
+/// handle can be awaited to get the outcome of the request or simply be
+/// dropped. A failed request aborts the transaction, so the error of an
+/// ignored request is reported by [`Transaction::done`].
+///
+/// No event listener is registered until the handle is awaited, which makes
+/// queueing many requests cheap.
+///
+/// [`Transaction::done`]: crate::Transaction::done
+#[must_use = "dropping the handle doesn't cancel the request, use `let _ =` to ignore its outcome"]
+pub struct PendingRequest<'a> {
+    request: web_sys::IdbRequest,
+    store: PhantomData<&'a ()>,
+}
 
-                let waker = cx.waker().to_owned();
+impl<'a> PendingRequest<'a> {
+    pub(crate) fn new(request: web_sys::IdbRequest) -> Self {
+        Self {
+            request,
+            store: PhantomData,
+        }
+    }
 
-                let onerror =
-                    Closure::wrap(Box::new(move || waker.clone().wake()) as Box<dyn FnMut()>);
+    /// Get the underlying `web_sys` request.
+    pub fn as_raw(&self) -> &web_sys::IdbRequest {
+        &self.request
+    }
+}
 
-                self.set_onerror(Some(onerror));
+impl<'a> IntoFuture for PendingRequest<'a> {
+    type Output = Result<(), JsValue>;
+    type IntoFuture = PendingRequestFuture<'a>;
 
-                Poll::Pending
-            }
-            ReadyState::Done => match self.inner.result() {
-                Ok(val) => Poll::Ready(Ok(val)),
-
-                Err(_) => match self.inner.error() {
-                    Ok(Some(e)) => Poll::Ready(Err(e.into())),
-                    Ok(None) => unreachable!("internal error polling open db request"),
-                    Err(e) => Poll::Ready(Err(e)),
-                },
-            },
-            _ => panic!("unexpected ready state"),
+    fn into_future(self) -> Self::IntoFuture {
+        PendingRequestFuture {
+            inner: IndexedDbRequest::new(self.request),
+            store: PhantomData,
         }
     }
 }
 
+impl<'a> fmt::Debug for PendingRequest<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingRequest")
+            .field("ready_state", &self.request.ready_state())
+            .finish()
+    }
+}
+
+/// The future of an awaited [`PendingRequest`].
+pub struct PendingRequestFuture<'a> {
+    inner: IndexedDbRequest,
+    store: PhantomData<&'a ()>,
+}
+
+impl<'a> Future for PendingRequestFuture<'a> {
+    type Output = Result<(), JsValue>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner).poll(cx).map_ok(|_| ())
+    }
+}
+
+impl<'a> fmt::Debug for PendingRequestFuture<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingRequestFuture")
+            .field("ready_state", &self.inner.inner.ready_state())
+            .finish()
+    }
+}
+
 /// Wraps the open db request. Private - the user interacts with the request using the function
 /// passed to the `open` method.
 pub(crate) struct IdbOpenDbRequest {
     // We need to move a ref for this into the upgradeneeded closure.
-    pub(crate) inner: Arc<web_sys::IdbOpenDbRequest>,
-    onsuccess: Mutex<Option<Closure<dyn FnMut()>>>,
-    onerror: Mutex<Option<Closure<dyn FnMut()>>>,
-    onupgradeneeded: Mutex<Option<Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)>>>,
+    pub(crate) inner: Rc<web_sys::IdbOpenDbRequest>,
+    waker: SharedWaker,
+    _on_done: EventListener,
+    _on_upgrade_needed: EventListener,
 }
 
 impl IdbOpenDbRequest {
     pub(crate) fn new(
         request: web_sys::IdbOpenDbRequest,
-        upgrade_callback: impl Fn(u32, &DbDuringUpgrade) + 'static,
+        upgrade_callback: impl Fn(u32, DbDuringUpgrade) + 'static,
     ) -> Self {
-        let request = Arc::new(request);
+        let request = Rc::new(request);
         let request_copy = request.clone();
 
-        let request = IdbOpenDbRequest {
-            inner: request,
-            onsuccess: Mutex::new(None),
-            onerror: Mutex::new(None),
-            onupgradeneeded: Mutex::new(None),
-        };
-
-        let onupgradeneeded = move |event: web_sys::IdbVersionChangeEvent| {
+        let onupgradeneeded = move |event: web_sys::Event| {
+            let event: web_sys::IdbVersionChangeEvent = event.unchecked_into();
             let old_version = event.old_version() as u32;
 
             let result = match request_copy.result() {
@@ -111,36 +290,54 @@ impl IdbOpenDbRequest {
             };
 
             let db = DbDuringUpgrade::from_raw_unchecked(result, request_copy.clone());
-            upgrade_callback(old_version, &db);
+            upgrade_callback(old_version, db);
         };
 
-        let onupgradeneeded = Closure::wrap(
-            Box::new(onupgradeneeded) as Box<dyn FnMut(web_sys::IdbVersionChangeEvent)>
-        );
-        request.set_onupgradeneeded(Some(onupgradeneeded));
+        let on_upgrade_needed = EventListener::new(&request, &["upgradeneeded"], onupgradeneeded);
 
-        request
-    }
+        let waker = SharedWaker::default();
+        let on_done = waker.listen(&request, DONE_EVENTS);
 
-    fn set_onsuccsess(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_onsuccess(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.onsuccess.lock().unwrap() = closure;
+        IdbOpenDbRequest {
+            inner: request,
+            waker,
+            _on_done: on_done,
+            _on_upgrade_needed: on_upgrade_needed,
+        }
     }
+}
 
-    fn set_onerror(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_onerror(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.onerror.lock().unwrap() = closure;
-    }
+impl Drop for IdbOpenDbRequest {
+    fn drop(&mut self) {
+        use web_sys::IdbRequestReadyState as ReadyState;
 
-    pub(crate) fn set_onupgradeneeded(
-        &self,
-        closure: Option<Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)>>,
-    ) {
-        self.inner
-            .set_onupgradeneeded(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.onupgradeneeded.lock().unwrap() = closure;
+        let request = &self.inner;
+
+        if request.ready_state() == ReadyState::Pending {
+            // Dropping the future cancels opening the database. The request
+            // itself can't be cancelled, so abort a pending upgrade instead of
+            // leaving the database at the new version without its object
+            // stores, and close the connection once it is opened.
+            EventListener::once(request, &["upgradeneeded"], |event| {
+                if let Some(transaction) = event
+                    .target()
+                    .and_then(|target| target.dyn_into::<web_sys::IdbOpenDbRequest>().ok())
+                    .and_then(|request| request.transaction())
+                {
+                    let _ = transaction.abort();
+                }
+            });
+
+            EventListener::once(request, &["success"], |event| {
+                if let Some(db) = event
+                    .target()
+                    .and_then(|target| target.dyn_into::<web_sys::IdbOpenDbRequest>().ok())
+                    .and_then(|request| request.result().ok())
+                {
+                    db.unchecked_into::<web_sys::IdbDatabase>().close();
+                }
+            });
+        }
     }
 }
 
@@ -158,33 +355,12 @@ impl Future for IdbOpenDbRequest {
 
         match self.inner.ready_state() {
             ReadyState::Pending => {
-                let waker = cx.waker().to_owned();
-
-                // If we're not ready set up onsuccess and onerror callbacks to notify the
-                // executor.
-                let onsuccess =
-                    Closure::wrap(Box::new(move || waker.clone().wake()) as Box<dyn FnMut()>);
-                self.set_onsuccsess(Some(onsuccess));
-
-                let waker = cx.waker().to_owned();
-
-                let onerror =
-                    Closure::wrap(Box::new(move || waker.clone().wake()) as Box<dyn FnMut()>);
-
-                self.set_onerror(Some(onerror));
-
+                self.waker.register(cx.waker());
                 Poll::Pending
             }
-            ReadyState::Done => match self.inner.result() {
-                Ok(val) => Poll::Ready(Ok(IndexedDb {
-                    inner: Arc::new(val.unchecked_into()),
-                })),
-                Err(_) => match self.inner.error() {
-                    Ok(Some(e)) => Poll::Ready(Err(e.into())),
-                    Ok(None) => unreachable!("internal error polling open db request"),
-                    Err(e) => Poll::Ready(Err(e)),
-                },
-            },
+            ReadyState::Done => Poll::Ready(
+                request_result(&self.inner).map(|val| IndexedDb::new(val.unchecked_into())),
+            ),
             _ => panic!("unexpected ready state"),
         }
     }
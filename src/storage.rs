@@ -0,0 +1,700 @@
+//! Traits that allow application code to be generic over the storage it uses.
+//!
+//! The traits mirror the API of the browser types, [`IndexedDb`] implements
+//! [`Database`] and the in-memory emulation of the [`memory`] module
+//! implements the same traits, which allows the same storage code to run in
+//! a browser and in native tests. Third parties can plug in their own
+//! backends by implementing the traits.
+//!
+//! Values are converted using serde, object stores of the browser
+//! implementation need to use a codec implementing [`SerdeCodec`].
+//!
+//! # Examples
+//!
+//! ```
+//! # use futures::executor::block_on;
+//! use indexeddb::{
+//!     memory::MemoryDb,
+//!     storage::{Database, Store, Transaction},
+//!     TransactionMode,
+//! };
+//!
+//! async fn count_users<D: Database>(db: &D) -> Result<u32, D::Error> {
+//!     let transaction = db.transaction(TransactionMode::Readonly);
+//!     let store = transaction.object_store("users")?;
+//!
+//!     store.count(None).await
+//! }
+//!
+//! # block_on(async {
+//! let db = MemoryDb::open("users", 1, |_, db| {
+//!     db.create_object_store("users").unwrap();
+//! }).await.expect("Failed to open the database");
+//!
+//! assert_eq!(count_users(&db).await.unwrap(), 0);
+//! # });
+//! ```
+//!
+//! [`IndexedDb`]: crate::IndexedDb
+//! [`memory`]: crate::memory
+//! [`SerdeCodec`]: crate::codec::SerdeCodec
+
+// Futures of IndexedDB are never `Send`, wasm is single threaded, so there is
+// no point in adding `Send` bounds to the futures of the traits.
+#![allow(async_fn_in_trait)]
+
+use std::{
+    fmt::{self, Debug},
+    rc::Rc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::JsValue;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::memory::{
+    Error as MemoryError, MemoryCursor, MemoryDb, MemoryIndex, MemoryObjectStore, MemoryTransaction,
+};
+use crate::{
+    codec::{Json, SerdeCodec},
+    transaction::{Dynamic, WriteMode},
+    IndexedDb, InvalidKey, KeyRange, TransactionMode,
+};
+
+/// A database that hands out transactions.
+pub trait Database {
+    /// The error type of the database and all the types it hands out, the
+    /// errors of [`KeyRange`] constructors convert into it.
+    type Error: Debug + From<InvalidKey>;
+
+    /// The type of the transactions of the database.
+    type Transaction<'a>: Transaction<Error = Self::Error>
+    where
+        Self: 'a;
+
+    /// Get the name of this database.
+    fn name(&self) -> String;
+
+    /// The current version of the database.
+    fn version(&self) -> u64;
+
+    /// Get the names of the object stores in this database.
+    fn object_store_names(&self) -> Vec<String>;
+
+    /// Start a transaction covering all the object stores of the database.
+    fn transaction(&self, mode: TransactionMode) -> Self::Transaction<'_>;
+}
+
+/// A transaction of a [`Database`].
+pub trait Transaction {
+    /// The error type of the transaction.
+    type Error: Debug;
+
+    /// The type of the object stores of the transaction.
+    type Store<'a>: Store<Error = Self::Error>
+    where
+        Self: 'a;
+
+    /// Get the object store with the given name.
+    fn object_store(&self, name: &str) -> Result<Self::Store<'_>, Self::Error>;
+
+    /// Commit the transaction and wait for it to be done.
+    async fn done(self) -> Result<(), Self::Error>;
+
+    /// Abort the transaction cancelling all the writes that were done using
+    /// this transaction.
+    async fn abort(self) -> Result<(), Self::Error>;
+
+    /// Get a handle that aborts the transaction while its object stores are
+    /// still borrowed.
+    fn abort_handle(&self) -> AbortHandle;
+}
+
+/// Aborts a [`Transaction`] without consuming it.
+///
+/// Aborting a transaction that already finished does nothing.
+#[derive(Clone)]
+pub struct AbortHandle {
+    abort: Rc<dyn Fn()>,
+}
+
+impl AbortHandle {
+    /// Create a handle that calls `abort` to abort the transaction.
+    pub fn new(abort: impl Fn() + 'static) -> Self {
+        Self {
+            abort: Rc::new(abort),
+        }
+    }
+
+    /// Abort the transaction.
+    pub fn abort(&self) {
+        (self.abort)()
+    }
+}
+
+impl Debug for AbortHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AbortHandle").finish_non_exhaustive()
+    }
+}
+
+/// An object store that is bound to a [`Transaction`].
+///
+/// Writes fail at runtime if the transaction is read only.
+pub trait Store {
+    /// The error type of the object store.
+    type Error: Debug;
+
+    /// The type of the indexes of the object store.
+    type Index<'a>: Index<Error = Self::Error>
+    where
+        Self: 'a;
+
+    /// The type of the cursors of the object store.
+    type Cursor<'a>: Cursor<Error = Self::Error>
+    where
+        Self: 'a;
+
+    /// The name of the object store.
+    fn name(&self) -> String;
+
+    /// Get the value with the given key.
+    async fn get<V: DeserializeOwned>(
+        &self,
+        key: &impl Serialize,
+    ) -> Result<Option<V>, Self::Error>;
+
+    /// Get all the values with keys inside of the given range in key order,
+    /// all the values of the store if no range is given.
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, Self::Error>;
+
+    /// Count the values with keys inside of the given range, all the values
+    /// of the store if no range is given.
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, Self::Error>;
+
+    /// Get the index with the given name.
+    fn index(&self, name: &str) -> Result<Self::Index<'_>, Self::Error>;
+
+    /// Open a cursor that iterates over the entries of the store with keys
+    /// inside of the given range, all the entries if no range is given.
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, Self::Error>;
+
+    /// Add the given value under the given key, fails if a value with the
+    /// given key already exists.
+    async fn add<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), Self::Error>;
+
+    /// Store the given value under the given key, replacing any existing
+    /// value.
+    async fn put<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), Self::Error>;
+
+    /// Delete the value with the given key.
+    async fn delete(&self, key: &impl Serialize) -> Result<(), Self::Error>;
+
+    /// Delete all the values of the object store.
+    async fn clear(&self) -> Result<(), Self::Error>;
+}
+
+/// An index of a [`Store`].
+pub trait Index {
+    /// The error type of the index.
+    type Error: Debug;
+
+    /// The type of the cursors of the index.
+    type Cursor<'a>: Cursor<Error = Self::Error>
+    where
+        Self: 'a;
+
+    /// The name of the index.
+    fn name(&self) -> String;
+
+    /// Get the first value with the given index key.
+    async fn get<V: DeserializeOwned>(
+        &self,
+        key: &impl Serialize,
+    ) -> Result<Option<V>, Self::Error>;
+
+    /// Get all the values with index keys inside of the given range, all the
+    /// values of the index if no range is given.
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, Self::Error>;
+
+    /// Count the values with index keys inside of the given range, all the
+    /// values of the index if no range is given.
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, Self::Error>;
+
+    /// Open a cursor that iterates over the entries of the index with index
+    /// keys inside of the given range, all the entries if no range is given.
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, Self::Error>;
+}
+
+/// A cursor iterating over the entries of a [`Store`] or [`Index`].
+///
+/// The cursor starts out before the first entry, [`Cursor::next`] needs to be
+/// called to move it onto an entry. The accessors panic if the cursor doesn't
+/// point to an entry.
+pub trait Cursor {
+    /// The error type of the cursor.
+    type Error: Debug;
+
+    /// Move the cursor to the next entry, returns `false` if there are no
+    /// more entries left.
+    async fn next(&mut self) -> Result<bool, Self::Error>;
+
+    /// The key of the current entry, the index key for cursors of an index.
+    fn key<K: DeserializeOwned>(&self) -> Result<K, Self::Error>;
+
+    /// The primary key of the current entry.
+    fn primary_key<K: DeserializeOwned>(&self) -> Result<K, Self::Error>;
+
+    /// The value of the current entry.
+    fn value<V: DeserializeOwned>(&self) -> Result<V, Self::Error>;
+}
+
+impl Database for IndexedDb {
+    type Error = JsValue;
+    type Transaction<'a> = crate::Transaction<'a, Dynamic, Json>;
+
+    fn name(&self) -> String {
+        self.name()
+    }
+
+    fn version(&self) -> u64 {
+        self.version()
+    }
+
+    fn object_store_names(&self) -> Vec<String> {
+        self.object_store_names()
+    }
+
+    fn transaction(&self, mode: TransactionMode) -> Self::Transaction<'_> {
+        self.transaction(mode)
+    }
+}
+
+impl<'t, M: WriteMode, C: SerdeCodec> Transaction for crate::Transaction<'t, M, C> {
+    type Error = JsValue;
+    type Store<'a>
+        = crate::TransactionObjectStore<'a, M, C>
+    where
+        Self: 'a;
+
+    fn object_store(&self, name: &str) -> Result<Self::Store<'_>, JsValue> {
+        self.object_store(name)
+    }
+
+    async fn done(self) -> Result<(), JsValue> {
+        self.done().await
+    }
+
+    async fn abort(self) -> Result<(), JsValue> {
+        self.abort().await
+    }
+
+    fn abort_handle(&self) -> AbortHandle {
+        let transaction = self.as_raw().clone();
+
+        // Fails if the transaction already finished.
+        AbortHandle::new(move || {
+            let _ = transaction.abort();
+        })
+    }
+}
+
+impl<'t, M: WriteMode, C: SerdeCodec> Store for crate::TransactionObjectStore<'t, M, C> {
+    type Error = JsValue;
+    type Index<'a>
+        = crate::Index<'a, C>
+    where
+        Self: 'a;
+    type Cursor<'a>
+        = crate::Cursor<'a, C>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, JsValue> {
+        self.get_raw(key).await?.map(C::decode_serde).transpose()
+    }
+
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, JsValue> {
+        self.get_all_js(range)
+            .await?
+            .into_iter()
+            .map(C::decode_serde)
+            .collect()
+    }
+
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, JsValue> {
+        self.inner.count(range).await
+    }
+
+    fn index(&self, name: &str) -> Result<Self::Index<'_>, JsValue> {
+        self.inner.index(name)
+    }
+
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, JsValue> {
+        match range {
+            Some(range) => self.inner.open_cursor_with_range(range),
+            None => self.inner.open_cursor(),
+        }
+    }
+
+    async fn add<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), JsValue> {
+        self.add_raw(key, &C::encode_serde(value)?).await
+    }
+
+    async fn put<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), JsValue> {
+        self.put_raw(key, &C::encode_serde(value)?).await
+    }
+
+    async fn delete(&self, key: &impl Serialize) -> Result<(), JsValue> {
+        self.delete(key).await
+    }
+
+    async fn clear(&self) -> Result<(), JsValue> {
+        self.clear().await
+    }
+}
+
+impl<'i, C: SerdeCodec> Index for crate::Index<'i, C> {
+    type Error = JsValue;
+    type Cursor<'a>
+        = crate::Cursor<'a, C>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.name()
+    }
+
+    async fn get<V: DeserializeOwned>(&self, key: &impl Serialize) -> Result<Option<V>, JsValue> {
+        self.get_js(key).await?.map(C::decode_serde).transpose()
+    }
+
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, JsValue> {
+        self.get_all_js(range)
+            .await?
+            .into_iter()
+            .map(C::decode_serde)
+            .collect()
+    }
+
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, JsValue> {
+        self.count(range).await
+    }
+
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, JsValue> {
+        self.open_cursor(range)
+    }
+}
+
+impl<'c, C: SerdeCodec> Cursor for crate::Cursor<'c, C> {
+    type Error = JsValue;
+
+    async fn next(&mut self) -> Result<bool, JsValue> {
+        self.next().await
+    }
+
+    fn key<K: DeserializeOwned>(&self) -> Result<K, JsValue> {
+        self.key()
+    }
+
+    fn primary_key<K: DeserializeOwned>(&self) -> Result<K, JsValue> {
+        self.primary_key()
+    }
+
+    fn value<V: DeserializeOwned>(&self) -> Result<V, JsValue> {
+        C::decode_serde(self.value_raw())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Database for MemoryDb {
+    type Error = MemoryError;
+    type Transaction<'a> = MemoryTransaction<'a, Dynamic>;
+
+    fn name(&self) -> String {
+        self.name()
+    }
+
+    fn version(&self) -> u64 {
+        self.version()
+    }
+
+    fn object_store_names(&self) -> Vec<String> {
+        self.object_store_names()
+    }
+
+    fn transaction(&self, mode: TransactionMode) -> Self::Transaction<'_> {
+        self.transaction(mode)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'t, M: WriteMode> Transaction for MemoryTransaction<'t, M> {
+    type Error = MemoryError;
+    type Store<'a>
+        = MemoryObjectStore<'a, M>
+    where
+        Self: 'a;
+
+    fn object_store(&self, name: &str) -> Result<Self::Store<'_>, MemoryError> {
+        self.object_store(name)
+    }
+
+    async fn done(self) -> Result<(), MemoryError> {
+        self.done().await
+    }
+
+    async fn abort(self) -> Result<(), MemoryError> {
+        self.abort().await
+    }
+
+    fn abort_handle(&self) -> AbortHandle {
+        self.abort_handle()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'t, M: WriteMode> Store for MemoryObjectStore<'t, M> {
+    type Error = MemoryError;
+    type Index<'a>
+        = MemoryIndex<'a>
+    where
+        Self: 'a;
+    type Cursor<'a>
+        = MemoryCursor<'a>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.name()
+    }
+
+    async fn get<V: DeserializeOwned>(
+        &self,
+        key: &impl Serialize,
+    ) -> Result<Option<V>, MemoryError> {
+        self.get(key).await
+    }
+
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, MemoryError> {
+        self.get_all(range).await
+    }
+
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, MemoryError> {
+        self.count(range).await
+    }
+
+    fn index(&self, name: &str) -> Result<Self::Index<'_>, MemoryError> {
+        self.index(name)
+    }
+
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, MemoryError> {
+        match range {
+            Some(range) => self.open_cursor_with_range(range),
+            None => self.open_cursor(),
+        }
+    }
+
+    async fn add<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), MemoryError> {
+        self.add(key, value).await
+    }
+
+    async fn put<V: Serialize + ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+    ) -> Result<(), MemoryError> {
+        self.put(key, value).await
+    }
+
+    async fn delete(&self, key: &impl Serialize) -> Result<(), MemoryError> {
+        self.delete(key).await
+    }
+
+    async fn clear(&self) -> Result<(), MemoryError> {
+        self.clear().await
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'i> Index for MemoryIndex<'i> {
+    type Error = MemoryError;
+    type Cursor<'a>
+        = MemoryCursor<'a>
+    where
+        Self: 'a;
+
+    fn name(&self) -> String {
+        self.name()
+    }
+
+    async fn get<V: DeserializeOwned>(
+        &self,
+        key: &impl Serialize,
+    ) -> Result<Option<V>, MemoryError> {
+        self.get(key).await
+    }
+
+    async fn get_all<V: DeserializeOwned>(
+        &self,
+        range: Option<&KeyRange>,
+    ) -> Result<Vec<V>, MemoryError> {
+        self.get_all(range).await
+    }
+
+    async fn count(&self, range: Option<&KeyRange>) -> Result<u32, MemoryError> {
+        self.count(range).await
+    }
+
+    fn open_cursor(&self, range: Option<&KeyRange>) -> Result<Self::Cursor<'_>, MemoryError> {
+        self.open_cursor(range)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'c> Cursor for MemoryCursor<'c> {
+    type Error = MemoryError;
+
+    async fn next(&mut self) -> Result<bool, MemoryError> {
+        self.next().await
+    }
+
+    fn key<K: DeserializeOwned>(&self) -> Result<K, MemoryError> {
+        self.key()
+    }
+
+    fn primary_key<K: DeserializeOwned>(&self) -> Result<K, MemoryError> {
+        self.primary_key()
+    }
+
+    fn value<V: DeserializeOwned>(&self) -> Result<V, MemoryError> {
+        self.value()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cursor, Database, Index, Store, Transaction};
+    #[cfg(not(target_arch = "wasm32"))]
+    use crate::memory::MemoryFactory;
+    use crate::{IndexedDb, KeyRange, TransactionMode};
+    #[cfg(not(target_arch = "wasm32"))]
+    use futures::executor::block_on;
+    use serde_json::{json, Value};
+    use std::convert::TryFrom;
+    use wasm_bindgen_test::*;
+
+    /// Storage code that only knows about the traits.
+    async fn exercise<D: Database>(db: &D) {
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("users").unwrap();
+
+        store.clear().await.unwrap();
+        store.put(&1, &json!({ "name": "b" })).await.unwrap();
+        store.add(&2, &json!({ "name": "a" })).await.unwrap();
+        store.add(&3, &json!({ "name": "c" })).await.unwrap();
+        store.delete(&3).await.unwrap();
+        drop(store);
+        transaction.done().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("users").unwrap();
+
+        let user: Value = store.get(&1).await.unwrap().unwrap();
+        assert_eq!(user["name"], "b");
+        assert_eq!(store.count(None).await.unwrap(), 2);
+
+        let users: Vec<Value> = store
+            .get_all(Some(&KeyRange::try_from(2..).unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(users, vec![json!({ "name": "a" })]);
+
+        let index = store.index("by_name").unwrap();
+        assert_eq!(
+            index
+                .count(Some(&KeyRange::only(&"a").unwrap()))
+                .await
+                .unwrap(),
+            1
+        );
+
+        let mut cursor = index.open_cursor(None).unwrap();
+        let mut keys = Vec::new();
+
+        while cursor.next().await.unwrap() {
+            let name: String = cursor.key().unwrap();
+            let id: u32 = cursor.primary_key().unwrap();
+            keys.push((name, id));
+        }
+
+        assert_eq!(keys, vec![("a".to_owned(), 2), ("b".to_owned(), 1)]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn memory_backend() {
+        block_on(async {
+            let db = MemoryFactory::new()
+                .open("storage", 1, |_, db| {
+                    let store = db.create_object_store("users").unwrap();
+                    store.create_index("by_name", "name", false).unwrap();
+                })
+                .await
+                .unwrap();
+
+            exercise(&db).await;
+        });
+    }
+
+    #[wasm_bindgen_test]
+    async fn browser_backend() {
+        let db = IndexedDb::open("storage", 1, |_, db| {
+            let store = db.create_object_store("users").unwrap();
+            store.create_index("by_name", "name", false).unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        exercise(&db).await;
+    }
+}
@@ -1,7 +1,9 @@
 use std::{
+    cell::{Cell, RefCell},
+    fmt,
     marker::PhantomData,
     pin::Pin,
-    sync::{Arc, Mutex},
+    rc::Rc,
 };
 
 use futures::{
@@ -9,13 +11,22 @@ use futures::{
     Future,
 };
 
-use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{IdbTransaction, IdbTransactionMode};
 
-use crate::{IndexedDb, ObjectStore, TransactionObjectStore};
+use crate::{
+    change_feed::{ChangeFeed, ChangeLog},
+    codec::{Codec, Json},
+    request::{EventListener, SharedWaker},
+    IndexedDb, ObjectStore, TransactionObjectStore,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
 
 /// The mode the transaction should be opened in.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransactionMode {
     /// The transaction will be opened only for reading.
     Readonly,
@@ -23,23 +34,175 @@ pub enum TransactionMode {
     ReadWrite,
 }
 
-impl Into<IdbTransactionMode> for TransactionMode {
-    fn into(self) -> IdbTransactionMode {
-        match self {
+impl From<TransactionMode> for IdbTransactionMode {
+    fn from(mode: TransactionMode) -> Self {
+        match mode {
             TransactionMode::Readonly => IdbTransactionMode::Readonly,
             TransactionMode::ReadWrite => IdbTransactionMode::Readwrite,
         }
     }
 }
 
+/// Type level marker for the mode of a transaction.
+///
+/// This trait is sealed, it's implemented for [`ReadOnly`], [`ReadWrite`],
+/// [`VersionChange`] and [`Dynamic`].
+pub trait Mode: sealed::Sealed {}
+
+/// Marker trait for transaction modes that allow writes to object stores.
+pub trait WriteMode: Mode {}
+
+/// Marker trait for transaction modes that can be requested when a
+/// transaction is started.
+pub trait StaticMode: Mode {
+    #[doc(hidden)]
+    const MODE: TransactionMode;
+}
+
+/// Transaction mode marker for read only transactions.
+#[derive(Debug)]
+pub struct ReadOnly;
+
+/// Transaction mode marker for read/write transactions.
+#[derive(Debug)]
+pub struct ReadWrite;
+
+/// Transaction mode marker for the transaction of a database upgrade.
+#[derive(Debug)]
+pub struct VersionChange;
+
+/// Transaction mode marker for transactions that had their mode chosen at
+/// runtime.
+///
+/// Writes are allowed at the type level, if the transaction was opened with
+/// [`TransactionMode::Readonly`] writes will fail with a `ReadOnlyError`.
+#[derive(Debug)]
+pub struct Dynamic;
+
+impl sealed::Sealed for ReadOnly {}
+impl sealed::Sealed for ReadWrite {}
+impl sealed::Sealed for VersionChange {}
+impl sealed::Sealed for Dynamic {}
+
+impl Mode for ReadOnly {}
+impl Mode for ReadWrite {}
+impl Mode for VersionChange {}
+impl Mode for Dynamic {}
+
+impl WriteMode for ReadWrite {}
+impl WriteMode for VersionChange {}
+impl WriteMode for Dynamic {}
+
+impl StaticMode for ReadOnly {
+    const MODE: TransactionMode = TransactionMode::Readonly;
+}
+
+impl StaticMode for ReadWrite {
+    const MODE: TransactionMode = TransactionMode::ReadWrite;
+}
+
 /// Struct representing an indexeddb transaction.
+///
+/// The mode `M` of the transaction decides which operations are available on
+/// the object stores of the transaction, e.g. writes are only possible if the
+/// mode implements [`WriteMode`].
+///
+/// Object stores of the transaction use the codec `C` to convert values,
+/// unless a different codec is requested using
+/// [`Transaction::object_store_with_codec`].
 #[derive(Debug)]
-pub struct Transaction<'a> {
+pub struct Transaction<'a, M: Mode = Dynamic, C: Codec = Json> {
     pub(crate) inner: IdbTransaction,
+    pub(crate) events: Rc<TransactionEvents>,
+    pub(crate) changes: Option<Rc<ChangeLog>>,
+    pub(crate) abort_on_drop: bool,
     pub(crate) db: PhantomData<&'a IndexedDb>,
+    pub(crate) mode: PhantomData<M>,
+    pub(crate) codec: PhantomData<C>,
 }
 
-impl<'a> Transaction<'a> {
+impl<'a, M: Mode, C: Codec> Transaction<'a, M, C> {
+    pub(crate) fn new(inner: IdbTransaction) -> Self {
+        Self::with_events(Rc::new(TransactionEvents::new(inner.clone())), inner)
+    }
+
+    fn with_events(events: Rc<TransactionEvents>, inner: IdbTransaction) -> Self {
+        Self {
+            inner,
+            events,
+            changes: None,
+            abort_on_drop: false,
+            db: PhantomData,
+            mode: PhantomData,
+            codec: PhantomData,
+        }
+    }
+
+    /// Change the codec that the object stores of this transaction use.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode, codec::SerdeWasmBindgen};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db
+    ///     .transaction(TransactionMode::ReadWrite)
+    ///     .with_codec::<SerdeWasmBindgen>();
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// store.add(&"Hello", &vec![1u64 << 60]).await.unwrap();
+    /// transaction.done().await.unwrap();
+    /// # });
+    /// ```
+    pub fn with_codec<C2: Codec>(mut self) -> Transaction<'a, M, C2> {
+        let mut transaction = Transaction::with_events(self.events.clone(), self.inner.clone());
+        transaction.changes = self.changes.clone();
+        transaction.abort_on_drop = self.abort_on_drop;
+        self.abort_on_drop = false;
+
+        transaction
+    }
+
+    /// Record the writes of the transaction for the change feed of the
+    /// database, if it has one.
+    pub(crate) fn with_feed(mut self, feed: Option<Rc<ChangeFeed>>) -> Self {
+        self.changes = feed.map(|feed| Rc::new(ChangeLog::new(feed)));
+        self
+    }
+
+    /// Abort the transaction if it is dropped before [`Transaction::done`]
+    /// resolved.
+    ///
+    /// By default dropping a transaction, e.g. when the future that runs it
+    /// is cancelled, lets the requests that were already made commit. With
+    /// this option set the writes are rolled back instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::ReadWrite).abort_on_drop();
+    /// let store = transaction.object_store("test").unwrap();
+    ///
+    /// store.put(&"Hello", &"world").await.unwrap();
+    ///
+    /// // Nothing is stored, the transaction was dropped without calling `done`.
+    /// # });
+    /// ```
+    pub fn abort_on_drop(mut self) -> Self {
+        self.abort_on_drop = true;
+        self
+    }
+
     /// Get the object store with the given name.
     ///
     /// # Arguments
@@ -59,15 +222,115 @@ impl<'a> Transaction<'a> {
     /// let store = transaction.object_store("test").unwrap();
     /// # });
     /// ```
-    pub fn object_store(&self, name: &str) -> Result<TransactionObjectStore, JsValue> {
+    pub fn object_store(&self, name: &str) -> Result<TransactionObjectStore<'_, M, C>, JsValue> {
+        self.object_store_with_codec(name)
+    }
+
+    /// Get the object store with the given name using the codec `C2` instead
+    /// of the codec of the transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the object store that should be fetched.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode, codec::Raw};
+    /// # use futures::executor::block_on;
+    /// # use wasm_bindgen::JsValue;
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let transaction = db.transaction(TransactionMode::Readonly);
+    /// let store = transaction.object_store_with_codec::<Raw>("test").unwrap();
+    ///
+    /// let value: Option<JsValue> = store.get(&"Hello").await.unwrap();
+    /// # });
+    /// ```
+    pub fn object_store_with_codec<C2: Codec>(
+        &self,
+        name: &str,
+    ) -> Result<TransactionObjectStore<'_, M, C2>, JsValue> {
         let store = self.inner.object_store(name)?;
 
         Ok(TransactionObjectStore {
-            inner: ObjectStore { inner: store },
+            inner: ObjectStore::new(store),
+            changes: self.changes.clone(),
             transaction: PhantomData,
         })
     }
 
+    /// Call `callback` when the transaction completed successfully.
+    ///
+    /// Any number of callbacks can be registered, they are subscribed using
+    /// `addEventListener` and don't replace the `on*` handlers of the
+    /// underlying transaction. Callbacks are removed when the transaction is
+    /// dropped, before that [`Transaction::done`] needs to be awaited or the
+    /// transaction needs to be kept alive otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use indexeddb::{IndexedDb, TransactionMode};
+    /// # use futures::executor::block_on;
+    /// # use std::{cell::Cell, rc::Rc};
+    /// # block_on(async {
+    /// # let db = IndexedDb::open("test", 1, |_, db| {
+    /// #   db.create_object_store("test").unwrap();
+    /// # }).await .expect("Failed to open indexed DB");
+    /// let stored = Rc::new(Cell::new(false));
+    ///
+    /// let transaction = db.transaction(TransactionMode::ReadWrite);
+    /// let on_complete = stored.clone();
+    /// transaction.on_complete(move || on_complete.set(true));
+    ///
+    /// let store = transaction.object_store("test").unwrap();
+    /// store.put(&"Hello", &"world").await.unwrap();
+    /// transaction.done().await.unwrap();
+    ///
+    /// assert!(stored.get());
+    /// # });
+    /// ```
+    pub fn on_complete(&self, mut callback: impl FnMut() + 'static) {
+        self.events.observe(&["complete"], move |_| callback());
+    }
+
+    /// Call `callback` with the error of every request of the transaction
+    /// that failed, see [`Transaction::on_complete`].
+    ///
+    /// The transaction is aborted after a request failed.
+    pub fn on_error(&self, mut callback: impl FnMut(JsValue) + 'static) {
+        self.events.observe(&["error"], move |event| {
+            let error = event
+                .target()
+                .and_then(|target| target.dyn_into::<web_sys::IdbRequest>().ok())
+                .and_then(|request| request.error().ok().flatten())
+                .map_or(JsValue::UNDEFINED, Into::into);
+
+            callback(error)
+        });
+    }
+
+    /// Call `callback` when the transaction was aborted, see
+    /// [`Transaction::on_complete`].
+    ///
+    /// The callback gets the error that caused the abort, `undefined` if the
+    /// transaction was aborted explicitly.
+    pub fn on_abort(&self, mut callback: impl FnMut(JsValue) + 'static) {
+        let transaction = self.inner.clone();
+
+        self.events.observe(&["abort"], move |_| {
+            callback(transaction.error().map_or(JsValue::UNDEFINED, Into::into))
+        });
+    }
+
+    /// Get the underlying `web_sys` transaction.
+    pub fn as_raw(&self) -> &IdbTransaction {
+        &self.inner
+    }
+
     /// Wait for the transaction to be done.
     ///
     /// # Examples
@@ -89,115 +352,141 @@ impl<'a> Transaction<'a> {
     /// transaction.done().await;
     /// # });
     /// ```
-    pub async fn done(self) -> Result<(), JsValue> {
-        let transaction = self.inner.clone();
-        let transaction = TransactionFuture::new(transaction);
+    pub async fn done(mut self) -> Result<(), JsValue> {
+        let transaction = TransactionFuture {
+            events: self.events.clone(),
+        };
+
+        let result = transaction.await;
+        self.abort_on_drop = false;
+        result?;
+
+        // The writes are committed at this point, a failed broadcast only
+        // means that the change streams miss them.
+        if let Some(changes) = &self.changes {
+            let _ = changes.publish();
+        }
 
-        transaction.await
+        Ok(())
     }
 
     /// Abort the transaction cancelling all the writes that were done using
     /// this transaction.
-    pub async fn abort(self) -> Result<(), JsValue> {
-        let transaction = self.inner.clone();
-        let transaction = TransactionFuture::new(transaction);
+    pub async fn abort(mut self) -> Result<(), JsValue> {
+        self.abort_on_drop = false;
+        self.inner.abort()?;
+
+        let transaction = TransactionFuture {
+            events: self.events.clone(),
+        };
+
+        match transaction.await {
+            // The future reports an aborted transaction as an undefined error.
+            Err(e) if e.is_undefined() => Ok(()),
+            result => result,
+        }
+    }
+}
 
-        transaction.await
+impl<'a, M: Mode, C: Codec> Drop for Transaction<'a, M, C> {
+    fn drop(&mut self) {
+        if self.abort_on_drop {
+            // Fails if the transaction already finished, there is nothing to
+            // roll back then.
+            let _ = self.inner.abort();
+        }
     }
 }
 
-/// State a transaction future can be in.
-#[derive(Clone, Copy)]
+/// State a transaction can be in.
+#[derive(Debug, Clone, Copy)]
 enum TransactionState {
-    Pending,
+    Active,
     Completed,
-    Error,
     Aborted,
 }
 
-/// A future that allows waiting for a transaction to be done or aborted.
-struct TransactionFuture {
+/// Tracks the outcome of a transaction from the moment it was created, so
+/// that waiting for the transaction works even if it finished before.
+pub(crate) struct TransactionEvents {
     inner: IdbTransaction,
-    state: Arc<Mutex<TransactionState>>,
-    on_completed: Mutex<Option<Closure<dyn FnMut()>>>,
-    on_error: Mutex<Option<Closure<dyn FnMut()>>>,
-    on_abort: Mutex<Option<Closure<dyn FnMut()>>>,
+    state: Rc<Cell<TransactionState>>,
+    waker: SharedWaker,
+    // A request error fires an `error` event first, the transaction is
+    // aborted afterwards unless the error was handled.
+    _on_done: EventListener,
+    observers: RefCell<Vec<EventListener>>,
 }
 
-impl TransactionFuture {
-    fn new(transaction: IdbTransaction) -> Self {
+impl TransactionEvents {
+    pub(crate) fn new(transaction: IdbTransaction) -> Self {
+        let state = Rc::new(Cell::new(TransactionState::Active));
+        let waker = SharedWaker::default();
+
+        let on_done = {
+            let state = state.clone();
+            let waker = waker.clone();
+
+            EventListener::new(&transaction, &["complete", "abort"], move |event| {
+                state.set(match event.type_().as_str() {
+                    "complete" => TransactionState::Completed,
+                    _ => TransactionState::Aborted,
+                });
+                waker.wake();
+            })
+        };
+
         Self {
             inner: transaction,
-            state: Arc::new(Mutex::new(TransactionState::Pending)),
-            on_completed: Mutex::new(None),
-            on_error: Mutex::new(None),
-            on_abort: Mutex::new(None),
+            state,
+            waker,
+            _on_done: on_done,
+            observers: RefCell::new(Vec::new()),
         }
     }
 
-    fn state(&self) -> TransactionState {
-        *self.state.lock().unwrap()
-    }
-
-    fn set_on_complete(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_oncomplete(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.on_completed.lock().unwrap() = closure;
+    fn observe(
+        &self,
+        events: &'static [&'static str],
+        callback: impl FnMut(web_sys::Event) + 'static,
+    ) {
+        let listener = EventListener::new(&self.inner, events, callback);
+        self.observers.borrow_mut().push(listener);
     }
+}
 
-    fn set_on_error(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_onerror(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.on_error.lock().unwrap() = closure;
+impl fmt::Debug for TransactionEvents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransactionEvents")
+            .field("state", &self.state.get())
+            .field("observers", &self.observers.borrow().len())
+            .finish()
     }
+}
 
-    fn set_on_abort(&self, closure: Option<Closure<dyn FnMut()>>) {
-        self.inner
-            .set_onabort(closure.as_ref().map(|c| c.as_ref().unchecked_ref()));
-        *self.on_abort.lock().unwrap() = closure;
-    }
+/// A future that allows waiting for a transaction to be done or aborted.
+struct TransactionFuture {
+    events: Rc<TransactionEvents>,
 }
 
 impl Future for TransactionFuture {
     type Output = Result<(), JsValue>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match self.state() {
-            TransactionState::Pending => {
-                let waker = cx.waker().to_owned();
-                let state = self.state.clone();
-
-                let on_complete = Closure::wrap(Box::new(move || {
-                    *state.lock().unwrap() = TransactionState::Completed;
-                    waker.clone().wake()
-                }) as Box<dyn FnMut()>);
-                self.set_on_complete(Some(on_complete));
-
-                let waker = cx.waker().to_owned();
-                let state = self.state.clone();
-
-                let on_error = Closure::wrap(Box::new(move || {
-                    *state.lock().unwrap() = TransactionState::Error;
-                    waker.clone().wake()
-                }) as Box<dyn FnMut()>);
-
-                self.set_on_error(Some(on_error));
-
-                let waker = cx.waker().to_owned();
-                let state = self.state.clone();
-
-                let on_abort = Closure::wrap(Box::new(move || {
-                    *state.lock().unwrap() = TransactionState::Aborted;
-                    waker.clone().wake()
-                }) as Box<dyn FnMut()>);
-
-                self.set_on_abort(Some(on_abort));
+        let events = &self.events;
 
+        match events.state.get() {
+            TransactionState::Active => {
+                events.waker.register(cx.waker());
                 Poll::Pending
             }
             TransactionState::Completed => Poll::Ready(Ok(())),
-            TransactionState::Error => Poll::Ready(Err(self.inner.error().into())),
-            TransactionState::Aborted => Poll::Ready(Err(JsValue::undefined())),
+            // The error that caused the abort, an aborted transaction is
+            // reported as an undefined error if it was aborted explicitly.
+            TransactionState::Aborted => Poll::Ready(Err(events
+                .inner
+                .error()
+                .map_or(JsValue::UNDEFINED, Into::into))),
         }
     }
 }
@@ -205,10 +494,11 @@ impl Future for TransactionFuture {
 #[cfg(test)]
 mod test {
     use crate::{IndexedDb, TransactionMode};
+    use futures::FutureExt;
+    use std::{cell::RefCell, rc::Rc};
+    use wasm_bindgen::{closure::Closure, JsCast};
     use wasm_bindgen_test::*;
 
-    wasm_bindgen_test_configure!(run_in_browser);
-
     #[wasm_bindgen_test]
     async fn await_transaction() {
         let db = IndexedDb::open("test2", 1, |_, db| {
@@ -241,4 +531,230 @@ mod test {
             .unwrap();
         assert_eq!(value, "world");
     }
+
+    #[wasm_bindgen_test]
+    async fn typed_transactions() {
+        let db = IndexedDb::open("test3", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.readwrite_transaction();
+        let store = transaction.object_store("test").unwrap();
+        let key = "Hello".to_owned();
+
+        store
+            .add(&key, &"world".to_owned())
+            .await
+            .expect("Can't write to the store");
+        transaction
+            .done()
+            .await
+            .expect("Can't await end of transaction");
+
+        let transaction = db.readonly_transaction();
+        let store = transaction.object_store("test").unwrap();
+
+        let value: String = store
+            .get(&key)
+            .await
+            .expect("Can't get string out of store")
+            .unwrap();
+        assert_eq!(value, "world");
+    }
+
+    #[wasm_bindgen_test]
+    async fn drop_pending_futures() {
+        let db = IndexedDb::open("test_drop", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+
+        // Polled once and dropped while the request is still pending, the
+        // request completing later must not call into the dropped future.
+        assert!(store.put(&"Hello", &"world").now_or_never().is_none());
+        assert!(store.get::<String>(&"Hello").now_or_never().is_none());
+
+        transaction
+            .done()
+            .await
+            .expect("Can't await end of transaction");
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("test").unwrap();
+
+        let value: Option<String> = store.get(&"Hello").await.unwrap();
+        assert_eq!(value.as_deref(), Some("world"));
+    }
+
+    #[wasm_bindgen_test]
+    async fn abort_on_drop() {
+        let db = IndexedDb::open("test_abort_on_drop", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite).abort_on_drop();
+        let store = transaction.object_store("test").unwrap();
+        store.put(&"Hello", &"world").await.unwrap();
+
+        // Cancel the future waiting for the transaction.
+        assert!(transaction.done().now_or_never().is_none());
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("test").unwrap();
+
+        let value: Option<String> = store.get(&"Hello").await.unwrap();
+        assert_eq!(value, None);
+    }
+
+    /// Let the event loop run, transactions without pending requests finish
+    /// in the meantime.
+    async fn sleep(ms: i32) {
+        crate::locks::sleep(ms).await.unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn late_done() {
+        let db = IndexedDb::open("test_late_done", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        store.put(&"Hello", &"world").await.unwrap();
+
+        // The transaction completes before `done` is called.
+        sleep(50).await;
+
+        transaction
+            .done()
+            .await
+            .expect("Can't await end of a completed transaction");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        assert!(store.add(&"Hello", &"again").await.is_err());
+
+        // The transaction is aborted before `done` is called.
+        sleep(50).await;
+
+        let error = transaction.done().await.unwrap_err();
+        assert!(!error.is_undefined());
+    }
+
+    #[wasm_bindgen_test]
+    async fn late_request_poll() {
+        let db = IndexedDb::open("test_late_request", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+
+        let put = store.put(&"Hello", &"world");
+        let mut put = Box::pin(put);
+
+        // Issue the request, then only poll it again after it succeeded.
+        assert!((&mut put).now_or_never().is_none());
+        sleep(50).await;
+
+        put.await.expect("Can't await a finished request");
+        transaction.done().await.unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn event_observers() {
+        let db = IndexedDb::open("test_observers", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let observe = |name: &'static str| {
+            let events = events.clone();
+            move || events.borrow_mut().push(name)
+        };
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+
+        // A handler installed by other code isn't replaced.
+        let on_complete = Closure::wrap(Box::new(observe("handler")) as Box<dyn FnMut()>);
+        transaction
+            .as_raw()
+            .set_oncomplete(Some(on_complete.as_ref().unchecked_ref()));
+
+        transaction.on_complete(observe("first"));
+        transaction.on_complete(observe("second"));
+
+        let store = transaction.object_store("test").unwrap();
+        store.put(&"Hello", &"world").await.unwrap();
+        transaction.done().await.unwrap();
+
+        let mut events = events.borrow().clone();
+        events.sort_unstable();
+        assert_eq!(events, vec!["first", "handler", "second"]);
+
+        let errors = Rc::new(RefCell::new(Vec::new()));
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let on_error = errors.clone();
+        transaction.on_error(move |error| on_error.borrow_mut().push(("error", error)));
+        let on_abort = errors.clone();
+        transaction.on_abort(move |error| on_abort.borrow_mut().push(("abort", error)));
+
+        let store = transaction.object_store("test").unwrap();
+        assert!(store.add(&"Hello", &"again").await.is_err());
+        assert!(transaction.done().await.is_err());
+
+        let errors = errors.borrow();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, "error");
+        assert_eq!(errors[1].0, "abort");
+        assert!(!errors[1].1.is_undefined());
+    }
+
+    #[wasm_bindgen_test]
+    async fn queued_requests() {
+        let db = IndexedDb::open("test_queued", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+
+        for i in 0..100u32 {
+            let _ = store.queue_put(&i, &i).unwrap();
+        }
+
+        let last = store.queue_delete(&99u32).unwrap();
+        last.await.unwrap();
+        transaction.done().await.unwrap();
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("test").unwrap();
+        assert_eq!(store.count(None).await.unwrap(), 99);
+
+        // The error of an ignored request aborts the transaction.
+        let _ = store.queue_add(&1u32, &1u32).unwrap();
+        let _ = store.queue_put(&100u32, &100u32).unwrap();
+        assert!(transaction.done().await.is_err());
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("test").unwrap();
+        assert_eq!(store.get::<u32>(&100u32).await.unwrap(), None);
+    }
 }
@@ -0,0 +1,427 @@
+//! Entries that expire after a time-to-live.
+//!
+//! Values are stored inside of an envelope that records when they expire.
+//! Reads through a [`TtlStore`] skip expired entries, they are deleted in the
+//! background by a [`Sweeper`] using an index on the expiry time. The index
+//! needs to be created during an upgrade using [`create_expiry_index`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use indexeddb::{IndexedDb, TransactionMode, ttl::{self, Sweeper, TtlStore}};
+//! # use futures::executor::block_on;
+//! # use std::time::Duration;
+//! # block_on(async {
+//! let db = IndexedDb::open("cache", 1, |_, db| {
+//!     let store = db.create_object_store("responses").unwrap();
+//!     ttl::create_expiry_index(&store).unwrap();
+//! }).await .expect("Failed to open indexed DB");
+//!
+//! let _sweeper = Sweeper::new(&db, "responses").spawn();
+//!
+//! let transaction = db.transaction(TransactionMode::ReadWrite);
+//! let store = transaction.object_store("responses").unwrap();
+//! let cache = TtlStore::new(&store);
+//!
+//! cache
+//!     .put_with_ttl(&"/users", &"[]", Duration::from_secs(60))
+//!     .await
+//!     .unwrap();
+//!
+//! let response: Option<String> = cache.get(&"/users").await.unwrap();
+//! # });
+//! ```
+
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    codec::{Codec, Decode, Encode, Json},
+    cursor::Cursor,
+    db::IndexedDb,
+    key_range::KeyRange,
+    locks::sleep,
+    object_store::{ObjectStoreDuringUpgrade, TransactionObjectStore},
+    request::IndexedDbRequest,
+    transaction::{Dynamic, Mode, TransactionMode, WriteMode},
+};
+
+/// The name of the index on the expiry time of the entries.
+pub const EXPIRY_INDEX: &str = "ttl_expires";
+
+/// The number of entries a sweeper deletes per transaction by default.
+pub const DEFAULT_BATCH_SIZE: u32 = 100;
+
+/// How often a spawned sweeper runs by default.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+const EXPIRES: &str = "expires";
+const VALUE: &str = "value";
+
+/// Create the index on the expiry time that the [`Sweeper`] uses.
+///
+/// Entries that don't expire aren't part of the index.
+pub fn create_expiry_index(store: &ObjectStoreDuringUpgrade<'_>) -> Result<(), JsValue> {
+    store.create_index(EXPIRY_INDEX, EXPIRES, false)
+}
+
+fn property(value: &JsValue, name: &str) -> Result<JsValue, JsValue> {
+    js_sys::Reflect::get(value, &name.into())
+}
+
+/// Is the given envelope expired at the time `now`?
+fn is_expired(envelope: &JsValue, now: f64) -> Result<bool, JsValue> {
+    Ok(property(envelope, EXPIRES)?
+        .as_f64()
+        .is_some_and(|expires| expires <= now))
+}
+
+/// Unwrap the value of an envelope, `None` if it is expired.
+fn unwrap_envelope(envelope: &JsValue, now: f64) -> Result<Option<JsValue>, JsValue> {
+    if is_expired(envelope, now)? {
+        Ok(None)
+    } else {
+        property(envelope, VALUE).map(Some)
+    }
+}
+
+/// An object store whose entries can expire.
+///
+/// All the entries of the store need to be written through a `TtlStore`,
+/// values are wrapped into an envelope holding the expiry time. Expired
+/// entries are invisible to reads, even before a [`Sweeper`] deleted them.
+#[derive(Debug)]
+pub struct TtlStore<'s, 'a, M: Mode = Dynamic, C: Codec = Json> {
+    inner: &'s TransactionObjectStore<'a, M, C>,
+}
+
+impl<'s, 'a, M: Mode, C: Codec> TtlStore<'s, 'a, M, C> {
+    /// Wrap the given object store.
+    pub fn new(inner: &'s TransactionObjectStore<'a, M, C>) -> Self {
+        Self { inner }
+    }
+
+    /// Get the value with the given key, `None` if it doesn't exist or is
+    /// expired.
+    pub async fn get<V>(&self, key: &impl Serialize) -> Result<Option<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        let envelope = match self.inner.get_raw(key).await? {
+            Some(envelope) => envelope,
+            None => return Ok(None),
+        };
+
+        unwrap_envelope(&envelope, js_sys::Date::now())?
+            .map(C::decode)
+            .transpose()
+    }
+
+    /// Get all the values that aren't expired, optionally only the ones with
+    /// keys inside of the given range.
+    pub async fn get_all<V>(&self, range: Option<&KeyRange>) -> Result<Vec<V>, JsValue>
+    where
+        C: Decode<V>,
+    {
+        let now = js_sys::Date::now();
+        let mut values = Vec::new();
+
+        for envelope in self.inner.get_all_js(range).await? {
+            if let Some(value) = unwrap_envelope(&envelope, now)? {
+                values.push(C::decode(value)?);
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Open a cursor that iterates over the entries that aren't expired,
+    /// optionally only the ones with keys inside of the given range.
+    pub fn open_cursor(&self, range: Option<&KeyRange>) -> Result<TtlCursor<'s, C>, JsValue> {
+        let cursor = match range {
+            Some(range) => self.inner.open_cursor_with_range(range)?,
+            None => self.inner.open_cursor()?,
+        };
+
+        Ok(TtlCursor {
+            inner: cursor,
+            now: js_sys::Date::now(),
+        })
+    }
+}
+
+impl<'s, 'a, M: WriteMode, C: Codec> TtlStore<'s, 'a, M, C> {
+    /// Store the given value under the given key without an expiry time,
+    /// replacing any existing value.
+    pub async fn put<V: ?Sized>(&self, key: &impl Serialize, value: &V) -> Result<(), JsValue>
+    where
+        C: Encode<V>,
+    {
+        self.put_envelope(key, value, None).await
+    }
+
+    /// Store the given value under the given key, it expires once the given
+    /// time-to-live passed. Any existing value is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the value.
+    ///
+    /// * `value` - The value that should be stored.
+    ///
+    /// * `ttl` - How long the value is valid.
+    pub async fn put_with_ttl<V: ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+        ttl: Duration,
+    ) -> Result<(), JsValue>
+    where
+        C: Encode<V>,
+    {
+        let expires = js_sys::Date::now() + ttl.as_secs_f64() * 1000.0;
+        self.put_envelope(key, value, Some(expires)).await
+    }
+
+    /// Delete the value with the given key.
+    pub async fn delete(&self, key: &impl Serialize) -> Result<(), JsValue> {
+        self.inner.delete(key).await
+    }
+
+    async fn put_envelope<V: ?Sized>(
+        &self,
+        key: &impl Serialize,
+        value: &V,
+        expires: Option<f64>,
+    ) -> Result<(), JsValue>
+    where
+        C: Encode<V>,
+    {
+        let envelope = js_sys::Object::new();
+        js_sys::Reflect::set(&envelope, &VALUE.into(), &C::encode(value)?)?;
+
+        if let Some(expires) = expires {
+            js_sys::Reflect::set(&envelope, &EXPIRES.into(), &expires.into())?;
+        }
+
+        self.inner.put_raw(key, &envelope).await
+    }
+}
+
+/// A cursor of a [`TtlStore`] that skips expired entries.
+///
+/// Entries are checked against the time the cursor was opened.
+#[derive(Debug)]
+pub struct TtlCursor<'s, C: Codec = Json> {
+    inner: Cursor<'s, C>,
+    now: f64,
+}
+
+impl<'s, C: Codec> TtlCursor<'s, C> {
+    /// Move the cursor to the next entry that isn't expired.
+    ///
+    /// Returns `false` if there are no more entries left.
+    pub async fn next(&mut self) -> Result<bool, JsValue> {
+        while self.inner.next().await? {
+            if !is_expired(&self.inner.value_raw(), self.now)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// The key of the current entry.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn key<K: DeserializeOwned>(&self) -> Result<K, JsValue> {
+        self.inner.key()
+    }
+
+    /// The value of the current entry.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the cursor doesn't point to an entry.
+    pub fn value<V>(&self) -> Result<V, JsValue>
+    where
+        C: Decode<V>,
+    {
+        C::decode(property(&self.inner.value_raw(), VALUE)?)
+    }
+}
+
+/// Deletes the expired entries of an object store.
+///
+/// The store needs the index created by [`create_expiry_index`].
+#[derive(Debug, Clone)]
+pub struct Sweeper {
+    db: IndexedDb,
+    store: String,
+    batch_size: u32,
+    interval: Duration,
+}
+
+impl Sweeper {
+    /// Create a sweeper for the object store with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database containing the object store.
+    ///
+    /// * `store` - The name of the object store whose expired entries should
+    ///   be deleted.
+    pub fn new(db: &IndexedDb, store: &str) -> Self {
+        Self {
+            db: db.clone(),
+            store: store.to_owned(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Set the number of entries that are deleted per transaction, small
+    /// batches keep the transactions short so they don't hold up others.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the given `batch_size` is 0.
+    pub fn with_batch_size(mut self, batch_size: u32) -> Self {
+        assert!(batch_size > 0, "the batch size must be >= 1");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Set how often a spawned sweeper runs.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Delete all the entries that are expired right now.
+    ///
+    /// Returns the number of deleted entries.
+    pub async fn sweep(&self) -> Result<u32, JsValue> {
+        let mut deleted = 0;
+
+        loop {
+            let range = web_sys::IdbKeyRange::upper_bound(&js_sys::Date::now().into())?;
+
+            let transaction = self.db.transaction(TransactionMode::ReadWrite);
+            let store = transaction.object_store(&self.store)?;
+            let index = store.index(EXPIRY_INDEX)?;
+
+            let keys = index
+                .as_raw()
+                .get_all_keys_with_key_and_limit(&range, self.batch_size)?;
+            let keys = js_sys::Array::from(&IndexedDbRequest::new(keys).await?);
+
+            // Failed requests abort the transaction, `done` reports them.
+            for key in keys.iter() {
+                store.as_raw().delete(&key)?;
+            }
+
+            transaction.done().await?;
+            deleted += keys.length();
+
+            if keys.length() < self.batch_size {
+                return Ok(deleted);
+            }
+        }
+    }
+
+    /// Run the sweeper in the background until the returned handle is
+    /// dropped.
+    ///
+    /// Failed runs are ignored, the next run tries again.
+    pub fn spawn(self) -> SweeperHandle {
+        let stopped = Rc::new(Cell::new(false));
+        let handle = SweeperHandle {
+            stopped: stopped.clone(),
+        };
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let interval = self.interval.as_millis().min(i32::MAX as u128) as i32;
+
+            while !stopped.get() {
+                let _ = self.sweep().await;
+
+                if sleep(interval).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        handle
+    }
+}
+
+/// Stops a spawned [`Sweeper`] when dropped.
+#[derive(Debug)]
+pub struct SweeperHandle {
+    stopped: Rc<Cell<bool>>,
+}
+
+impl Drop for SweeperHandle {
+    fn drop(&mut self) {
+        self.stopped.set(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{create_expiry_index, Sweeper, TtlStore};
+    use crate::{IndexedDb, TransactionMode};
+    use std::time::Duration;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn expiry() {
+        let db = IndexedDb::open("ttl", 1, |_, db| {
+            let store = db.create_object_store("cache").unwrap();
+            create_expiry_index(&store).unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB");
+
+        let transaction = db.transaction(TransactionMode::ReadWrite);
+        let store = transaction.object_store("cache").unwrap();
+        store.clear().await.unwrap();
+
+        let cache = TtlStore::new(&store);
+        cache.put(&"forever", &1).await.unwrap();
+        cache
+            .put_with_ttl(&"expired", &2, Duration::from_millis(0))
+            .await
+            .unwrap();
+        cache
+            .put_with_ttl(&"valid", &3, Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(cache.get::<u32>(&"forever").await.unwrap(), Some(1));
+        assert_eq!(cache.get::<u32>(&"expired").await.unwrap(), None);
+        assert_eq!(cache.get_all::<u32>(None).await.unwrap(), vec![1, 3]);
+
+        let mut cursor = cache.open_cursor(None).unwrap();
+        let mut keys = Vec::new();
+
+        while cursor.next().await.unwrap() {
+            keys.push(cursor.key::<String>().unwrap());
+        }
+
+        assert_eq!(keys, vec!["forever", "valid"]);
+        transaction.done().await.unwrap();
+
+        let sweeper = Sweeper::new(&db, "cache").with_batch_size(1);
+        assert_eq!(sweeper.sweep().await.unwrap(), 1);
+
+        let transaction = db.transaction(TransactionMode::Readonly);
+        let store = transaction.object_store("cache").unwrap();
+        assert_eq!(store.count(None).await.unwrap(), 2);
+    }
+}
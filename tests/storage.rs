@@ -0,0 +1,141 @@
+//! Storage code written against the `storage` traits runs unchanged on
+//! IndexedDB in a browser and on the in-memory emulation in native tests.
+
+#[cfg(not(target_arch = "wasm32"))]
+use indexeddb::memory::MemoryFactory;
+use indexeddb::{
+    storage::{Cursor, Database, Index, Store, Transaction},
+    IndexedDb, KeyRange, TransactionMode,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_test::*;
+
+#[cfg(not(worker_tests))]
+wasm_bindgen_test_configure!(run_in_browser);
+#[cfg(worker_tests)]
+wasm_bindgen_test_configure!(run_in_dedicated_worker);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Note {
+    id: u32,
+    tag: String,
+    text: String,
+}
+
+impl Note {
+    fn new(id: u32, tag: &str, text: &str) -> Self {
+        Self {
+            id,
+            tag: tag.to_owned(),
+            text: text.to_owned(),
+        }
+    }
+}
+
+async fn save<D: Database>(db: &D, notes: &[Note]) -> Result<(), D::Error> {
+    let transaction = db.transaction(TransactionMode::ReadWrite);
+    {
+        let store = transaction.object_store("notes")?;
+
+        for note in notes {
+            store.put(&note.id, note).await?;
+        }
+    }
+
+    transaction.done().await
+}
+
+async fn with_tag<D: Database>(db: &D, tag: &str) -> Result<Vec<Note>, D::Error> {
+    let transaction = db.transaction(TransactionMode::Readonly);
+    let store = transaction.object_store("notes")?;
+
+    let notes = store
+        .index("by_tag")?
+        .get_all(Some(&KeyRange::only(tag)?))
+        .await;
+
+    notes
+}
+
+async fn ids<D: Database>(db: &D) -> Result<Vec<u32>, D::Error> {
+    let transaction = db.transaction(TransactionMode::Readonly);
+    let store = transaction.object_store("notes")?;
+    let mut cursor = store.open_cursor(None)?;
+    let mut ids = Vec::new();
+
+    while cursor.next().await? {
+        ids.push(cursor.key()?);
+    }
+
+    Ok(ids)
+}
+
+async fn delete<D: Database>(db: &D, id: u32) -> Result<(), D::Error> {
+    let transaction = db.transaction(TransactionMode::ReadWrite);
+    transaction.object_store("notes")?.delete(&id).await?;
+    transaction.done().await
+}
+
+/// The same scenario for every backend, the database needs a `notes` store
+/// with a `by_tag` index on `tag`.
+async fn notes<D: Database>(db: &D) {
+    save(
+        db,
+        &[
+            Note::new(3, "work", "Write the report"),
+            Note::new(1, "home", "Water the plants"),
+            Note::new(2, "work", "Answer the mails"),
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(ids(db).await.unwrap(), vec![1, 2, 3]);
+    assert_eq!(
+        with_tag(db, "work").await.unwrap(),
+        vec![
+            Note::new(2, "work", "Answer the mails"),
+            Note::new(3, "work", "Write the report")
+        ]
+    );
+
+    delete(db, 2).await.unwrap();
+    assert_eq!(ids(db).await.unwrap(), vec![1, 3]);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn memory() {
+    futures::executor::block_on(async {
+        let db = MemoryFactory::new()
+            .open("notes", 1, |_, db| {
+                let store = db.create_object_store("notes").unwrap();
+                store.create_index("by_tag", "tag", false).unwrap();
+            })
+            .await
+            .expect("Failed to open the database");
+
+        notes(&db).await;
+    });
+}
+
+#[wasm_bindgen_test]
+async fn browser() {
+    let db = IndexedDb::open("notes", 1, |_, db| {
+        let store = db.create_object_store("notes").unwrap();
+        store.create_index("by_tag", "tag", false).unwrap();
+    })
+    .await
+    .expect("Failed to open indexed DB");
+
+    let transaction = db.transaction(TransactionMode::ReadWrite);
+    transaction
+        .object_store("notes")
+        .unwrap()
+        .clear()
+        .await
+        .unwrap();
+    transaction.done().await.unwrap();
+
+    notes(&db).await;
+}
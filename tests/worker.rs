@@ -0,0 +1,54 @@
+//! Tests that run inside of a dedicated worker, where no `Window` exists.
+//!
+//! The other tests run in a browser window, building them with
+//! `RUSTFLAGS="--cfg worker_tests"` runs them in a dedicated worker as well.
+
+use indexeddb::{Factory, IndexedDb, TransactionMode};
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_dedicated_worker);
+
+#[wasm_bindgen_test]
+async fn open_in_worker() {
+    let db = IndexedDb::open("worker", 1, |_, db| {
+        db.create_object_store("test").unwrap();
+    })
+    .await
+    .expect("Failed to open indexed DB in a worker");
+
+    assert_eq!(db.name(), "worker");
+    assert_eq!(db.version(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn read_write_in_worker() {
+    let factory = Factory::new().expect("No IndexedDB factory in a worker");
+    let db = factory
+        .open("worker2", 1, |_, db| {
+            db.create_object_store("test").unwrap();
+        })
+        .await
+        .expect("Failed to open indexed DB in a worker");
+
+    let transaction = db.transaction(TransactionMode::ReadWrite);
+    let store = transaction.object_store("test").unwrap();
+
+    store
+        .add(&"Hello", &"world".to_owned())
+        .await
+        .expect("Can't write to the store");
+    transaction
+        .done()
+        .await
+        .expect("Can't await end of transaction");
+
+    let transaction = db.transaction(TransactionMode::Readonly);
+    let store = transaction.object_store("test").unwrap();
+
+    let value: String = store
+        .get(&"Hello")
+        .await
+        .expect("Can't get string out of store")
+        .unwrap();
+    assert_eq!(value, "world");
+}